@@ -0,0 +1,479 @@
+use std::f64::consts::TAU;
+
+use orbits::ASTRONOMICAL_UNIT;
+
+use super::system::{
+    BodyDefinition, ElementsDefinition, Marker, OrbitDefinition, ShipDefinition, SystemDefinition,
+    TerrainDefinition, ViewDefinition,
+};
+
+const GRAVITATIONAL_CONSTANT: f64 = 6.6743e-11;
+const SUN_MASS: f64 = 1.98847e30;
+const SUN_RADIUS: f64 = 6.957e8;
+const SUN_LUMINOSITY: f64 = 3.828e26;
+const SUN_TEMPERATURE: f64 = 5772.0;
+const EARTH_MASS: f64 = 5.97217e24;
+const EARTH_RADIUS: f64 = 6.371e6;
+const JUPITER_RADIUS: f64 = 6.9911e7;
+/// Mean density of moons in kg/m³, between ice and rock
+const MOON_DENSITY: f64 = 2500.0;
+/// Planets are separated by at least this many mutual Hill radii, so the system stays stable
+/// for billions of years
+const MUTUAL_HILL_SEPARATION: f64 = 10.0;
+/// Prograde moons are only stable inside this fraction of the Hill sphere
+const STABLE_HILL_FRACTION: f64 = 0.4;
+const SYLLABLES: [&str; 16] = [
+    "ka", "lo", "ve", "ri", "sa", "tor", "nex", "mi", "du", "ra", "zen", "o", "li", "qua", "bel",
+    "un",
+];
+const NUMERALS: [&str; 8] = ["I", "II", "III", "IV", "V", "VI", "VII", "VIII"];
+
+/// SplitMix64, small and stable across platforms so a seed always builds the same system
+struct Random(u64);
+
+impl Random {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1)
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.unit()
+    }
+
+    /// Uniform in logarithmic scale, for values spanning orders of magnitude
+    fn log_range(&mut self, min: f64, max: f64) -> f64 {
+        self.range(min.ln(), max.ln()).exp()
+    }
+
+    /// Uniform between both values, inclusive
+    fn count(&mut self, min: usize, max: usize) -> usize {
+        min + (self.next_u64() % (max - min + 1) as u64) as usize
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        self.unit() < probability
+    }
+
+    fn color(&mut self, base: (f32, f32, f32), variation: f32) -> (f32, f32, f32) {
+        let mut channel = |value: f32| {
+            (value + self.range(-f64::from(variation), f64::from(variation)) as f32).clamp(0.0, 1.0)
+        };
+        (channel(base.0), channel(base.1), channel(base.2))
+    }
+}
+
+/// A planet placed by the generator, before its moons
+struct PlannedPlanet {
+    name: String,
+    mass: f64,
+    radius: f64,
+    semimajor_axis: f64,
+    eccentricity: f64,
+    gas_giant: bool,
+    habitable: bool,
+}
+
+/// Builds a plausible star system from a seed: a main sequence star, planets on stable orbits
+/// that never cross, moons inside the stable part of their planet's Hill sphere and a ship
+/// around the most habitable planet. The same seed always gives the same system.
+pub fn generate_system(seed: u64) -> SystemDefinition {
+    let mut random = Random(seed);
+    let star_name = star_name(&mut random);
+
+    // Main sequence relations, good enough from red dwarfs to F stars
+    let star_mass_ratio = random.log_range(0.5, 1.5);
+    let star_mass = star_mass_ratio * SUN_MASS;
+    let star_radius = star_mass_ratio.powf(0.8) * SUN_RADIUS;
+    let luminosity_ratio = star_mass_ratio.powi(4);
+    let temperature = SUN_TEMPERATURE * (luminosity_ratio / star_mass_ratio.powf(1.6)).powf(0.25);
+
+    let mut bodies = vec![BodyDefinition {
+        name: star_name.clone(),
+        mass: star_mass,
+        radius: star_radius,
+        parent: None,
+        orbit: None,
+        binary: None,
+        view: Some(ViewDefinition {
+            radius: star_radius as f32,
+            color: star_color(temperature),
+            terrain: None,
+        }),
+        luminosity: Some(luminosity_ratio * SUN_LUMINOSITY),
+        markers: vec![Marker::Sun, Marker::Active],
+    }];
+
+    let planets = plan_planets(&mut random, &star_name, star_mass, luminosity_ratio);
+    let home = planets
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| {
+            let distance = |planet: &PlannedPlanet| {
+                let habitable_distance = luminosity_ratio.sqrt() * ASTRONOMICAL_UNIT;
+                (planet.semimajor_axis / habitable_distance).ln().abs()
+                    + if planet.gas_giant { 10.0 } else { 0.0 }
+            };
+            distance(a).total_cmp(&distance(b))
+        })
+        .map(|(index, _)| index);
+
+    let mut ships = Vec::new();
+    for (index, planet) in planets.iter().enumerate() {
+        let is_home = Some(index) == home;
+        bodies.push(BodyDefinition {
+            name: planet.name.clone(),
+            mass: planet.mass,
+            radius: planet.radius,
+            parent: Some(star_name.clone()),
+            orbit: Some(OrbitDefinition::Elements(random_elements(
+                &mut random,
+                planet.semimajor_axis,
+                planet.eccentricity,
+                0.05,
+                star_mass,
+            ))),
+            binary: None,
+            view: Some(planet_view(&mut random, planet, is_home)),
+            luminosity: None,
+            markers: if is_home { vec![Marker::Earth] } else { vec![] },
+        });
+
+        let hill_radius = planet.semimajor_axis
+            * (1.0 - planet.eccentricity)
+            * (planet.mass / (3.0 * star_mass)).cbrt();
+        bodies.extend(moons(&mut random, planet, hill_radius));
+
+        if is_home {
+            let altitude = (planet.radius * 0.05).max(300.0e3);
+            ships.push(ShipDefinition {
+                name: "Ship".to_string(),
+                parent: planet.name.clone(),
+                orbit: OrbitDefinition::Elements(ElementsDefinition {
+                    semimajor_axis: planet.radius + altitude,
+                    eccentricity: 0.0005,
+                    argument_of_periapsis: 0.0,
+                    inclination: 0.5,
+                    longitude_of_ascending_node: 0.0,
+                    periapsis_epoch: 0.0,
+                }),
+                current: true,
+            });
+        }
+    }
+
+    SystemDefinition {
+        name: format!("{star_name} (seed {seed})"),
+        layout: None,
+        bodies,
+        ships,
+    }
+}
+
+fn star_name(random: &mut Random) -> String {
+    let syllables = random.count(2, 3);
+    let name: String = (0..syllables)
+        .map(|_| SYLLABLES[random.count(0, SYLLABLES.len() - 1)])
+        .collect();
+    let mut characters = name.chars();
+    characters
+        .next()
+        .map(|first| first.to_uppercase().chain(characters).collect())
+        .unwrap_or(name)
+}
+
+/// Approximate color of a black body, from red dwarfs to blue-white stars
+fn star_color(temperature: f64) -> (f32, f32, f32) {
+    const COLORS: [(f64, (f32, f32, f32)); 5] = [
+        (3000.0, (1.0, 0.55, 0.3)),
+        (4500.0, (1.0, 0.78, 0.55)),
+        (5800.0, (1.0, 0.95, 0.8)),
+        (7000.0, (0.95, 0.95, 1.0)),
+        (10000.0, (0.7, 0.8, 1.0)),
+    ];
+    let index = COLORS
+        .iter()
+        .position(|(limit, _)| temperature < *limit)
+        .unwrap_or(COLORS.len());
+    if index == 0 {
+        return COLORS[0].1;
+    }
+    if index == COLORS.len() {
+        return COLORS[COLORS.len() - 1].1;
+    }
+    let (low, low_color) = COLORS[index - 1];
+    let (high, high_color) = COLORS[index];
+    let t = ((temperature - low) / (high - low)) as f32;
+    (
+        low_color.0 + (high_color.0 - low_color.0) * t,
+        low_color.1 + (high_color.1 - low_color.1) * t,
+        low_color.2 + (high_color.2 - low_color.2) * t,
+    )
+}
+
+/// Places planets outwards with roughly geometric spacing, pushing each one out until it is
+/// far enough from the previous one in mutual Hill radii and their orbits can not cross
+fn plan_planets(
+    random: &mut Random,
+    star_name: &str,
+    star_mass: f64,
+    luminosity_ratio: f64,
+) -> Vec<PlannedPlanet> {
+    let frost_line = 2.7 * luminosity_ratio.sqrt() * ASTRONOMICAL_UNIT;
+    let habitable_zone = (0.95 * luminosity_ratio.sqrt() * ASTRONOMICAL_UNIT)
+        ..(1.4 * luminosity_ratio.sqrt() * ASTRONOMICAL_UNIT);
+    let count = random.count(3, 9);
+
+    let mut planets: Vec<PlannedPlanet> = Vec::with_capacity(count);
+    let mut semimajor_axis = random.range(0.2, 0.5) * luminosity_ratio.sqrt() * ASTRONOMICAL_UNIT;
+    for index in 0..count {
+        let gas_giant = semimajor_axis > frost_line && random.chance(0.7);
+        let (mass, radius) = if gas_giant {
+            let mass = random.log_range(10.0, 1000.0) * EARTH_MASS;
+            (mass, random.range(0.35, 1.1) * JUPITER_RADIUS)
+        } else {
+            let mass_ratio = random.log_range(0.05, 5.0);
+            (
+                mass_ratio * EARTH_MASS,
+                mass_ratio.powf(0.27) * EARTH_RADIUS,
+            )
+        };
+        let eccentricity = random.range(0.0, 0.08);
+
+        if let Some(previous) = planets.last() {
+            // The mutual Hill radius grows with the mean of both semimajor axes, so the
+            // separation a - a' >= N * k * (a + a') / 2 is solved for a
+            let spacing =
+                MUTUAL_HILL_SEPARATION * ((previous.mass + mass) / (3.0 * star_mass)).cbrt() / 2.0;
+            let apoapsis = previous.semimajor_axis * (1.0 + previous.eccentricity);
+            semimajor_axis = semimajor_axis
+                .max(previous.semimajor_axis * (1.0 + spacing) / (1.0 - spacing))
+                .max(1.2 * apoapsis / (1.0 - eccentricity));
+        }
+
+        planets.push(PlannedPlanet {
+            name: format!("{star_name} {}", char::from(b'b' + index as u8)),
+            mass,
+            radius,
+            semimajor_axis,
+            eccentricity,
+            gas_giant,
+            habitable: !gas_giant && habitable_zone.contains(&semimajor_axis),
+        });
+        semimajor_axis *= random.range(1.4, 2.0);
+    }
+    planets
+}
+
+/// Random orientation and phase for an orbit of the given shape
+fn random_elements(
+    random: &mut Random,
+    semimajor_axis: f64,
+    eccentricity: f64,
+    max_inclination: f64,
+    parent_mass: f64,
+) -> ElementsDefinition {
+    let period = TAU * (semimajor_axis.powi(3) / (GRAVITATIONAL_CONSTANT * parent_mass)).sqrt();
+    ElementsDefinition {
+        semimajor_axis,
+        eccentricity,
+        argument_of_periapsis: random.range(0.0, TAU),
+        inclination: random.range(0.0, max_inclination),
+        longitude_of_ascending_node: random.range(0.0, TAU),
+        periapsis_epoch: -random.range(0.0, period),
+    }
+}
+
+/// Gas giants get bands from their color, rocky planets get terrain. Only habitable planets
+/// and the home planet have oceans and vegetation.
+fn planet_view(random: &mut Random, planet: &PlannedPlanet, home: bool) -> ViewDefinition {
+    if planet.gas_giant {
+        let base = [
+            (0.8, 0.7, 0.55),
+            (0.9, 0.82, 0.6),
+            (0.6, 0.85, 0.9),
+            (0.3, 0.45, 0.9),
+        ][random.count(0, 3)];
+        return ViewDefinition {
+            radius: planet.radius as f32,
+            color: random.color(base, 0.1),
+            terrain: None,
+        };
+    }
+
+    let ground = random.color((0.55, 0.45, 0.35), 0.2);
+    let scale = |(red, green, blue): (f32, f32, f32), factor: f32| {
+        (red * factor, green * factor, blue * factor)
+    };
+    let terrain = if planet.habitable || home {
+        TerrainDefinition {
+            deep_water: random.color((0.0, 0.05, 0.5), 0.05),
+            water: random.color((0.0, 0.2, 0.9), 0.1),
+            sand: random.color((1.0, 0.9, 0.6), 0.1),
+            grass: random.color((0.1, 0.7, 0.1), 0.2),
+            mountains: scale(ground, 0.8),
+            snow: (1.0, 1.0, 1.0),
+        }
+    } else {
+        TerrainDefinition {
+            deep_water: scale(ground, 0.3),
+            water: scale(ground, 0.45),
+            sand: scale(ground, 0.7),
+            grass: ground,
+            mountains: scale(ground, 0.8),
+            snow: random.color((0.9, 0.9, 0.9), 0.1),
+        }
+    };
+    ViewDefinition {
+        radius: planet.radius as f32,
+        color: if planet.habitable || home {
+            (0.2, 0.4, 0.9)
+        } else {
+            ground
+        },
+        terrain: Some(terrain),
+    }
+}
+
+/// Moons from a few planet radii up to the stable part of the Hill sphere, each one further
+/// out than the previous one
+fn moons(random: &mut Random, planet: &PlannedPlanet, hill_radius: f64) -> Vec<BodyDefinition> {
+    let max_moons = if planet.gas_giant { 8 } else { 2 };
+    let count = random.count(0, max_moons);
+    let limit = STABLE_HILL_FRACTION * hill_radius;
+    let mut semimajor_axis = planet.radius * random.range(3.0, 6.0);
+
+    let mut moons = Vec::with_capacity(count);
+    for numeral in NUMERALS.iter().take(count) {
+        let eccentricity = random.range(0.0, 0.05);
+        if semimajor_axis * (1.0 + eccentricity) > limit {
+            break;
+        }
+        let mass_ratio = if planet.gas_giant {
+            random.log_range(1e-7, 1e-4)
+        } else {
+            random.log_range(1e-4, 1e-2)
+        };
+        let mass = mass_ratio * planet.mass;
+        let radius = (3.0 * mass / (4.0 * std::f64::consts::PI * MOON_DENSITY)).cbrt();
+        moons.push(BodyDefinition {
+            name: format!("{} {numeral}", planet.name),
+            mass,
+            radius,
+            parent: Some(planet.name.clone()),
+            orbit: Some(OrbitDefinition::Elements(random_elements(
+                random,
+                semimajor_axis,
+                eccentricity,
+                0.1,
+                planet.mass,
+            ))),
+            binary: None,
+            view: Some(ViewDefinition {
+                radius: radius as f32,
+                color: random.color((0.6, 0.58, 0.55), 0.15),
+                terrain: None,
+            }),
+            luminosity: None,
+            markers: vec![],
+        });
+        semimajor_axis *= random.range(1.5, 2.2);
+    }
+    moons
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEEDS: std::ops::Range<u64> = 0..500;
+
+    fn elements(body: &BodyDefinition) -> ElementsDefinition {
+        match body.orbit {
+            Some(OrbitDefinition::Elements(elements)) => elements,
+            _ => panic!("{} should have orbital elements", body.name),
+        }
+    }
+
+    #[test]
+    fn same_seed_same_system() {
+        for seed in SEEDS {
+            let system = generate_system(seed);
+            assert_eq!(system, generate_system(seed));
+            assert_eq!(system.validate(), Ok(()), "seed {seed}");
+        }
+        assert_ne!(generate_system(1), generate_system(2));
+    }
+
+    #[test]
+    fn planets_are_stable() {
+        for seed in SEEDS {
+            let system = generate_system(seed);
+            let star = &system.bodies[0];
+            let planets: Vec<&BodyDefinition> = system
+                .bodies
+                .iter()
+                .filter(|body| body.parent.as_ref() == Some(&star.name))
+                .collect();
+            assert!(planets.len() >= 3, "seed {seed}");
+
+            for pair in planets.windows(2) {
+                let (inner, outer) = (elements(pair[0]), elements(pair[1]));
+                let mutual_hill_radius = ((pair[0].mass + pair[1].mass) / (3.0 * star.mass)).cbrt()
+                    * (inner.semimajor_axis + outer.semimajor_axis)
+                    / 2.0;
+                let separation = outer.semimajor_axis - inner.semimajor_axis;
+                assert!(
+                    separation >= MUTUAL_HILL_SEPARATION * mutual_hill_radius * (1.0 - 1e-12),
+                    "seed {seed}: {} and {} are {} mutual Hill radii apart",
+                    pair[0].name,
+                    pair[1].name,
+                    separation / mutual_hill_radius
+                );
+                let apoapsis = inner.semimajor_axis * (1.0 + inner.eccentricity);
+                let periapsis = outer.semimajor_axis * (1.0 - outer.eccentricity);
+                assert!(apoapsis < periapsis, "seed {seed}: orbits cross");
+            }
+        }
+    }
+
+    #[test]
+    fn moons_stay_in_the_hill_sphere() {
+        for seed in SEEDS {
+            let system = generate_system(seed);
+            let star = &system.bodies[0];
+            for planet in system
+                .bodies
+                .iter()
+                .filter(|body| body.parent.as_ref() == Some(&star.name))
+            {
+                let orbit = elements(planet);
+                let hill_radius = orbit.semimajor_axis
+                    * (1.0 - orbit.eccentricity)
+                    * (planet.mass / (3.0 * star.mass)).cbrt();
+                for moon in system
+                    .bodies
+                    .iter()
+                    .filter(|body| body.parent.as_ref() == Some(&planet.name))
+                {
+                    let moon_orbit = elements(moon);
+                    let apoapsis = moon_orbit.semimajor_axis * (1.0 + moon_orbit.eccentricity);
+                    assert!(
+                        apoapsis < STABLE_HILL_FRACTION * hill_radius,
+                        "seed {seed}: {} leaves the stable part of the Hill sphere",
+                        moon.name
+                    );
+                    assert!(moon_orbit.semimajor_axis > planet.radius);
+                }
+            }
+        }
+    }
+}
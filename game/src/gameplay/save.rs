@@ -0,0 +1,897 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use nalgebra::{Quaternion, UnitQuaternion, Vector3};
+use orbits::{
+    Attitude, Body, EquinoctialElements, Frame, History, ManeuverNode, Orbit, OrbitsAround,
+    SimulationClock, StabilityAssist, StateVectors, TimeSpeed, Vessel, VesselError,
+};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    CurrentShip, SelectedSystem, Ship, SolarSystem,
+    ship::{FlightControls, spawn_ship},
+    system::{PendingSystem, SystemDefinition, spawn_loaded_system, start_loading},
+};
+
+/// Written by F5 and read by F9
+const QUICKSAVE_PATH: &str = "saves/quicksave.save.ron";
+
+/// Writes the game to save files and loads it back.
+/// F5 saves to the quicksave and F9 loads it.
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SaveGame>()
+            .add_event::<LoadGame>()
+            .add_systems(
+                Update,
+                (
+                    read_keyboard.run_if(resource_exists::<ButtonInput<KeyCode>>),
+                    load_game.before(spawn_loaded_system),
+                    restore_save
+                        .after(spawn_loaded_system)
+                        .run_if(not(resource_exists::<PendingSystem>).and(saved_system_spawned)),
+                    abandon_restore.after(spawn_loaded_system).run_if(
+                        resource_exists::<RestoreSave>
+                            .and(not(resource_exists::<PendingSystem>))
+                            .and(not(saved_system_spawned)),
+                    ),
+                ),
+            )
+            // Once the frame has been simulated
+            .add_systems(PostUpdate, save_game);
+    }
+}
+
+/// Writes the state of the game to a file
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct SaveGame(pub PathBuf);
+
+/// Replaces the game with the one saved in a file.
+/// The solar system is spawned again from its definition and then moved to the saved state.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct LoadGame(pub PathBuf);
+
+impl LoadGame {
+    /// Reads `--load <path>` from the command line arguments
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        args.windows(2)
+            .find(|pair| pair[0] == "--load")
+            .map(|pair| Self(PathBuf::from(&pair[1])))
+    }
+}
+
+/// Camera settings kept in save files. The render plugin keeps it up to date and moves the
+/// camera when a loaded game changes it. There is none without a camera, like in headless mode.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CameraState {
+    /// Flying with the free camera instead of orbiting
+    pub free: bool,
+    /// Orbiting the current ship instead of the whole map
+    pub close: bool,
+    /// Name of the planet the scene is centered on
+    pub focus: Option<String>,
+    /// Distance from the orbit camera to its center
+    pub distance: f32,
+    /// Horizontal and vertical angles of the orbit camera
+    pub angle: (f32, f32),
+    /// Position relative to the focused planet, in meters
+    pub position: (f64, f64, f64),
+    /// Quaternion as (x, y, z, w)
+    pub rotation: (f32, f32, f32, f32),
+}
+
+/// Every version of the save format, files are migrated to the latest one when they are read.
+/// A new version keeps the types of the previous one under new names, adds a variant for them
+/// and converts them in `migrate`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum VersionedSave {
+    V1(SaveFile),
+}
+
+impl VersionedSave {
+    fn migrate(self) -> SaveFile {
+        match self {
+            Self::V1(save) => save,
+        }
+    }
+}
+
+/// Everything needed to continue a game.
+/// Lengths are in meters and epochs in seconds of the `SimulationClock`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct SaveFile {
+    system: SelectedSystem,
+    clock: ClockState,
+    time_speed: f64,
+    current_ship: Option<String>,
+    /// Bodies are spawned from the system definition and then put back on their saved orbit
+    bodies: Vec<BodyState>,
+    ships: Vec<ShipState>,
+    /// Missing when saved in headless mode
+    camera: Option<CameraState>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+struct ClockState {
+    reference_julian_date: f64,
+    seconds: f64,
+    paused: bool,
+    warp_target: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct BodyState {
+    name: String,
+    orbit: OrbitState,
+}
+
+/// Frame and epoch of an `Orbit`, restored exactly so rails keep their phase
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+struct OrbitState {
+    epoch: f64,
+    frame: FrameState,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+enum FrameState {
+    Free {
+        position: (f64, f64, f64),
+        velocity: (f64, f64, f64),
+    },
+    Rails {
+        elements: ElementsState,
+        mean_longitude: f64,
+        mean_movement: f64,
+    },
+}
+
+/// Modified equinoctial elements, like `EquinoctialElements`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+struct ElementsState {
+    p: f64,
+    f: f64,
+    g: f64,
+    h: f64,
+    k: f64,
+    true_longitude: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct ShipState {
+    name: String,
+    parent: String,
+    orbit: OrbitState,
+    vessel: VesselState,
+    attitude: AttitudeState,
+    stability_assist: AssistState,
+    maneuver: Option<ManeuverState>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+struct VesselState {
+    dry_mass: f64,
+    propellant_mass: f64,
+    thrust: f64,
+    specific_impulse: f64,
+    throttle: f64,
+    thrust_direction: (f64, f64, f64),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+struct AttitudeState {
+    /// Quaternion as (w, i, j, k)
+    orientation: (f64, f64, f64, f64),
+    angular_velocity: (f64, f64, f64),
+    moment_of_inertia: (f64, f64, f64),
+    max_torque: (f64, f64, f64),
+}
+
+/// Like `StabilityAssist`, targets are saved by name
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+enum AssistState {
+    Off,
+    Stability,
+    Prograde,
+    Retrograde,
+    Normal,
+    AntiNormal,
+    RadialOut,
+    RadialIn,
+    Target(String),
+    Maneuver,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+struct ManeuverState {
+    epoch: f64,
+    delta_v: (f64, f64, f64),
+}
+
+#[derive(Debug)]
+enum SaveError {
+    /// The solar system has not been spawned yet
+    NoSystem,
+    Io(std::io::Error),
+    Serialize(ron::Error),
+    Parse(ron::error::SpannedError),
+}
+
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoSystem => write!(f, "There is no solar system to save"),
+            Self::Io(error) => write!(f, "Could not access the file: {error}"),
+            Self::Serialize(error) => write!(f, "Could not write the save: {error}"),
+            Self::Parse(error) => write!(f, "Could not parse the save: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<ron::Error> for SaveError {
+    fn from(error: ron::Error) -> Self {
+        Self::Serialize(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for SaveError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        Self::Parse(error)
+    }
+}
+
+impl SaveFile {
+    /// Reads a save of any version, migrating it to the current one
+    fn read(path: &Path) -> Result<Self, SaveError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    fn parse(text: &str) -> Result<Self, SaveError> {
+        let save: VersionedSave = ron::from_str(text)?;
+        Ok(save.migrate())
+    }
+
+    fn write(self, path: &Path) -> Result<(), SaveError> {
+        if let Some(folder) = path.parent() {
+            std::fs::create_dir_all(folder)?;
+        }
+        std::fs::write(path, self.into_ron()?)?;
+        Ok(())
+    }
+
+    fn into_ron(self) -> Result<String, SaveError> {
+        Ok(ron::ser::to_string_pretty(
+            &VersionedSave::V1(self),
+            PrettyConfig::default(),
+        )?)
+    }
+}
+
+impl OrbitState {
+    fn new(orbit: &Orbit) -> Self {
+        let frame = match *orbit.frame() {
+            Frame::Free(StateVectors { position, velocity }) => FrameState::Free {
+                position: tuple(position),
+                velocity: tuple(velocity),
+            },
+            Frame::Orbit {
+                elements,
+                mean_longitude,
+                mean_movement,
+            } => FrameState::Rails {
+                elements: ElementsState {
+                    p: elements.p,
+                    f: elements.f,
+                    g: elements.g,
+                    h: elements.h,
+                    k: elements.k,
+                    true_longitude: elements.true_longitude,
+                },
+                mean_longitude,
+                mean_movement,
+            },
+        };
+        Self {
+            epoch: orbit.epoch(),
+            frame,
+        }
+    }
+
+    fn frame(&self) -> Frame {
+        match self.frame {
+            FrameState::Free { position, velocity } => Frame::Free(StateVectors {
+                position: vector(position),
+                velocity: vector(velocity),
+            }),
+            FrameState::Rails {
+                elements,
+                mean_longitude,
+                mean_movement,
+            } => Frame::Orbit {
+                elements: EquinoctialElements {
+                    p: elements.p,
+                    f: elements.f,
+                    g: elements.g,
+                    h: elements.h,
+                    k: elements.k,
+                    true_longitude: elements.true_longitude,
+                },
+                mean_longitude,
+                mean_movement,
+            },
+        }
+    }
+}
+
+impl VesselState {
+    fn new(vessel: &Vessel) -> Self {
+        Self {
+            dry_mass: vessel.dry_mass(),
+            propellant_mass: vessel.propellant_mass(),
+            thrust: vessel.thrust(),
+            specific_impulse: vessel.specific_impulse(),
+            throttle: vessel.throttle(),
+            thrust_direction: tuple(vessel.thrust_direction()),
+        }
+    }
+
+    fn vessel(&self) -> Result<Vessel, VesselError> {
+        let mut vessel = Vessel::new(
+            self.dry_mass,
+            self.propellant_mass,
+            self.thrust,
+            self.specific_impulse,
+        )?;
+        vessel.set_throttle(self.throttle)?;
+        vessel.set_thrust_direction(vector(self.thrust_direction))?;
+        Ok(vessel)
+    }
+}
+
+impl AttitudeState {
+    fn new(attitude: &Attitude) -> Self {
+        let orientation = attitude.orientation();
+        Self {
+            orientation: (orientation.w, orientation.i, orientation.j, orientation.k),
+            angular_velocity: tuple(attitude.angular_velocity()),
+            moment_of_inertia: tuple(attitude.moment_of_inertia()),
+            max_torque: tuple(attitude.max_torque()),
+        }
+    }
+
+    fn attitude(&self) -> Result<Attitude, VesselError> {
+        let (w, i, j, k) = self.orientation;
+        Ok(
+            Attitude::new(vector(self.moment_of_inertia), vector(self.max_torque))?
+                .with_orientation(UnitQuaternion::new_normalize(Quaternion::new(w, i, j, k)))
+                .with_angular_velocity(vector(self.angular_velocity)),
+        )
+    }
+}
+
+impl AssistState {
+    /// Targets without a name are not saved, the assist falls back to damping the rotation
+    fn new(assist: StabilityAssist, names: &Query<&Name>) -> Self {
+        match assist {
+            StabilityAssist::Off => Self::Off,
+            StabilityAssist::Stability => Self::Stability,
+            StabilityAssist::Prograde => Self::Prograde,
+            StabilityAssist::Retrograde => Self::Retrograde,
+            StabilityAssist::Normal => Self::Normal,
+            StabilityAssist::AntiNormal => Self::AntiNormal,
+            StabilityAssist::RadialOut => Self::RadialOut,
+            StabilityAssist::RadialIn => Self::RadialIn,
+            StabilityAssist::Target(target) => names
+                .get(target)
+                .map_or(Self::Stability, |name| Self::Target(name.to_string())),
+            StabilityAssist::Maneuver => Self::Maneuver,
+        }
+    }
+
+    fn stability_assist(&self, entities: &HashMap<&str, Entity>) -> StabilityAssist {
+        match self {
+            Self::Off => StabilityAssist::Off,
+            Self::Stability => StabilityAssist::Stability,
+            Self::Prograde => StabilityAssist::Prograde,
+            Self::Retrograde => StabilityAssist::Retrograde,
+            Self::Normal => StabilityAssist::Normal,
+            Self::AntiNormal => StabilityAssist::AntiNormal,
+            Self::RadialOut => StabilityAssist::RadialOut,
+            Self::RadialIn => StabilityAssist::RadialIn,
+            Self::Target(name) => entities
+                .get(name.as_str())
+                .map_or(StabilityAssist::Stability, |&target| {
+                    StabilityAssist::Target(target)
+                }),
+            Self::Maneuver => StabilityAssist::Maneuver,
+        }
+    }
+}
+
+fn tuple(vector: Vector3<f64>) -> (f64, f64, f64) {
+    (vector.x, vector.y, vector.z)
+}
+
+fn vector((x, y, z): (f64, f64, f64)) -> Vector3<f64> {
+    Vector3::new(x, y, z)
+}
+
+/// F5 saves to the quicksave and F9 loads it
+fn read_keyboard(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut save: EventWriter<SaveGame>,
+    mut load: EventWriter<LoadGame>,
+) {
+    if keys.just_pressed(KeyCode::F5) {
+        save.write(SaveGame(PathBuf::from(QUICKSAVE_PATH)));
+    }
+    if keys.just_pressed(KeyCode::F9) {
+        load.write(LoadGame(PathBuf::from(QUICKSAVE_PATH)));
+    }
+}
+
+type SavedShip = (
+    &'static Name,
+    &'static Orbit,
+    &'static OrbitsAround,
+    &'static Vessel,
+    &'static Attitude,
+    &'static StabilityAssist,
+    Option<&'static ManeuverNode>,
+    Has<CurrentShip>,
+);
+
+/// The parts of the world that are written to save files
+#[derive(SystemParam)]
+struct GameState<'w, 's> {
+    selected: Res<'w, SelectedSystem>,
+    system: Option<Res<'w, SolarSystem>>,
+    clock: Res<'w, SimulationClock>,
+    time_speed: Res<'w, TimeSpeed>,
+    orbits: Query<'w, 's, &'static Orbit>,
+    ships: Query<'w, 's, SavedShip, With<Ship>>,
+    names: Query<'w, 's, &'static Name>,
+    camera: Option<Res<'w, CameraState>>,
+}
+
+impl GameState<'_, '_> {
+    fn save(&self) -> Result<SaveFile, SaveError> {
+        let system = self.system.as_deref().ok_or(SaveError::NoSystem)?;
+        let bodies = system
+            .bodies()
+            .filter_map(|(name, entity)| {
+                Some(BodyState {
+                    name: name.to_string(),
+                    orbit: OrbitState::new(self.orbits.get(entity).ok()?),
+                })
+            })
+            .collect();
+
+        let mut current_ship = None;
+        let mut ships = Vec::new();
+        for (name, orbit, orbits_around, vessel, attitude, assist, maneuver, current) in
+            self.ships.iter()
+        {
+            let Ok(parent) = self.names.get(orbits_around.0) else {
+                warn!("The parent of {name} has no name, the ship is not saved");
+                continue;
+            };
+            if current {
+                current_ship = Some(name.to_string());
+            }
+            ships.push(ShipState {
+                name: name.to_string(),
+                parent: parent.to_string(),
+                orbit: OrbitState::new(orbit),
+                vessel: VesselState::new(vessel),
+                attitude: AttitudeState::new(attitude),
+                stability_assist: AssistState::new(*assist, &self.names),
+                maneuver: maneuver.map(|maneuver| ManeuverState {
+                    epoch: maneuver.epoch,
+                    delta_v: tuple(maneuver.delta_v),
+                }),
+            });
+        }
+        ships.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(SaveFile {
+            system: self.selected.clone(),
+            clock: ClockState {
+                reference_julian_date: self.clock.reference_julian_date(),
+                seconds: self.clock.seconds(),
+                paused: self.clock.is_paused(),
+                warp_target: self.clock.warp_target(),
+            },
+            time_speed: self.time_speed.0,
+            current_ship,
+            bodies,
+            ships,
+            camera: self.camera.as_deref().cloned(),
+        })
+    }
+}
+
+fn save_game(mut requests: EventReader<SaveGame>, state: GameState) {
+    for SaveGame(path) in requests.read() {
+        match state.save().and_then(|save| save.write(path)) {
+            Ok(()) => info!("Saved the game to {}", path.display()),
+            Err(error) => error!("Could not save the game to {}: {error}", path.display()),
+        }
+    }
+}
+
+/// Save waiting for its solar system to be spawned
+#[derive(Resource, Debug)]
+struct RestoreSave {
+    save: SaveFile,
+    /// Definition of the saved system
+    handle: Handle<SystemDefinition>,
+}
+
+/// The solar system of the save being restored is the one in the world
+fn saved_system_spawned(save: Option<Res<RestoreSave>>, system: Option<Res<SolarSystem>>) -> bool {
+    matches!((save, system), (Some(save), Some(system)) if *system.handle() == save.handle)
+}
+
+/// Starts spawning the saved solar system, it replaces the current one once it has spawned.
+/// Files that can not be read and systems that can not be spawned leave the game as it was.
+fn load_game(
+    mut commands: Commands,
+    mut requests: EventReader<LoadGame>,
+    asset_server: Res<AssetServer>,
+    mut definitions: ResMut<Assets<SystemDefinition>>,
+) {
+    let Some(LoadGame(path)) = requests.read().last() else {
+        return;
+    };
+    let save = match SaveFile::read(path) {
+        Ok(save) => save,
+        Err(error) => {
+            error!("Could not load the game from {}: {error}", path.display());
+            return;
+        }
+    };
+
+    info!("Loading the game saved in {}", path.display());
+    let Some(handle) = start_loading(&mut commands, &asset_server, &mut definitions, &save.system)
+    else {
+        error!("Could not load the game from {}", path.display());
+        return;
+    };
+    commands.insert_resource(RestoreSave { save, handle });
+}
+
+/// The saved solar system failed to load or to spawn, the current game goes on. When the game
+/// was loaded before any system spawned, like with `--load`, the selected system is loaded.
+fn abandon_restore(
+    mut commands: Commands,
+    restore: Res<RestoreSave>,
+    system: Option<Res<SolarSystem>>,
+    selected: Res<SelectedSystem>,
+    asset_server: Res<AssetServer>,
+    mut definitions: ResMut<Assets<SystemDefinition>>,
+) {
+    error!(
+        "Could not restore the game, the {:?} system did not spawn",
+        restore.save.system
+    );
+    commands.remove_resource::<RestoreSave>();
+    if system.is_none() {
+        start_loading(&mut commands, &asset_server, &mut definitions, &selected);
+    }
+}
+
+/// Moves the freshly spawned solar system to the saved state. Ships of the definition that
+/// were not saved are despawned, the saved ones that are not in the definition are spawned.
+fn restore_save(
+    mut commands: Commands,
+    restore: Res<RestoreSave>,
+    mut system: ResMut<SolarSystem>,
+    bodies: Query<&Body>,
+    mut orbits: Query<&mut Orbit>,
+    ships: Query<(Entity, &Name, Has<CurrentShip>), With<Ship>>,
+    camera: Option<ResMut<CameraState>>,
+) {
+    commands.remove_resource::<RestoreSave>();
+    let save = &restore.save;
+    commands.insert_resource(save.system.clone());
+
+    let mut clock =
+        SimulationClock::new(save.clock.reference_julian_date).with_seconds(save.clock.seconds);
+    if let Some(target) = save.clock.warp_target {
+        clock.warp_to(target);
+    }
+    if save.clock.paused {
+        clock.pause();
+    }
+    commands.insert_resource(clock);
+    commands.insert_resource(TimeSpeed(save.time_speed));
+    // Snapshots taken before loading belong to another game
+    commands.insert_resource(History::default());
+    commands.insert_resource(FlightControls::default());
+
+    for body in &save.bodies {
+        let Some(mut orbit) = system
+            .body(&body.name)
+            .and_then(|entity| orbits.get_mut(entity).ok())
+        else {
+            warn!("{} is not in the solar system anymore", body.name);
+            continue;
+        };
+        if let Err(error) = orbit.restore(body.orbit.frame(), body.orbit.epoch) {
+            warn!("Could not restore the orbit of {}: {error}", body.name);
+        }
+    }
+
+    let saved: HashSet<&str> = save.ships.iter().map(|ship| ship.name.as_str()).collect();
+    for (entity, name, current) in ships.iter() {
+        if !saved.contains(name.as_str()) {
+            commands.entity(entity).despawn();
+            system.forget_ship(name);
+        } else if current {
+            commands.entity(entity).remove::<CurrentShip>();
+        }
+    }
+
+    let mut entities: HashMap<&str, Entity> = system.bodies().collect();
+    let mut restored = Vec::new();
+    for ship in &save.ships {
+        match restore_ship(&mut commands, &system, &bodies, ship) {
+            Ok(entity) => {
+                entities.insert(&ship.name, entity);
+                restored.push((entity, ship));
+            }
+            Err(error) => warn!("Could not restore the ship {}: {error}", ship.name),
+        }
+    }
+    // Targets can be other ships, so they are resolved once every ship exists
+    for (entity, ship) in restored {
+        let mut entity_commands = commands.entity(entity);
+        entity_commands.insert(ship.stability_assist.stability_assist(&entities));
+        if save.current_ship.as_ref() == Some(&ship.name) {
+            entity_commands.insert(CurrentShip);
+        }
+    }
+
+    if let (Some(mut camera), Some(state)) = (camera, &save.camera) {
+        *camera = state.clone();
+    }
+    info!("Loaded the game in {}", system.name());
+}
+
+fn restore_ship(
+    commands: &mut Commands,
+    system: &SolarSystem,
+    bodies: &Query<&Body>,
+    ship: &ShipState,
+) -> Result<Entity> {
+    let parent = system.body(&ship.parent).ok_or("Unknown parent")?;
+    let orbit = Orbit::from_frame(ship.orbit.frame(), ship.orbit.epoch, bodies.get(parent)?)?;
+    let vessel = ship.vessel.vessel()?;
+    let attitude = ship.attitude.attitude()?;
+
+    let entity = match system.ship(&ship.name) {
+        Some(entity) => {
+            commands
+                .entity(entity)
+                .insert((orbit, OrbitsAround(parent)));
+            entity
+        }
+        None => spawn_ship(commands, &ship.name, orbit, parent)?,
+    };
+    let mut entity_commands = commands.entity(entity);
+    entity_commands.insert((vessel, attitude));
+    match ship.maneuver {
+        Some(maneuver) => entity_commands.insert(ManeuverNode {
+            epoch: maneuver.epoch,
+            delta_v: vector(maneuver.delta_v),
+        }),
+        None => entity_commands.remove::<ManeuverNode>(),
+    };
+    Ok(entity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERSION_1: &str = include_str!("fixtures/v1.save.ron");
+
+    fn rails_orbit() -> Orbit {
+        let earth = Body::new(5.972e24);
+        let clock = SimulationClock::new(2451545.0).with_seconds(1234.5678);
+        Orbit::new_orbit(7.0e6, 0.1, 0.3, 0.5, 1.2, &earth, &clock, 100.0).unwrap()
+    }
+
+    fn free_orbit() -> Orbit {
+        let earth = Body::new(5.972e24);
+        Orbit::new_free(
+            Vector3::new(6.9e6, 1.0e5, -2.0e4),
+            Vector3::new(-10.0, 7600.0, 300.0),
+            &earth,
+        )
+        .unwrap()
+    }
+
+    fn vessel() -> Vessel {
+        let mut vessel = Vessel::new(1000.0, 1234.5, 20000.0, 320.0).unwrap();
+        vessel.set_throttle(0.3).unwrap();
+        vessel
+            .set_thrust_direction(Vector3::new(0.0, 0.6, 0.8))
+            .unwrap();
+        vessel
+    }
+
+    fn attitude() -> Attitude {
+        Attitude::new(
+            Vector3::new(125000.0, 125000.0, 50000.0),
+            Vector3::new(5000.0, 5000.0, 5000.0),
+        )
+        .unwrap()
+        .with_orientation(UnitQuaternion::from_euler_angles(0.1, 0.2, 0.3))
+        .with_angular_velocity(Vector3::new(0.01, -0.02, 0.03))
+    }
+
+    fn save() -> SaveFile {
+        SaveFile {
+            system: SelectedSystem::Generated(42),
+            clock: ClockState {
+                reference_julian_date: 2451545.0,
+                seconds: 1234.5678,
+                paused: true,
+                warp_target: Some(5000.0),
+            },
+            time_speed: 100.0,
+            current_ship: Some("Ship".to_string()),
+            bodies: vec![BodyState {
+                name: "Moon".to_string(),
+                orbit: OrbitState::new(&rails_orbit()),
+            }],
+            ships: vec![ShipState {
+                name: "Ship".to_string(),
+                parent: "Earth".to_string(),
+                orbit: OrbitState::new(&free_orbit()),
+                vessel: VesselState::new(&vessel()),
+                attitude: AttitudeState::new(&attitude()),
+                stability_assist: AssistState::Target("Moon".to_string()),
+                maneuver: Some(ManeuverState {
+                    epoch: 2000.0,
+                    delta_v: (1.0, -2.0, 3.0),
+                }),
+            }],
+            camera: None,
+        }
+    }
+
+    #[test]
+    fn saves_round_trip() {
+        let text = save().into_ron().unwrap();
+        let versioned: VersionedSave = ron::from_str(&text).unwrap();
+        assert!(matches!(versioned, VersionedSave::V1(_)));
+        assert_eq!(versioned.migrate(), save());
+        assert_eq!(SaveFile::parse(&text).unwrap(), save());
+    }
+
+    #[test]
+    fn orbits_round_trip_exactly() {
+        for orbit in [rails_orbit(), free_orbit()] {
+            let text = ron::to_string(&OrbitState::new(&orbit)).unwrap();
+            let state: OrbitState = ron::from_str(&text).unwrap();
+            assert_eq!(state.epoch.to_bits(), orbit.epoch().to_bits());
+            assert_eq!(state.frame(), *orbit.frame());
+        }
+        assert!(matches!(rails_orbit().frame(), Frame::Orbit { .. }));
+        assert_ne!(rails_orbit().epoch(), 0.0);
+    }
+
+    #[test]
+    fn vessels_round_trip() {
+        let text = ron::to_string(&VesselState::new(&vessel())).unwrap();
+        let state: VesselState = ron::from_str(&text).unwrap();
+        assert_eq!(state.vessel().unwrap(), vessel());
+
+        let text = ron::to_string(&AttitudeState::new(&attitude())).unwrap();
+        let state: AttitudeState = ron::from_str(&text).unwrap();
+        assert_eq!(state.attitude().unwrap(), attitude());
+    }
+
+    #[test]
+    fn reads_version_1() {
+        let versioned: VersionedSave = ron::from_str(VERSION_1).unwrap();
+        assert!(matches!(versioned, VersionedSave::V1(_)));
+
+        let save = SaveFile::parse(VERSION_1).unwrap();
+        assert_eq!(save.system, SelectedSystem::new("fictional"));
+        assert_eq!(save.current_ship.as_deref(), Some("Ship"));
+        assert_eq!(save.bodies.len(), 9);
+        assert_eq!(save.ships.len(), 2);
+        for body in &save.bodies {
+            assert!(matches!(body.orbit.frame(), Frame::Orbit { .. }));
+        }
+
+        let ship = &save.ships[0];
+        assert!(matches!(ship.orbit.frame(), Frame::Free(_)));
+        assert_eq!(ship.stability_assist, AssistState::Stability);
+        assert_eq!(ship.vessel.vessel().unwrap().propellant_mass(), 2000.0);
+        assert!(ship.attitude.attitude().is_ok());
+        assert!(ship.maneuver.is_some());
+        assert_eq!(
+            save.camera.and_then(|camera| camera.focus).as_deref(),
+            Some("Earth")
+        );
+    }
+
+    #[test]
+    fn failed_load_keeps_the_game() {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin::default(),
+            orbits::OrbitPlugin,
+            super::super::GamePlayPlugin,
+        ))
+        .insert_resource(SelectedSystem::Generated(3));
+        let mut save = None;
+        for _ in 0..100 {
+            app.update();
+            if app.world().contains_resource::<SolarSystem>() {
+                save = Some(
+                    app.world_mut()
+                        .run_system_cached(|state: GameState| state.save()),
+                );
+                break;
+            }
+        }
+        let mut save = save.unwrap().unwrap().unwrap();
+        let bodies: Vec<Entity> = app
+            .world()
+            .resource::<SolarSystem>()
+            .bodies()
+            .map(|(_, entity)| entity)
+            .collect();
+
+        save.system = SelectedSystem::new("systems/missing.system.ron");
+        let path = std::env::temp_dir().join("failed_load_keeps_the_game.save.ron");
+        save.write(&path).unwrap();
+        app.world_mut().send_event(LoadGame(path));
+        for _ in 0..100 {
+            app.update();
+            if !app.world().contains_resource::<RestoreSave>() {
+                break;
+            }
+        }
+
+        assert!(!app.world().contains_resource::<RestoreSave>());
+        assert!(!app.world().contains_resource::<PendingSystem>());
+        let world = app.world();
+        assert_eq!(
+            *world.resource::<SelectedSystem>(),
+            SelectedSystem::Generated(3)
+        );
+        assert!(world.resource::<SolarSystem>().name().contains("seed 3"));
+        assert!(
+            bodies
+                .iter()
+                .all(|&entity| world.get_entity(entity).is_ok())
+        );
+        assert!(
+            world
+                .iter_entities()
+                .any(|entity| entity.contains::<Ship>())
+        );
+    }
+}
@@ -1,68 +1,379 @@
-use bevy::{input::common_conditions::input_just_pressed, prelude::*};
-use orbits::Orbit;
+use bevy::prelude::*;
+use nalgebra::Vector3;
+use orbits::{
+    Attitude, AutoRails, DeltaTime, Orbit, OrbitsAround, RadiationPressure, Rewind,
+    StabilityAssist, Vessel,
+};
 
-use crate::render::{CameraCenter, CameraUp, MainCamera, OrbitDistance};
+/// Fraction of the throttle opened or closed per real second while a key is held
+const THROTTLE_RATE: f64 = 0.5;
+/// Gamepad sticks closer than this to the center are ignored
+const STICK_DEAD_ZONE: f32 = 0.1;
 
+/// Simulation side of the ships: turns the pilot input into throttle, torque and translation
+/// and picks which ship receives it
 pub struct ShipPlugin;
 
 impl Plugin for ShipPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_state(CameraMode::Map).add_systems(
-            Update,
-            (
-                update_ship_camera_orbit.run_if(in_state(CameraMode::Close)),
-                togle_camera_mode.run_if(input_just_pressed(KeyCode::KeyM)),
-            )
-                .chain(),
-        );
+        app.init_resource::<FlightControls>()
+            .add_event::<SwitchShip>()
+            .add_systems(
+                Update,
+                (
+                    reset_controls_on_rewind,
+                    read_keyboard.run_if(resource_exists::<ButtonInput<KeyCode>>),
+                    read_gamepads,
+                    switch_ships,
+                    apply_flight_controls,
+                )
+                    .chain(),
+            );
     }
 }
 
+/// A vessel simulated in the world, its view is attached by the render plugin.
+/// Ships are told apart by their `Name`.
+#[derive(Component)]
+#[require(Name = Name::new("Ship"))]
+pub struct Ship;
+
+/// The ship controlled by the player, only one ship has it at a time
 #[derive(Component)]
 pub struct CurrentShip;
 
-fn update_ship_camera_orbit(
-    query: Query<&Orbit, With<CurrentShip>>,
-    mut camera_center: ResMut<CameraCenter>,
-    mut camera_up: ResMut<CameraUp>,
+/// Makes another ship the `CurrentShip`. Ships are cycled in the order of their names.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwitchShip {
+    Next,
+    Previous,
+    To(Entity),
+}
+
+/// Reaction control thrusters, they translate the ship without turning it.
+/// Their propellant is not modeled.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Rcs {
+    /// Force along each body axis in N
+    pub thrust: f64,
+}
+
+/// Pilot input for the `CurrentShip`.
+/// Without a keyboard, like in headless mode, it can be written directly to fly the ship.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq)]
+pub struct FlightControls {
+    /// From 0 to 1
+    pub throttle: f64,
+    /// Pitch, yaw and roll, from -1 to 1
+    pub rotation: Vector3<f64>,
+    /// Right, up and forward in body axes, from -1 to 1
+    pub translation: Vector3<f64>,
+}
+
+/// Shift and Control open and close the throttle, Z and X set it to full or cut it.
+/// Arrows pitch and yaw, comma and period roll.
+/// H and N translate forward and back, J and L left and right, U and K up and down.
+/// Brackets switch to the previous and next ship.
+fn read_keyboard(
+    keys: Res<ButtonInput<KeyCode>>,
+    time: Res<Time<Real>>,
+    mut controls: ResMut<FlightControls>,
+    mut switch: EventWriter<SwitchShip>,
+) {
+    let axis = |positive: KeyCode, negative: KeyCode| {
+        f64::from(i8::from(keys.pressed(positive)) - i8::from(keys.pressed(negative)))
+    };
+
+    let throttle_change = axis(KeyCode::ShiftLeft, KeyCode::ControlLeft);
+    controls.throttle += throttle_change * THROTTLE_RATE * time.delta_secs_f64();
+    if keys.just_pressed(KeyCode::KeyZ) {
+        controls.throttle = 1.0;
+    }
+    if keys.just_pressed(KeyCode::KeyX) {
+        controls.throttle = 0.0;
+    }
+    controls.throttle = controls.throttle.clamp(0.0, 1.0);
+
+    controls.rotation = Vector3::new(
+        axis(KeyCode::ArrowDown, KeyCode::ArrowUp),
+        axis(KeyCode::ArrowLeft, KeyCode::ArrowRight),
+        axis(KeyCode::Period, KeyCode::Comma),
+    );
+    controls.translation = Vector3::new(
+        axis(KeyCode::KeyL, KeyCode::KeyJ),
+        axis(KeyCode::KeyU, KeyCode::KeyK),
+        axis(KeyCode::KeyH, KeyCode::KeyN),
+    );
+
+    if keys.just_pressed(KeyCode::BracketRight) {
+        switch.write(SwitchShip::Next);
+    }
+    if keys.just_pressed(KeyCode::BracketLeft) {
+        switch.write(SwitchShip::Previous);
+    }
+}
+
+/// Left stick pitches and yaws, right stick rolls and translates up and down, the triggers
+/// open and close the throttle and the pad translates left, right, forward and back.
+/// Adds to the keyboard input.
+fn read_gamepads(
+    gamepads: Query<&Gamepad>,
+    time: Res<Time<Real>>,
+    mut controls: ResMut<FlightControls>,
 ) {
-    let ship_transform = query
-        .single()
-        .expect("Could not get single ship")
-        .position();
-    camera_center.0.x = ship_transform.0 as f32;
-    camera_center.0.y = ship_transform.1 as f32;
-    camera_center.0.z = ship_transform.2 as f32;
-    camera_up.0 = camera_center.0.normalize();
+    let dead_zone = |value: f32| {
+        if value.abs() < STICK_DEAD_ZONE {
+            0.0
+        } else {
+            f64::from(value)
+        }
+    };
+
+    for gamepad in gamepads.iter() {
+        let trigger = |button| f64::from(gamepad.get(button).unwrap_or(0.0));
+        let throttle_change =
+            trigger(GamepadButton::RightTrigger2) - trigger(GamepadButton::LeftTrigger2);
+        controls.throttle = (controls.throttle
+            + throttle_change * THROTTLE_RATE * time.delta_secs_f64())
+        .clamp(0.0, 1.0);
+
+        let pad = |positive, negative| {
+            f64::from(i8::from(gamepad.pressed(positive)) - i8::from(gamepad.pressed(negative)))
+        };
+        let left = gamepad.left_stick();
+        let right = gamepad.right_stick();
+        controls.rotation +=
+            Vector3::new(dead_zone(left.y), -dead_zone(left.x), dead_zone(right.x));
+        controls.translation += Vector3::new(
+            pad(GamepadButton::DPadRight, GamepadButton::DPadLeft),
+            dead_zone(right.y),
+            pad(GamepadButton::DPadUp, GamepadButton::DPadDown),
+        );
+    }
+}
+
+type SwitchableShip = (
+    Entity,
+    &'static Name,
+    Option<&'static mut Vessel>,
+    Option<&'static mut Attitude>,
+);
+
+/// Moves `CurrentShip` to the requested ship. The ship left behind cuts its engine and lets go
+/// of the controls, so it coasts and goes back on rails.
+fn switch_ships(
+    mut commands: Commands,
+    mut events: EventReader<SwitchShip>,
+    mut ships: Query<SwitchableShip, With<Ship>>,
+    current: Query<Entity, With<CurrentShip>>,
+    mut controls: ResMut<FlightControls>,
+) {
+    let Some(event) = events.read().last().copied() else {
+        return;
+    };
+    let current = current.single().ok();
+
+    let mut order: Vec<(Entity, String)> = ships
+        .iter()
+        .map(|(entity, name, _, _)| (entity, name.as_str().to_string()))
+        .collect();
+    if order.is_empty() {
+        return;
+    }
+    order.sort_by(|(a, a_name), (b, b_name)| a_name.cmp(b_name).then(a.cmp(b)));
+    let index = current.and_then(|current| order.iter().position(|(ship, _)| *ship == current));
+    let target = match (event, index) {
+        (SwitchShip::To(target), _) => target,
+        (SwitchShip::Next, Some(index)) => order[(index + 1) % order.len()].0,
+        (SwitchShip::Previous, Some(index)) => order[(index + order.len() - 1) % order.len()].0,
+        (_, None) => order[0].0,
+    };
+    if Some(target) == current {
+        return;
+    }
+    if !ships.contains(target) {
+        warn!("Can not switch to {target}, it is not a ship");
+        return;
+    }
+
+    if let Some(current) = current {
+        if let Ok((_, _, vessel, attitude)) = ships.get_mut(current) {
+            if let Some(mut vessel) = vessel {
+                vessel.set_throttle(0.0).expect("Zero is a valid throttle");
+            }
+            if let Some(mut attitude) = attitude {
+                attitude.set_control(0.0, 0.0, 0.0);
+            }
+        }
+        commands.entity(current).remove::<CurrentShip>();
+    }
+    commands.entity(target).insert(CurrentShip);
+    *controls = FlightControls::default();
+    if let Ok((_, name, _, _)) = ships.get(target) {
+        info!("Switched to {name}");
+    }
 }
 
-#[derive(States, Debug, Hash, Eq, PartialEq, Clone)]
-enum CameraMode {
-    Map,
-    Close,
+/// The rewind restores the throttle of the ship, the pilot input continues from it
+fn reset_controls_on_rewind(
+    mut rewinds: EventReader<Rewind>,
+    mut controls: ResMut<FlightControls>,
+    ship: Query<&Vessel, With<CurrentShip>>,
+) {
+    if rewinds.read().last().is_none() {
+        return;
+    }
+    *controls = FlightControls {
+        throttle: ship.single().map_or(0.0, Vessel::throttle),
+        ..default()
+    };
 }
 
-fn togle_camera_mode(
-    state: Res<State<CameraMode>>,
-    mut next_state: ResMut<NextState<CameraMode>>,
-    mut orbit_distance: Query<&mut OrbitDistance, With<MainCamera>>,
-    mut camera_center: ResMut<CameraCenter>,
-    mut camera_up: ResMut<CameraUp>,
+type PilotedShip = (
+    &'static mut Orbit,
+    Option<&'static mut Vessel>,
+    Option<&'static mut Attitude>,
+    Option<&'static Rcs>,
+    Option<&'static mut AutoRails>,
+);
+
+/// Feeds the pilot input to the `CurrentShip`. `OrbitPlugin` takes it off rails while the
+/// engine or the RCS are firing.
+fn apply_flight_controls(
+    controls: Res<FlightControls>,
+    delta_time: Res<DeltaTime>,
+    mut ship: Query<PilotedShip, With<CurrentShip>>,
 ) {
-    match state.get() {
-        CameraMode::Map => {
-            // TODO: Make this dependant on planet size and also cache the old positions
-            orbit_distance.single_mut().unwrap().0 = 50.;
+    let Ok((mut orbit, vessel, attitude, rcs, auto_rails)) = ship.single_mut() else {
+        return;
+    };
+
+    let mut mass = None;
+    if let Some(mut vessel) = vessel {
+        if let Err(error) = vessel.set_throttle(controls.throttle.clamp(0.0, 1.0)) {
+            error!("Could not set the throttle: {error}");
+        }
+        mass = Some(vessel.mass());
+    }
+
+    let mut orientation = None;
+    if let Some(mut attitude) = attitude {
+        attitude.set_control(
+            controls.rotation.x,
+            controls.rotation.y,
+            controls.rotation.z,
+        );
+        orientation = Some(attitude.orientation());
+    }
+
+    let translation = controls.translation.map(|input| input.clamp(-1.0, 1.0));
+    let translating = translation != Vector3::zeros();
+    if let (true, Some(rcs), Some(mass), Some(orientation)) = (translating, rcs, mass, orientation)
+    {
+        let delta_v = orientation * translation * rcs.thrust / mass * delta_time.seconds();
+        if let Err(error) = orbit.add_velocity(delta_v) {
+            error!("Could not translate the ship: {error}");
+        }
+        if let Some(mut auto_rails) = auto_rails {
+            auto_rails.fire_thrusters();
+        }
+    }
+}
+
+/// Spawns a ship with the engine, RCS and inertia of a solid 10x10x20 m block of 3000 kg
+pub fn spawn_ship(
+    commands: &mut Commands,
+    name: &str,
+    orbit: Orbit,
+    parent: Entity,
+) -> Result<Entity> {
+    Ok(commands
+        .spawn((
+            Name::new(name.to_string()),
+            Ship,
+            RadiationPressure::new(10.0, 0.3, 3000.0),
+            Vessel::new(1000.0, 2000.0, 20.0e3, 320.0)?,
+            Attitude::new(Vector3::new(1.25e5, 1.25e5, 5.0e4), Vector3::repeat(5.0e3))?,
+            StabilityAssist::Stability,
+            Rcs { thrust: 2.0e3 },
+            orbit,
+            OrbitsAround(parent),
+        ))
+        .id())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+    use orbits::{Body, Frame, OrbitPlugin, OrbitSet, RailsPolicy, SimulationClock, TimeSpeed};
+
+    use super::*;
+
+    /// Frames in which the current ship was propagated on rails
+    #[derive(Resource, Default)]
+    struct FramesOnRails(u32);
+
+    fn count_frames_on_rails(
+        mut frames: ResMut<FramesOnRails>,
+        ship: Query<&Orbit, With<CurrentShip>>,
+    ) {
+        if let Ok(orbit) = ship.single()
+            && matches!(orbit.frame(), Frame::Orbit { .. })
+        {
+            frames.0 += 1;
+        }
+    }
+
+    #[test]
+    fn free_while_translating() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, OrbitPlugin, ShipPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .insert_resource(TimeSpeed(10.0))
+            .init_resource::<FramesOnRails>()
+            .add_systems(
+                PreUpdate,
+                count_frames_on_rails
+                    .after(OrbitSet::Rails)
+                    .before(OrbitSet::Propagate),
+            );
+        let earth_body = Body::new(5.97219e24).with_radius(6.371e6);
+        let earth = app.world_mut().spawn(earth_body).id();
+        let orbit = Orbit::new_orbit(
+            7.0e6,
+            0.0,
+            0.0,
+            0.5,
+            0.0,
+            &earth_body,
+            &SimulationClock::default(),
+            0.0,
+        )
+        .unwrap();
+        let ship = spawn_ship(&mut app.world_mut().commands(), "Ship", orbit, earth).unwrap();
+        app.world_mut().flush();
+        app.world_mut().entity_mut(ship).insert(CurrentShip);
+        app.update();
+        assert_eq!(app.world().resource::<FramesOnRails>().0, 1);
 
-            next_state.set(CameraMode::Close);
+        // Each frame is a simulated second
+        let coast_delay = RailsPolicy::default().coast_delay as usize;
+        app.world_mut().resource_mut::<FlightControls>().translation = Vector3::z();
+        app.update();
+        app.world_mut().resource_mut::<FramesOnRails>().0 = 0;
+        for _ in 0..coast_delay * 3 {
+            app.update();
         }
-        CameraMode::Close => {
-            // TODO: Make this dependant on planet size and also cache the old positions
-            orbit_distance.single_mut().unwrap().0 = 10000000.;
-            camera_center.0 = Vec3::ZERO;
-            camera_up.0 = Vec3::Y;
+        assert_eq!(app.world().resource::<FramesOnRails>().0, 0);
 
-            next_state.set(CameraMode::Map);
+        app.world_mut().resource_mut::<FlightControls>().translation = Vector3::zeros();
+        for _ in 0..coast_delay + 2 {
+            app.update();
         }
+        assert!(app.world().resource::<FramesOnRails>().0 > 0);
     }
 }
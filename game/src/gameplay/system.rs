@@ -0,0 +1,1244 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadState, io::Reader},
+    prelude::*,
+};
+use nalgebra::Vector3;
+use orbits::{
+    Body, J2000_JULIAN_DATE, LightSource, Orbit, OrbitsAround, PlanetEphemeris, SimulationClock,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    CurrentShip, Earth, PlanetLayout, Ship, Sun,
+    generator::generate_system,
+    planet::{ActiveBody, Appearance, create_body},
+    ship::spawn_ship,
+};
+
+/// Loads the selected solar system through the asset server and spawns it.
+/// With the `hot_reload` feature, edits to the file are applied while playing.
+pub struct SolarSystemPlugin;
+
+impl Plugin for SolarSystemPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<SystemDefinition>()
+            .init_asset_loader::<SystemLoader>()
+            .init_resource::<SelectedSystem>()
+            .add_systems(Startup, load_system)
+            .add_systems(
+                Update,
+                (
+                    spawn_loaded_system.run_if(resource_exists::<PendingSystem>),
+                    reload_system,
+                ),
+            );
+    }
+}
+
+/// The solar system spawned at startup.
+/// Chosen with `--system <preset, path or generated:seed>` on the command line or by the
+/// loaded save file.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum SelectedSystem {
+    /// Asset path relative to the assets folder
+    Asset(String),
+    /// Built by `generate_system` from the seed
+    Generated(u64),
+}
+
+impl Default for SelectedSystem {
+    fn default() -> Self {
+        Self::new("fictional")
+    }
+}
+
+impl SelectedSystem {
+    /// Systems shipped with the game, by name and path
+    pub const PRESETS: [(&str, &str); 2] = [
+        ("fictional", "systems/fictional.system.ron"),
+        ("solar", "systems/solar.system.ron"),
+    ];
+
+    /// Selects a preset by name or a generated system with `generated:<seed>`, a plain
+    /// `generated` picks a random seed. Any other value is used as the asset path.
+    pub fn new(value: &str) -> Self {
+        if value == "generated" {
+            return Self::Generated(getrandom::u64().unwrap_or_default());
+        }
+        if let Some(seed) = value
+            .strip_prefix("generated:")
+            .and_then(|seed| seed.parse().ok())
+        {
+            return Self::Generated(seed);
+        }
+        let path = Self::PRESETS
+            .iter()
+            .find(|(name, _)| *name == value)
+            .map_or(value, |(_, path)| path);
+        Self::Asset(path.to_string())
+    }
+
+    /// Reads `--system <value>` from the command line arguments
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        args.windows(2)
+            .find(|pair| pair[0] == "--system")
+            .map(|pair| Self::new(&pair[1]))
+    }
+}
+
+/// The solar system that is being loaded
+#[derive(Resource, Debug)]
+pub(super) struct PendingSystem(Handle<SystemDefinition>);
+
+/// The solar system in the world, along with the definition and entities it was built from
+#[derive(Resource, Debug)]
+pub struct SolarSystem {
+    handle: Handle<SystemDefinition>,
+    definition: SystemDefinition,
+    bodies: HashMap<String, Entity>,
+    ships: HashMap<String, Entity>,
+}
+
+/// Description of a solar system, read from `.system.ron` files.
+/// Lengths are in meters, masses in kg and angles in radians.
+/// Bodies are spawned in order, so parents must come before their satellites.
+#[derive(Asset, TypePath, Deserialize, Debug, Clone, PartialEq)]
+pub struct SystemDefinition {
+    pub name: String,
+    /// Placement of the planets with an ephemeris orbit, the `PlanetLayout` resource when
+    /// missing
+    #[serde(default)]
+    pub layout: Option<PlanetLayout>,
+    pub bodies: Vec<BodyDefinition>,
+    #[serde(default)]
+    pub ships: Vec<ShipDefinition>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct BodyDefinition {
+    pub name: String,
+    /// Barycenters of binary pairs take the mass of their members and leave it at zero
+    #[serde(default)]
+    pub mass: f64,
+    #[serde(default)]
+    pub radius: f64,
+    /// Only the root body has no parent
+    #[serde(default)]
+    pub parent: Option<String>,
+    /// Members of a binary pair get their orbit from the barycenter
+    #[serde(default)]
+    pub orbit: Option<OrbitDefinition>,
+    /// Makes the body a barycenter of two bodies orbiting each other
+    #[serde(default)]
+    pub binary: Option<BinaryDefinition>,
+    /// Bodies without a view are invisible, like barycenters
+    #[serde(default)]
+    pub view: Option<ViewDefinition>,
+    /// Shines with this luminosity in W
+    #[serde(default)]
+    pub luminosity: Option<f64>,
+    #[serde(default)]
+    pub markers: Vec<Marker>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub enum OrbitDefinition {
+    Elements(ElementsDefinition),
+    /// Mean elements of a major planet around the root body, placed according to `PlanetLayout`
+    Ephemeris(String),
+    Free {
+        position: (f64, f64, f64),
+        velocity: (f64, f64, f64),
+    },
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ElementsDefinition {
+    pub semimajor_axis: f64,
+    pub eccentricity: f64,
+    #[serde(default)]
+    pub argument_of_periapsis: f64,
+    #[serde(default)]
+    pub inclination: f64,
+    #[serde(default)]
+    pub longitude_of_ascending_node: f64,
+    /// Seconds since the reference epoch of the last periapsis passage
+    #[serde(default)]
+    pub periapsis_epoch: f64,
+}
+
+/// Two bodies orbiting their barycenter, `semimajor_axis` is their separation
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct BinaryDefinition {
+    pub primary: String,
+    pub secondary: String,
+    pub elements: ElementsDefinition,
+}
+
+/// Look of a planet, colors are sRGB from 0 to 1
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ViewDefinition {
+    pub radius: f32,
+    pub color: (f32, f32, f32),
+    /// Terrain colors, derived from `color` when missing
+    #[serde(default)]
+    pub terrain: Option<TerrainDefinition>,
+}
+
+/// Linear colors of each terrain height
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct TerrainDefinition {
+    pub deep_water: (f32, f32, f32),
+    pub water: (f32, f32, f32),
+    pub sand: (f32, f32, f32),
+    pub grass: (f32, f32, f32),
+    pub mountains: (f32, f32, f32),
+    pub snow: (f32, f32, f32),
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Marker {
+    Sun,
+    Earth,
+    /// The planet the camera starts around
+    Active,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ShipDefinition {
+    pub name: String,
+    pub parent: String,
+    pub orbit: OrbitDefinition,
+    /// The ship the player starts controlling, the first ship when none is marked
+    #[serde(default)]
+    pub current: bool,
+}
+
+/// Problems found in a system definition before spawning it
+#[derive(Debug, Clone, PartialEq)]
+pub enum SystemError {
+    NoBodies,
+    DuplicateName(String),
+    UnknownParent {
+        name: String,
+        parent: String,
+    },
+    /// The parent is defined after the body
+    ParentAfterChild {
+        name: String,
+        parent: String,
+    },
+    OrbitsItself(String),
+    MultipleRoots(String),
+    MissingOrbit(String),
+    InvalidMass(String),
+    InvalidRadius(String),
+    UnknownEphemeris {
+        name: String,
+        planet: String,
+    },
+    InvalidBinary {
+        name: String,
+        reason: &'static str,
+    },
+    MultipleActiveBodies,
+    MultipleCurrentShips,
+}
+
+impl std::fmt::Display for SystemError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoBodies => write!(f, "The system has no bodies"),
+            Self::DuplicateName(name) => write!(f, "{name} is defined more than once"),
+            Self::UnknownParent { name, parent } => {
+                write!(f, "{name} orbits {parent}, which is not defined")
+            }
+            Self::ParentAfterChild { name, parent } => {
+                write!(
+                    f,
+                    "{name} orbits {parent}, which has to be defined before it"
+                )
+            }
+            Self::OrbitsItself(name) => write!(f, "{name} can not orbit itself"),
+            Self::MultipleRoots(name) => {
+                write!(f, "{name} has no parent but the system already has a root")
+            }
+            Self::MissingOrbit(name) => write!(f, "{name} has a parent but no orbit"),
+            Self::InvalidMass(name) => write!(f, "{name} must have a positive mass"),
+            Self::InvalidRadius(name) => write!(f, "{name} must have a non negative radius"),
+            Self::UnknownEphemeris { name, planet } => {
+                write!(
+                    f,
+                    "{name} uses the ephemeris of {planet}, which is not available"
+                )
+            }
+            Self::InvalidBinary { name, reason } => write!(f, "Binary {name}: {reason}"),
+            Self::MultipleActiveBodies => write!(f, "More than one body is marked as active"),
+            Self::MultipleCurrentShips => write!(f, "More than one ship is marked as current"),
+        }
+    }
+}
+
+impl std::error::Error for SystemError {}
+
+/// Errors of the asset loader
+#[derive(Debug)]
+pub enum SystemLoadError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Invalid(SystemError),
+}
+
+impl std::fmt::Display for SystemLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "Could not read the system: {error}"),
+            Self::Parse(error) => write!(f, "Could not parse the system: {error}"),
+            Self::Invalid(error) => write!(f, "Invalid system: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for SystemLoadError {}
+
+impl From<std::io::Error> for SystemLoadError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for SystemLoadError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        Self::Parse(error)
+    }
+}
+
+impl From<SystemError> for SystemLoadError {
+    fn from(error: SystemError) -> Self {
+        Self::Invalid(error)
+    }
+}
+
+#[derive(Default)]
+struct SystemLoader;
+
+impl AssetLoader for SystemLoader {
+    type Asset = SystemDefinition;
+    type Settings = ();
+    type Error = SystemLoadError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<SystemDefinition, SystemLoadError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let definition: SystemDefinition = ron::de::from_bytes(&bytes)?;
+        definition.validate()?;
+        Ok(definition)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["system.ron"]
+    }
+}
+
+fn ephemeris(planet: &str) -> Option<PlanetEphemeris> {
+    PlanetEphemeris::ALL
+        .into_iter()
+        .find(|ephemeris| format!("{ephemeris:?}") == planet)
+}
+
+impl SystemDefinition {
+    /// Checks the names, hierarchy and physical values, orbits are checked when spawned.
+    /// Parents must come before their satellites, so the first body is the only root.
+    pub fn validate(&self) -> Result<(), SystemError> {
+        if self.bodies.is_empty() {
+            return Err(SystemError::NoBodies);
+        }
+
+        let mut indices = HashMap::new();
+        for (index, body) in self.bodies.iter().enumerate() {
+            if indices.insert(body.name.as_str(), index).is_some() {
+                return Err(SystemError::DuplicateName(body.name.clone()));
+            }
+        }
+
+        let mut root = None;
+        for (index, body) in self.bodies.iter().enumerate() {
+            let name = body.name.clone();
+            if !(body.radius >= 0.0 && body.radius.is_finite()) {
+                return Err(SystemError::InvalidRadius(name));
+            }
+            if body.binary.is_none() && !(body.mass > 0.0 && body.mass.is_finite()) {
+                return Err(SystemError::InvalidMass(name));
+            }
+
+            let member_of = self.bodies.iter().find(|barycenter| {
+                barycenter
+                    .binary
+                    .as_ref()
+                    .is_some_and(|binary| binary.primary == name || binary.secondary == name)
+            });
+            match &body.parent {
+                None if root.is_some() => return Err(SystemError::MultipleRoots(name)),
+                None => root = Some(index),
+                Some(parent) => match indices.get(parent.as_str()) {
+                    None => {
+                        return Err(SystemError::UnknownParent {
+                            name,
+                            parent: parent.clone(),
+                        });
+                    }
+                    Some(&parent_index) if parent_index == index => {
+                        return Err(SystemError::OrbitsItself(name));
+                    }
+                    Some(&parent_index) if parent_index > index => {
+                        return Err(SystemError::ParentAfterChild {
+                            name,
+                            parent: parent.clone(),
+                        });
+                    }
+                    Some(_) => {
+                        if body.orbit.is_none() && member_of.is_none() {
+                            return Err(SystemError::MissingOrbit(name));
+                        }
+                    }
+                },
+            }
+
+            if let Some(OrbitDefinition::Ephemeris(planet)) = &body.orbit
+                && ephemeris(planet).is_none()
+            {
+                return Err(SystemError::UnknownEphemeris {
+                    name,
+                    planet: planet.clone(),
+                });
+            }
+
+            if let Some(binary) = &body.binary {
+                let invalid = |reason| SystemError::InvalidBinary {
+                    name: name.clone(),
+                    reason,
+                };
+                for member in [&binary.primary, &binary.secondary] {
+                    let Some(&member_index) = indices.get(member.as_str()) else {
+                        return Err(invalid("unknown member"));
+                    };
+                    let member = &self.bodies[member_index];
+                    if member.parent.as_deref() != Some(name.as_str()) {
+                        return Err(invalid("members must orbit the barycenter"));
+                    }
+                    if member.orbit.is_some() {
+                        return Err(invalid("members can not have their own orbit"));
+                    }
+                }
+                if binary.primary == binary.secondary {
+                    return Err(invalid("a body can not orbit itself"));
+                }
+            }
+        }
+
+        let active = self
+            .bodies
+            .iter()
+            .filter(|body| body.markers.contains(&Marker::Active));
+        if active.count() > 1 {
+            return Err(SystemError::MultipleActiveBodies);
+        }
+
+        for ship in &self.ships {
+            if !indices.contains_key(ship.parent.as_str()) {
+                return Err(SystemError::UnknownParent {
+                    name: ship.name.clone(),
+                    parent: ship.parent.clone(),
+                });
+            }
+            if let OrbitDefinition::Ephemeris(planet) = &ship.orbit {
+                return Err(SystemError::UnknownEphemeris {
+                    name: ship.name.clone(),
+                    planet: planet.clone(),
+                });
+            }
+        }
+        if self.ships.iter().filter(|ship| ship.current).count() > 1 {
+            return Err(SystemError::MultipleCurrentShips);
+        }
+
+        Ok(())
+    }
+}
+
+impl OrbitDefinition {
+    fn orbit(&self, parent: &Body, clock: &SimulationClock, layout: PlanetLayout) -> Result<Orbit> {
+        Ok(match self {
+            Self::Elements(elements) => elements.orbit(parent, clock)?,
+            Self::Ephemeris(planet) => {
+                let planet = ephemeris(planet).ok_or("Unknown ephemeris")?;
+                match layout {
+                    PlanetLayout::Ephemerides => Orbit::from_ephemeris(planet, parent, clock)?,
+                    PlanetLayout::Periapsis => {
+                        let elements = planet.mean_elements(J2000_JULIAN_DATE);
+                        let argument_of_periapsis =
+                            elements.longitude_of_perihelion - elements.longitude_of_ascending_node;
+                        Orbit::new_orbit(
+                            elements.semimajor_axis,
+                            elements.eccentricity,
+                            argument_of_periapsis,
+                            elements.inclination,
+                            elements.longitude_of_ascending_node,
+                            parent,
+                            clock,
+                            0.0,
+                        )?
+                    }
+                }
+            }
+            Self::Free { position, velocity } => Orbit::new_free(
+                Vector3::new(position.0, position.1, position.2),
+                Vector3::new(velocity.0, velocity.1, velocity.2),
+                parent,
+            )?,
+        })
+    }
+}
+
+impl ElementsDefinition {
+    fn orbit(&self, parent: &Body, clock: &SimulationClock) -> Result<Orbit> {
+        Ok(Orbit::new_orbit(
+            self.semimajor_axis,
+            self.eccentricity,
+            self.argument_of_periapsis,
+            self.inclination,
+            self.longitude_of_ascending_node,
+            parent,
+            clock,
+            self.periapsis_epoch,
+        )?)
+    }
+}
+
+fn load_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut definitions: ResMut<Assets<SystemDefinition>>,
+    selected: Res<SelectedSystem>,
+) {
+    start_loading(&mut commands, &asset_server, &mut definitions, &selected);
+}
+
+/// Starts loading or generating a system, it is spawned by `spawn_loaded_system` once ready.
+/// Returns the handle of its definition, or `None` if it could not be generated.
+pub(super) fn start_loading(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    definitions: &mut Assets<SystemDefinition>,
+    selected: &SelectedSystem,
+) -> Option<Handle<SystemDefinition>> {
+    let handle = match selected {
+        SelectedSystem::Asset(path) => {
+            info!("Loading the solar system {path}");
+            asset_server.load(path.clone())
+        }
+        SelectedSystem::Generated(seed) => {
+            info!("Generating a solar system with seed {seed}");
+            let definition = generate_system(*seed);
+            if let Err(error) = definition.validate() {
+                error!("Generated an invalid system: {error}");
+                return None;
+            }
+            definitions.add(definition)
+        }
+    };
+    commands.insert_resource(PendingSystem(handle.clone()));
+    Some(handle)
+}
+
+/// Spawns the pending system once its definition is ready. The current system and every ship
+/// are only despawned when the new one could be created.
+pub(super) fn spawn_loaded_system(
+    mut commands: Commands,
+    pending: Res<PendingSystem>,
+    asset_server: Res<AssetServer>,
+    definitions: Res<Assets<SystemDefinition>>,
+    clock: Res<SimulationClock>,
+    layout: Res<PlanetLayout>,
+    (previous, ships): (Option<Res<SolarSystem>>, Query<Entity, With<Ship>>),
+) {
+    // Generated systems are added directly, without going through the asset server
+    let Some(definition) = definitions.get(&pending.0) else {
+        if let LoadState::Failed(error) = asset_server.load_state(&pending.0) {
+            error!("Could not load the solar system: {error}");
+            commands.remove_resource::<PendingSystem>();
+        }
+        return;
+    };
+    commands.remove_resource::<PendingSystem>();
+
+    let mut system = SolarSystem::new(pending.0.clone());
+    match system.apply(&mut commands, definition, &clock, *layout) {
+        Ok(_) => {
+            info!("Spawned the solar system {}", definition.name);
+            if let Some(previous) = previous {
+                previous.despawn_bodies(&mut commands);
+            }
+            for ship in ships.iter() {
+                commands.entity(ship).despawn();
+            }
+            if let Some(ship) = system.starting_ship() {
+                commands.entity(ship).insert(CurrentShip);
+            }
+            commands.insert_resource(system);
+        }
+        Err(error) => {
+            error!(
+                "Could not create the solar system {}: {error}",
+                definition.name
+            );
+            for entity in system
+                .bodies
+                .into_values()
+                .chain(system.ships.into_values())
+            {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
+
+/// Applies the edits of the definition file while playing. Invalid files, either rejected by
+/// the loader or with orbits that can not be created, leave the system as it was.
+fn reload_system(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<SystemDefinition>>,
+    system: Option<ResMut<SolarSystem>>,
+    definitions: Res<Assets<SystemDefinition>>,
+    clock: Res<SimulationClock>,
+    layout: Res<PlanetLayout>,
+    current_ship: Query<Entity, With<CurrentShip>>,
+) {
+    let Some(mut system) = system else {
+        events.clear();
+        return;
+    };
+    if !events.read().any(|event| event.is_modified(&system.handle)) {
+        return;
+    }
+    let Some(definition) = definitions.get(&system.handle) else {
+        return;
+    };
+
+    match system.apply(&mut commands, definition, &clock, *layout) {
+        Ok(removed) => {
+            info!("Reloaded the solar system {}", definition.name);
+            if current_ship
+                .single()
+                .is_ok_and(|ship| removed.contains(&ship))
+                && let Some(ship) = system.starting_ship()
+            {
+                commands.entity(ship).insert(CurrentShip);
+            }
+        }
+        Err(error) => error!(
+            "Could not reload the solar system {}: {error}",
+            definition.name
+        ),
+    }
+}
+
+impl SolarSystem {
+    fn new(handle: Handle<SystemDefinition>) -> Self {
+        Self {
+            handle,
+            definition: SystemDefinition {
+                name: String::new(),
+                layout: None,
+                bodies: Vec::new(),
+                ships: Vec::new(),
+            },
+            bodies: HashMap::new(),
+            ships: HashMap::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.definition.name
+    }
+
+    /// Bodies in the order of the definition
+    pub(super) fn bodies(&self) -> impl Iterator<Item = (&str, Entity)> {
+        self.definition.bodies.iter().filter_map(|body| {
+            let entity = self.bodies.get(&body.name)?;
+            Some((body.name.as_str(), *entity))
+        })
+    }
+
+    pub(super) fn body(&self, name: &str) -> Option<Entity> {
+        self.bodies.get(name).copied()
+    }
+
+    pub(super) fn ship(&self, name: &str) -> Option<Entity> {
+        self.ships.get(name).copied()
+    }
+
+    /// Stops tracking a ship of the definition that was despawned, the next reload of the
+    /// definition spawns it again
+    pub(super) fn forget_ship(&mut self, name: &str) {
+        self.ships.remove(name);
+    }
+
+    pub(super) fn handle(&self) -> &Handle<SystemDefinition> {
+        &self.handle
+    }
+
+    /// Despawns every body of the system, ships are left to the caller
+    pub(super) fn despawn_bodies(&self, commands: &mut Commands) {
+        for &entity in self.bodies.values() {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    /// The ship marked as current in the definition, or the first one
+    fn starting_ship(&self) -> Option<Entity> {
+        let ships = &self.definition.ships;
+        let ship = ships.iter().find(|ship| ship.current).or(ships.first())?;
+        self.ships.get(&ship.name).copied()
+    }
+
+    /// Brings the world in line with a validated definition and returns the despawned entities.
+    /// Bodies whose definition and parent did not change are left alone, as are ships whose
+    /// definition did not change, so they keep their entity and state. The `Active` marker
+    /// only moves the camera when no planet has the focus, see `ActiveBody`. The `layout` is
+    /// used unless the definition sets its own.
+    /// Fails without changing anything if an orbit can not be created.
+    fn apply(
+        &mut self,
+        commands: &mut Commands,
+        definition: &SystemDefinition,
+        clock: &SimulationClock,
+        layout: PlanetLayout,
+    ) -> Result<Vec<Entity>> {
+        let layout = definition.layout.unwrap_or(layout);
+        let layout_changed = self.definition.layout != definition.layout;
+        let old_body = |name: &str| self.definition.bodies.iter().find(|body| body.name == name);
+        let mut changed: HashSet<&str> = definition
+            .bodies
+            .iter()
+            .filter(|body| {
+                old_body(&body.name) != Some(*body)
+                    || (layout_changed && matches!(body.orbit, Some(OrbitDefinition::Ephemeris(_))))
+            })
+            .map(|body| body.name.as_str())
+            .collect();
+        // The mass of a barycenter comes from its members
+        for body in &definition.bodies {
+            if let Some(binary) = &body.binary
+                && (changed.contains(binary.primary.as_str())
+                    || changed.contains(binary.secondary.as_str()))
+            {
+                changed.insert(body.name.as_str());
+            }
+        }
+
+        // Every orbit is computed before touching the world, so a definition that can not be
+        // spawned leaves the system as it was. Bodies that are kept have no update.
+        let mut updates: Vec<(&BodyDefinition, Option<BodyUpdate>)> = Vec::new();
+        let mut physics: HashMap<&str, Body> = HashMap::new();
+        // Orbits of binary members, computed with their barycenter
+        let mut pair_orbits: HashMap<&str, Orbit> = HashMap::new();
+        for body_definition in &definition.bodies {
+            let name = body_definition.name.as_str();
+            let body = body_definition.body(definition);
+            // Orbits depend on the mass of the parent
+            if body_definition
+                .parent
+                .as_ref()
+                .is_some_and(|parent| changed.contains(parent.as_str()))
+            {
+                changed.insert(name);
+            }
+            physics.insert(name, body);
+            if self.bodies.contains_key(name) && !changed.contains(name) {
+                updates.push((body_definition, None));
+                continue;
+            }
+
+            let orbit = match &body_definition.parent {
+                None => None,
+                Some(parent_name) => {
+                    let parent_body = physics
+                        .get(parent_name.as_str())
+                        .ok_or("Parent not spawned")?;
+                    let orbit = match (&body_definition.orbit, pair_orbits.remove(name)) {
+                        (Some(orbit), _) => orbit.orbit(parent_body, clock, layout)?,
+                        (None, Some(orbit)) => orbit,
+                        (None, None) => {
+                            return Err(SystemError::MissingOrbit(name.to_string()).into());
+                        }
+                    };
+                    Some((orbit, parent_name.as_str()))
+                }
+            };
+
+            if let Some(binary) = &body_definition.binary {
+                let elements = binary.elements;
+                let (primary_orbit, secondary_orbit) = Orbit::new_binary_pair(
+                    elements.semimajor_axis,
+                    elements.eccentricity,
+                    elements.argument_of_periapsis,
+                    elements.inclination,
+                    elements.longitude_of_ascending_node,
+                    definition_mass(definition, &binary.primary),
+                    definition_mass(definition, &binary.secondary),
+                    &body,
+                    clock,
+                    elements.periapsis_epoch,
+                )?;
+                pair_orbits.insert(binary.primary.as_str(), primary_orbit);
+                pair_orbits.insert(binary.secondary.as_str(), secondary_orbit);
+            }
+            updates.push((body_definition, Some(BodyUpdate { body, orbit })));
+        }
+
+        let old_ship = |name: &str| self.definition.ships.iter().find(|ship| ship.name == name);
+        let mut ship_updates = Vec::new();
+        for ship in &definition.ships {
+            let &parent_body = physics
+                .get(ship.parent.as_str())
+                .ok_or("Parent not spawned")?;
+            let update = match self.ships.get(&ship.name) {
+                Some(&entity) if old_ship(&ship.name) == Some(ship) => ShipUpdate::Keep {
+                    entity,
+                    new_parent: changed
+                        .contains(ship.parent.as_str())
+                        .then_some(parent_body),
+                },
+                Some(&entity) => ShipUpdate::Replace {
+                    entity,
+                    orbit: ship.orbit.orbit(&parent_body, clock, layout)?,
+                },
+                None => ShipUpdate::Spawn(ship.orbit.orbit(&parent_body, clock, layout)?),
+            };
+            ship_updates.push((ship, update));
+        }
+
+        let mut bodies: HashMap<&str, Entity> = HashMap::new();
+        for (body_definition, update) in updates {
+            let name = body_definition.name.as_str();
+            let Some(BodyUpdate { body, orbit }) = update else {
+                bodies.insert(name, self.bodies[name]);
+                continue;
+            };
+            // Parents come first, so they are already in the world
+            let orbit = orbit.map(|(orbit, parent)| (orbit, bodies[parent]));
+
+            let entity = match self.bodies.get(name) {
+                Some(&entity) => {
+                    update_body(commands, entity, body_definition, body, orbit);
+                    entity
+                }
+                None => create_body(
+                    commands,
+                    body,
+                    orbit,
+                    body_definition.view.clone().map(Appearance),
+                ),
+            };
+            let mut entity_commands = commands.entity(entity);
+            entity_commands
+                .insert(Name::new(body_definition.name.clone()))
+                .remove::<(LightSource, Sun, Earth, ActiveBody)>();
+            if let Some(luminosity) = body_definition.luminosity {
+                entity_commands.insert(LightSource { luminosity });
+            }
+            for marker in &body_definition.markers {
+                match marker {
+                    Marker::Sun => entity_commands.insert(Sun),
+                    Marker::Earth => entity_commands.insert(Earth),
+                    Marker::Active => entity_commands.insert(ActiveBody),
+                };
+            }
+            bodies.insert(name, entity);
+            self.bodies.insert(body_definition.name.clone(), entity);
+        }
+
+        let mut ships = HashMap::new();
+        for (ship, update) in ship_updates {
+            let parent = bodies[ship.parent.as_str()];
+            let entity = match update {
+                ShipUpdate::Keep { entity, new_parent } => {
+                    // Keeps flying from where it is around the new version of its parent
+                    if let Some(parent_body) = new_parent {
+                        commands
+                            .entity(entity)
+                            .queue(move |mut entity: EntityWorldMut| {
+                                if let Some(mut orbit) = entity.get_mut::<Orbit>() {
+                                    let state = orbit.state_vectors();
+                                    match Orbit::new_free(
+                                        state.position,
+                                        state.velocity,
+                                        &parent_body,
+                                    ) {
+                                        Ok(free) => *orbit = free,
+                                        Err(error) => warn!("Could not move a ship: {error}"),
+                                    }
+                                }
+                            });
+                    }
+                    entity
+                }
+                ShipUpdate::Replace { entity, orbit } => {
+                    commands
+                        .entity(entity)
+                        .insert((orbit, OrbitsAround(parent)));
+                    entity
+                }
+                ShipUpdate::Spawn(orbit) => spawn_ship(commands, &ship.name, orbit, parent)?,
+            };
+            ships.insert(ship.name.clone(), entity);
+        }
+
+        let mut removed = Vec::new();
+        self.bodies.retain(|name, entity| {
+            let keep = bodies.contains_key(name.as_str());
+            if !keep {
+                removed.push(*entity);
+            }
+            keep
+        });
+        for (name, entity) in self.ships.drain() {
+            if !ships.contains_key(&name) {
+                removed.push(entity);
+            }
+        }
+        for &entity in &removed {
+            commands.entity(entity).despawn();
+        }
+        self.ships = ships;
+        self.definition = definition.clone();
+
+        Ok(removed)
+    }
+}
+
+/// New physics of a body that is spawned or replaced, the orbit names its parent
+struct BodyUpdate<'a> {
+    body: Body,
+    orbit: Option<(Orbit, &'a str)>,
+}
+
+/// What happens to a ship of the definition
+enum ShipUpdate {
+    /// Unchanged, with the new physics of its parent if the parent changed
+    Keep {
+        entity: Entity,
+        new_parent: Option<Body>,
+    },
+    /// Changed, it is put on the orbit of the new definition
+    Replace {
+        entity: Entity,
+        orbit: Orbit,
+    },
+    Spawn(Orbit),
+}
+
+impl BodyDefinition {
+    fn body(&self, definition: &SystemDefinition) -> Body {
+        let body = match &self.binary {
+            Some(binary) => Body::new_barycenter(
+                definition_mass(definition, &binary.primary),
+                definition_mass(definition, &binary.secondary),
+            ),
+            None => Body::new(self.mass),
+        };
+        if self.radius > 0.0 {
+            body.with_radius(self.radius)
+        } else {
+            body
+        }
+    }
+}
+
+/// Replaces the physics and the look of a body that is already in the world
+fn update_body(
+    commands: &mut Commands,
+    entity: Entity,
+    definition: &BodyDefinition,
+    body: Body,
+    orbit: Option<(Orbit, Entity)>,
+) {
+    let mut entity_commands = commands.entity(entity);
+    entity_commands.insert(body);
+    match orbit {
+        Some((orbit, parent)) => entity_commands.insert((orbit, OrbitsAround(parent))),
+        None => entity_commands.remove::<(Orbit, OrbitsAround)>(),
+    };
+    match &definition.view {
+        // The render plugin rebuilds the view of changed planets
+        Some(view) => entity_commands.insert(Appearance(view.clone())),
+        None => entity_commands.remove::<Appearance>(),
+    };
+}
+
+fn definition_mass(definition: &SystemDefinition, name: &str) -> f64 {
+    definition
+        .bodies
+        .iter()
+        .find(|body| body.name == name)
+        .map_or(0.0, |body| body.mass)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(name: &str, parent: Option<&str>) -> BodyDefinition {
+        BodyDefinition {
+            name: name.to_string(),
+            mass: 1.0e24,
+            radius: 0.0,
+            parent: parent.map(str::to_string),
+            orbit: parent.map(|_| {
+                OrbitDefinition::Elements(ElementsDefinition {
+                    semimajor_axis: 1.0e9,
+                    eccentricity: 0.1,
+                    argument_of_periapsis: 0.0,
+                    inclination: 0.0,
+                    longitude_of_ascending_node: 0.0,
+                    periapsis_epoch: 0.0,
+                })
+            }),
+            binary: None,
+            view: None,
+            luminosity: None,
+            markers: Vec::new(),
+        }
+    }
+
+    fn ship(name: &str, parent: &str) -> ShipDefinition {
+        ShipDefinition {
+            name: name.to_string(),
+            parent: parent.to_string(),
+            orbit: OrbitDefinition::Free {
+                position: (1.0e7, 0.0, 0.0),
+                velocity: (0.0, 0.0, 1.0e3),
+            },
+            current: false,
+        }
+    }
+
+    /// A star with a planet and a ship around it
+    fn system() -> SystemDefinition {
+        SystemDefinition {
+            name: "Test".to_string(),
+            layout: None,
+            bodies: vec![body("Star", None), body("Planet", Some("Star"))],
+            ships: vec![ship("Ship", "Planet")],
+        }
+    }
+
+    /// A barycenter orbiting the star with two members
+    fn binary_system() -> SystemDefinition {
+        let mut system = system();
+        let mut barycenter = body("Barycenter", Some("Star"));
+        barycenter.mass = 0.0;
+        barycenter.binary = Some(BinaryDefinition {
+            primary: "Primary".to_string(),
+            secondary: "Secondary".to_string(),
+            elements: ElementsDefinition {
+                semimajor_axis: 1.0e8,
+                eccentricity: 0.0,
+                argument_of_periapsis: 0.0,
+                inclination: 0.0,
+                longitude_of_ascending_node: 0.0,
+                periapsis_epoch: 0.0,
+            },
+        });
+        let mut primary = body("Primary", Some("Barycenter"));
+        primary.orbit = None;
+        let mut secondary = body("Secondary", Some("Barycenter"));
+        secondary.orbit = None;
+        system.bodies.extend([barycenter, primary, secondary]);
+        system
+    }
+
+    #[test]
+    fn valid_systems() {
+        assert_eq!(system().validate(), Ok(()));
+        assert_eq!(binary_system().validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_broken_hierarchies() {
+        let mut empty = system();
+        empty.bodies.clear();
+        assert_eq!(empty.validate(), Err(SystemError::NoBodies));
+
+        let mut duplicate = system();
+        duplicate.bodies.push(body("Planet", Some("Star")));
+        assert_eq!(
+            duplicate.validate(),
+            Err(SystemError::DuplicateName("Planet".to_string()))
+        );
+
+        let mut unknown_parent = system();
+        unknown_parent.bodies.push(body("Moon", Some("Nowhere")));
+        assert_eq!(
+            unknown_parent.validate(),
+            Err(SystemError::UnknownParent {
+                name: "Moon".to_string(),
+                parent: "Nowhere".to_string(),
+            })
+        );
+
+        let mut parent_after_child = system();
+        parent_after_child.bodies.swap(0, 1);
+        assert_eq!(
+            parent_after_child.validate(),
+            Err(SystemError::ParentAfterChild {
+                name: "Planet".to_string(),
+                parent: "Star".to_string(),
+            })
+        );
+
+        // Would leave the system without a root
+        let mut orbits_itself = system();
+        orbits_itself.bodies[0] = body("Star", Some("Star"));
+        assert_eq!(
+            orbits_itself.validate(),
+            Err(SystemError::OrbitsItself("Star".to_string()))
+        );
+        let mut orbits_itself = system();
+        orbits_itself.bodies[1].parent = Some("Planet".to_string());
+        assert_eq!(
+            orbits_itself.validate(),
+            Err(SystemError::OrbitsItself("Planet".to_string()))
+        );
+
+        let mut multiple_roots = system();
+        multiple_roots.bodies.push(body("Other star", None));
+        assert_eq!(
+            multiple_roots.validate(),
+            Err(SystemError::MultipleRoots("Other star".to_string()))
+        );
+
+        let mut missing_orbit = system();
+        missing_orbit.bodies[1].orbit = None;
+        assert_eq!(
+            missing_orbit.validate(),
+            Err(SystemError::MissingOrbit("Planet".to_string()))
+        );
+
+        let mut multiple_active = system();
+        for body in &mut multiple_active.bodies {
+            body.markers.push(Marker::Active);
+        }
+        assert_eq!(
+            multiple_active.validate(),
+            Err(SystemError::MultipleActiveBodies)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        for mass in [0.0, -1.0, f64::NAN] {
+            let mut invalid_mass = system();
+            invalid_mass.bodies[1].mass = mass;
+            assert_eq!(
+                invalid_mass.validate(),
+                Err(SystemError::InvalidMass("Planet".to_string()))
+            );
+        }
+
+        for radius in [-1.0, f64::INFINITY] {
+            let mut invalid_radius = system();
+            invalid_radius.bodies[1].radius = radius;
+            assert_eq!(
+                invalid_radius.validate(),
+                Err(SystemError::InvalidRadius("Planet".to_string()))
+            );
+        }
+
+        let mut unknown_ephemeris = system();
+        unknown_ephemeris.bodies[1].orbit = Some(OrbitDefinition::Ephemeris("Vulcan".to_string()));
+        assert_eq!(
+            unknown_ephemeris.validate(),
+            Err(SystemError::UnknownEphemeris {
+                name: "Planet".to_string(),
+                planet: "Vulcan".to_string(),
+            })
+        );
+
+        // Ships can not follow an ephemeris
+        let mut ship_ephemeris = system();
+        ship_ephemeris.ships[0].orbit = OrbitDefinition::Ephemeris("Earth".to_string());
+        assert_eq!(
+            ship_ephemeris.validate(),
+            Err(SystemError::UnknownEphemeris {
+                name: "Ship".to_string(),
+                planet: "Earth".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_binaries() {
+        let invalid = |reason| {
+            Err(SystemError::InvalidBinary {
+                name: "Barycenter".to_string(),
+                reason,
+            })
+        };
+
+        let mut unknown_member = binary_system();
+        unknown_member.bodies[2].binary.as_mut().unwrap().secondary = "Nobody".to_string();
+        assert_eq!(unknown_member.validate(), invalid("unknown member"));
+
+        let mut elsewhere = binary_system();
+        elsewhere.bodies[4].parent = Some("Star".to_string());
+        elsewhere.bodies[4].orbit = elsewhere.bodies[1].orbit.clone();
+        assert_eq!(
+            elsewhere.validate(),
+            invalid("members must orbit the barycenter")
+        );
+
+        let mut own_orbit = binary_system();
+        own_orbit.bodies[3].orbit = own_orbit.bodies[1].orbit.clone();
+        assert_eq!(
+            own_orbit.validate(),
+            invalid("members can not have their own orbit")
+        );
+
+        let mut itself = binary_system();
+        itself.bodies[2].binary.as_mut().unwrap().secondary = "Primary".to_string();
+        itself.bodies.pop();
+        assert_eq!(itself.validate(), invalid("a body can not orbit itself"));
+    }
+
+    #[test]
+    fn rejects_invalid_ships() {
+        let mut unknown_parent = system();
+        unknown_parent.ships[0].parent = "Nowhere".to_string();
+        assert_eq!(
+            unknown_parent.validate(),
+            Err(SystemError::UnknownParent {
+                name: "Ship".to_string(),
+                parent: "Nowhere".to_string(),
+            })
+        );
+
+        let mut multiple_current = system();
+        multiple_current.ships.push(ship("Other ship", "Star"));
+        for ship in &mut multiple_current.ships {
+            ship.current = true;
+        }
+        assert_eq!(
+            multiple_current.validate(),
+            Err(SystemError::MultipleCurrentShips)
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::{path::PathBuf, time::Duration};
+
+use bevy::{app::ScheduleRunnerPlugin, diagnostic::FrameCount, log::LogPlugin, prelude::*};
+use orbits::{AbsolutePosition, SimulationClock, TimeSpeed};
+
+use crate::gameplay::{
+    GamePlayPlugin, LoadGame, PlanetLayout, SaveGame, SelectedSystem, SolarSystem,
+};
+
+/// Command line options of the headless mode:
+/// `--headless [--warp <speed>] [--frames <count>] [--report <frames>] [--system <preset, path or generated:seed>]
+/// [--ephemerides] [--load <path>] [--save <path>]`
+#[derive(Resource, Debug, Clone)]
+struct HeadlessOptions {
+    /// Initial `TimeSpeed`
+    warp: f64,
+    /// Frames to simulate before exiting, runs forever if not set
+    frames: Option<u32>,
+    /// Frames between every print of the state
+    report: u32,
+    system: SelectedSystem,
+    layout: PlanetLayout,
+    /// Save file to continue from
+    load: Option<PathBuf>,
+    /// Where the game is saved when the frames have been simulated
+    save: Option<PathBuf>,
+}
+
+impl HeadlessOptions {
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut options = Self {
+            warp: 1.0,
+            frames: None,
+            report: 60,
+            system: SelectedSystem::default(),
+            layout: PlanetLayout::default(),
+            load: None,
+            save: None,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {name}"))
+                    .cloned()
+            };
+            match arg.as_str() {
+                "--headless" => {}
+                "--warp" => {
+                    options.warp = value(arg)?
+                        .parse()
+                        .map_err(|error| format!("Invalid warp: {error}"))?
+                }
+                "--frames" => {
+                    options.frames = Some(
+                        value(arg)?
+                            .parse()
+                            .map_err(|error| format!("Invalid frame count: {error}"))?,
+                    )
+                }
+                "--report" => {
+                    options.report = value(arg)?
+                        .parse::<u32>()
+                        .map_err(|error| format!("Invalid report interval: {error}"))?
+                        .max(1)
+                }
+                "--system" => options.system = SelectedSystem::new(&value(arg)?),
+                "--ephemerides" => options.layout = PlanetLayout::Ephemerides,
+                "--load" => options.load = Some(PathBuf::from(value(arg)?)),
+                "--save" => options.save = Some(PathBuf::from(value(arg)?)),
+                _ => return Err(format!("Unknown argument {arg}")),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Runs the simulation without a window or GPU and prints the state of the system
+pub fn run(args: &[String]) -> AppExit {
+    let options = match HeadlessOptions::from_args(args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{error}");
+            return AppExit::error();
+        }
+    };
+
+    let mut app = App::new();
+    app.add_plugins(
+        MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+            1.0 / 60.0,
+        ))),
+    )
+    .add_plugins((LogPlugin::default(), AssetPlugin::default()))
+    .add_plugins(orbits::OrbitPlugin)
+    .add_plugins(GamePlayPlugin)
+    .insert_resource(options.system.clone())
+    .insert_resource(options.layout)
+    .insert_resource(TimeSpeed(options.warp))
+    .insert_resource(options.clone())
+    .add_systems(Update, (print_state, exit_after_frames).chain());
+    if let Some(path) = options.load {
+        app.world_mut().send_event(LoadGame(path));
+    }
+    app.run()
+}
+
+fn print_state(
+    options: Res<HeadlessOptions>,
+    frame: Res<FrameCount>,
+    clock: Res<SimulationClock>,
+    system: Option<Res<SolarSystem>>,
+    objects: Query<(Entity, Option<&Name>, &AbsolutePosition)>,
+) {
+    if !frame.0.is_multiple_of(options.report) {
+        return;
+    }
+
+    match system {
+        Some(system) => println!("{} {} (frame {})", system.name(), clock.utc(), frame.0),
+        None => println!("{} (frame {})", clock.utc(), frame.0),
+    }
+    for (entity, name, position) in objects.iter() {
+        let position = position.0 / 1000.0;
+        match name {
+            Some(name) => print!("  {name}"),
+            None => print!("  {entity}"),
+        }
+        println!(
+            ": ({:.0}, {:.0}, {:.0}) km",
+            position.x, position.y, position.z
+        );
+    }
+}
+
+fn exit_after_frames(
+    options: Res<HeadlessOptions>,
+    frame: Res<FrameCount>,
+    mut save: EventWriter<SaveGame>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if options.frames.is_some_and(|frames| frame.0 + 1 >= frames) {
+        // Saved at the end of this frame, before exiting
+        if let Some(path) = &options.save {
+            save.write(SaveGame(path.clone()));
+        }
+        exit.write(AppExit::Success);
+    }
+}
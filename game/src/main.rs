@@ -2,12 +2,18 @@ use bevy::{asset::AssetMetaCheck, prelude::*};
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
 mod gameplay;
+mod headless;
 #[cfg(feature = "online")]
 mod multiplayer;
 mod render;
 mod ui;
 
-fn main() {
+fn main() -> AppExit {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--headless") {
+        return headless::run(&args);
+    }
+
     let mut app = App::new();
     app.add_plugins(
         DefaultPlugins
@@ -31,12 +37,17 @@ fn main() {
     )
     .add_plugins(orbits::OrbitPlugin)
     .add_plugins(gameplay::GamePlayPlugin)
+    .insert_resource(gameplay::SelectedSystem::from_args(&args).unwrap_or_default())
+    .insert_resource(gameplay::PlanetLayout::from_args(&args))
     .add_plugins(bevy_egui::EguiPlugin::default())
     .add_plugins(WorldInspectorPlugin::new())
     .add_plugins((render::RenderPlugin, ui::UiPlugin));
+    if let Some(load) = gameplay::LoadGame::from_args(&args) {
+        app.world_mut().send_event(load);
+    }
 
     #[cfg(feature = "online")]
     app.add_plugins((multiplayer::ServerPlugin, multiplayer::ClientPlugin));
 
-    app.run();
+    app.run()
 }
@@ -1,6 +1,10 @@
+use bevy::ecs::system::SystemParam;
 use bevy::input::common_conditions::input_just_pressed;
 use bevy::prelude::*;
 use free_camera::FreeCameraPlugin;
+use orbits::AbsolutePosition;
+
+use crate::gameplay::CameraState;
 
 pub use orbit_camera::{CameraCenter, CameraUp, OrbitDistance};
 pub use planet::{CurrentPlanet, Planet};
@@ -8,7 +12,9 @@ pub use planet::{CurrentPlanet, Planet};
 mod free_camera;
 mod orbit_camera;
 mod planet;
-use orbit_camera::OrbitCameraPlugin;
+mod ship;
+use orbit_camera::{OrbitAngle, OrbitCameraPlugin};
+use ship::{CameraMode as ShipCameraMode, ShipViewPlugin};
 
 #[derive(Component)]
 pub struct MainCamera;
@@ -36,11 +42,117 @@ impl CameraPosition {
     }
 }
 
+type CameraView = (
+    &'static mut Transform,
+    &'static mut OrbitDistance,
+    &'static mut OrbitAngle,
+);
+
+/// Reads and restores the camera for save files
+#[derive(SystemParam)]
+struct CameraSettings<'w, 's> {
+    mode: Option<Res<'w, State<CameraMode>>>,
+    next_mode: Option<ResMut<'w, NextState<CameraMode>>>,
+    ship_mode: Option<Res<'w, State<ShipCameraMode>>>,
+    next_ship_mode: Option<ResMut<'w, NextState<ShipCameraMode>>>,
+    position: Option<ResMut<'w, CameraPosition>>,
+    center: Option<ResMut<'w, CameraCenter>>,
+    up: Option<ResMut<'w, CameraUp>>,
+    camera: Query<'w, 's, CameraView, With<MainCamera>>,
+    planets: Query<'w, 's, (Entity, &'static Name, Has<CurrentPlanet>), With<Planet>>,
+}
+
+impl CameraSettings<'_, '_> {
+    /// `None` when there is no camera
+    fn state(&self) -> Option<CameraState> {
+        let (transform, distance, angle) = self.camera.single().ok()?;
+        let position = self.position.as_deref()?;
+        let rotation = transform.rotation;
+        Some(CameraState {
+            free: self
+                .mode
+                .as_ref()
+                .is_some_and(|mode| *mode.get() == CameraMode::Free),
+            close: self
+                .ship_mode
+                .as_ref()
+                .is_some_and(|mode| *mode.get() == ShipCameraMode::Close),
+            focus: self
+                .planets
+                .iter()
+                .find(|(_, _, current)| *current)
+                .map(|(_, name, _)| name.to_string()),
+            distance: distance.0,
+            angle: (angle.x, angle.y),
+            position: (position.x, position.y, position.z),
+            rotation: (rotation.x, rotation.y, rotation.z, rotation.w),
+        })
+    }
+
+    /// The focused planet keeps the focus if there is no planet with the saved name
+    fn restore(&mut self, commands: &mut Commands, state: &CameraState) {
+        if let Some(next_mode) = &mut self.next_mode {
+            next_mode.set(if state.free {
+                CameraMode::Free
+            } else {
+                CameraMode::Orbit
+            });
+        }
+        if let Some(next_ship_mode) = &mut self.next_ship_mode {
+            next_ship_mode.set(if state.close {
+                ShipCameraMode::Close
+            } else {
+                ShipCameraMode::Map
+            });
+        }
+        // The close camera follows the ship by itself
+        if !state.close {
+            if let Some(center) = &mut self.center {
+                center.0 = Vec3::ZERO;
+            }
+            if let Some(up) = &mut self.up {
+                up.0 = Vec3::Y;
+            }
+        }
+        if let Some(position) = &mut self.position {
+            (position.x, position.y, position.z) = state.position;
+        }
+        if let Ok((mut transform, mut distance, mut angle)) = self.camera.single_mut() {
+            let (x, y, z, w) = state.rotation;
+            transform.rotation = Quat::from_xyzw(x, y, z, w).normalize();
+            distance.0 = state.distance;
+            (angle.x, angle.y) = state.angle;
+        }
+
+        let Some(focus) = &state.focus else {
+            return;
+        };
+        if !self
+            .planets
+            .iter()
+            .any(|(_, name, _)| name.as_str() == focus)
+        {
+            return;
+        }
+        for (entity, name, current) in self.planets.iter() {
+            match (name.as_str() == focus, current) {
+                (true, false) => {
+                    commands.entity(entity).insert(CurrentPlanet);
+                }
+                (false, true) => {
+                    commands.entity(entity).remove::<CurrentPlanet>();
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
 impl Plugin for RenderPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(MaterialPlugin::<planet::material::PlanetMaterial>::default())
             .insert_resource(CameraPosition::default())
-            .add_plugins((FreeCameraPlugin, OrbitCameraPlugin))
+            .add_plugins((FreeCameraPlugin, OrbitCameraPlugin, ShipViewPlugin))
             .insert_state(CameraMode::Orbit)
             .add_systems(
                 Update,
@@ -49,11 +161,44 @@ impl Plugin for RenderPlugin {
                     planet::update_chunks,
                     planet::on_planet_load,
                     planet::on_planet_unload,
+                    planet::on_planet_changed,
+                    update_positions,
                 ),
+            )
+            // Once the simulation has spawned the bodies of the frame
+            .add_systems(
+                PostUpdate,
+                (
+                    planet::update_planet_views,
+                    planet::focus_active_planet,
+                    sync_camera_state,
+                )
+                    .chain(),
             );
     }
 }
 
+/// Keeps the `CameraState` of save files up to date, and moves the camera when a loaded game
+/// changes it
+fn sync_camera_state(
+    mut commands: Commands,
+    state: Option<ResMut<CameraState>>,
+    mut settings: CameraSettings,
+) {
+    let Some(mut state) = state else {
+        if let Some(current) = settings.state() {
+            commands.insert_resource(current);
+        }
+        return;
+    };
+    // Changes made by this system are not seen on its next run
+    if state.is_changed() && !state.is_added() {
+        settings.restore(&mut commands, &state);
+    } else if let Some(current) = settings.state() {
+        state.set_if_neq(current);
+    }
+}
+
 fn toggle_camera_mode(
     state: Res<State<CameraMode>>,
     mut next_state: ResMut<NextState<CameraMode>>,
@@ -63,3 +208,23 @@ fn toggle_camera_mode(
         CameraMode::Orbit => next_state.set(CameraMode::Free),
     }
 }
+
+/// Places every simulated object relative to the current planet and the camera
+fn update_positions(
+    current_planet_query: Query<&AbsolutePosition, With<CurrentPlanet>>,
+    mut positions_query: Query<(&AbsolutePosition, &mut Transform)>,
+    camera_position: Res<CameraPosition>,
+) {
+    let current_planet = current_planet_query
+        .single()
+        .map(|position| position.0)
+        .unwrap_or_default();
+
+    for (position, mut transform) in positions_query.iter_mut() {
+        transform.translation = Vec3 {
+            x: (position.0.x - current_planet.x - camera_position.x) as f32,
+            y: (position.0.y - current_planet.y - camera_position.y) as f32,
+            z: (position.0.z - current_planet.z - camera_position.z) as f32,
+        };
+    }
+}
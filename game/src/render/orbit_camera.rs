@@ -132,9 +132,9 @@ fn camera_on_orbit_mode(camera_mode: Res<State<CameraMode>>) -> bool {
 }
 
 #[derive(Component, Debug)]
-struct OrbitAngle {
-    x: f32,
-    y: f32,
+pub(super) struct OrbitAngle {
+    pub(super) x: f32,
+    pub(super) y: f32,
 }
 
 #[derive(Resource, Debug, Default)]
@@ -6,6 +6,9 @@ use bevy::{
 use chunk::Chunk;
 use mesh::{MidpointIndexCache, UnusedIndices, UnusedVertices, VertexRc};
 
+use orbits::Body;
+
+use crate::gameplay::{ActiveBody, Appearance};
 use crate::render::planet::material::PlanetUniforms;
 
 use super::CameraPosition;
@@ -60,6 +63,26 @@ pub struct Planet {
 }
 
 impl Planet {
+    /// Terrain colors are derived from the base color when they are not defined
+    pub fn from_appearance(Appearance(view): &Appearance) -> Self {
+        let (red, green, blue) = view.color;
+        let color = Srgba::new(red, green, blue, 1.0);
+        let Some(terrain) = view.terrain else {
+            return Self::from_radious_and_color(view.radius, color);
+        };
+        let linear = |(red, green, blue): (f32, f32, f32)| LinearRgba::new(red, green, blue, 1.0);
+        Self {
+            radius: view.radius,
+            color,
+            deep_water_color: linear(terrain.deep_water),
+            water_color: linear(terrain.water),
+            sand_color: linear(terrain.sand),
+            grass_color: linear(terrain.grass),
+            mountains_color: linear(terrain.mountains),
+            snow_color: linear(terrain.snow),
+        }
+    }
+
     pub fn from_radious_and_color(radius: f32, color: Srgba) -> Self {
         fn scale_color(base: Srgba, factor: f32) -> LinearRgba {
             LinearRgba::new(
@@ -86,6 +109,51 @@ impl Planet {
 #[derive(Component)]
 pub struct CurrentPlanet;
 
+/// Gives a transform to the bodies spawned by the simulation, and a planet view to the ones
+/// with an `Appearance`. The view is rebuilt by `on_planet_changed` when the look changes.
+pub fn update_planet_views(
+    mut commands: Commands,
+    bodies: Query<Entity, Added<Body>>,
+    appearances: Query<(Entity, &Appearance), Changed<Appearance>>,
+    mut removed: RemovedComponents<Appearance>,
+) {
+    for entity in bodies.iter() {
+        commands.entity(entity).insert((
+            // Will be at the origin until the positions are updated
+            GlobalTransform::from_xyz(0.0, 0.0, 0.0),
+            Transform::from_xyz(0.0, 0.0, 0.0),
+            InheritedVisibility::VISIBLE,
+        ));
+    }
+    for (entity, appearance) in appearances.iter() {
+        commands
+            .entity(entity)
+            .insert(Planet::from_appearance(appearance));
+    }
+    for entity in removed.read() {
+        // Despawned bodies are already gone
+        if let Ok(mut entity_commands) = commands.get_entity(entity) {
+            entity_commands
+                .remove::<Planet>()
+                .despawn_related::<Children>();
+        }
+    }
+}
+
+/// Focuses the active body when no planet has the focus, like when a system is spawned
+pub fn focus_active_planet(
+    mut commands: Commands,
+    focused: Query<(), With<CurrentPlanet>>,
+    active: Query<Entity, (With<ActiveBody>, With<Planet>)>,
+) {
+    if !focused.is_empty() {
+        return;
+    }
+    if let Some(entity) = active.iter().next() {
+        commands.entity(entity).insert(CurrentPlanet);
+    }
+}
+
 pub fn update_chunks(
     mut query: Query<(
         &Mesh3d,
@@ -150,24 +218,13 @@ pub fn on_planet_unload(
         let Ok(planet_config) = planets.get(planet) else {
             return;
         };
-
-        let mesh = meshes.add(Sphere::new(planet_config.radius));
-        let low_res_view = commands
-            .spawn((
-                Mesh3d(mesh),
-                MeshMaterial3d(materials.add(StandardMaterial {
-                    base_color: planet_config.color.into(),
-                    unlit: true,
-                    ..default()
-                })),
-            ))
-            .id();
-
-        commands
-            .get_entity(planet)
-            .unwrap()
-            .despawn_related::<Children>()
-            .add_child(low_res_view);
+        spawn_low_res_view(
+            &mut commands,
+            planet,
+            planet_config,
+            &mut meshes,
+            &mut materials,
+        );
     }
 }
 
@@ -178,70 +235,184 @@ pub fn on_planet_load(
     mut materials: ResMut<Assets<material::PlanetMaterial>>,
 ) {
     for (entity, planet) in planet_to_load.iter() {
-        // Spawn all the chunks
-        let mut chunks = Vec::with_capacity(20);
-        for (v1, v2, v3) in ICOSAHEDRON_VERTEX_POSITIONS {
-            let mut vertices = vec![v1, v2, v3];
-            for vertex in &mut vertices {
-                for coord in vertex.iter_mut() {
-                    *coord *= planet.radius;
-                }
-            }
-            let mut indices = vec![];
-            let mut unused_indices = UnusedIndices::default();
-            let mut unused_vertices = UnusedVertices::default();
-            let mut vertex_rc = VertexRc::default();
-            let mut midpoint_cache = MidpointIndexCache::default();
-
-            let chunk = Chunk::new(
-                true,
-                1,
-                planet.radius,
-                [0, 1, 2],
-                &mut indices,
-                &mut vertices,
-                &mut unused_indices,
-                &mut unused_vertices,
-                &mut vertex_rc,
-                &mut midpoint_cache,
-            );
+        spawn_chunks(&mut commands, entity, planet, &mut meshes, &mut materials);
+    }
+}
 
-            let mesh = Mesh::new(
-                bevy::render::mesh::PrimitiveTopology::TriangleList,
-                RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
-            )
-            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vertices)
-            .with_inserted_indices(Indices::U32(indices));
-
-            let chunk = commands
-                .spawn((
-                    Mesh3d(meshes.add(mesh)),
-                    MeshMaterial3d(materials.add(material::PlanetMaterial {
-                        data: PlanetUniforms::new(planet.radius, 8800.0),
-                        deep_water_color: planet.deep_water_color,
-                        water_color: planet.water_color,
-                        sand_color: planet.sand_color,
-                        grass_color: planet.grass_color,
-                        mountain_color: planet.mountains_color,
-                        snow_color: planet.snow_color,
-                    })),
-                    Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)),
-                    PlanetViewBundle {
-                        chunk,
-                        midpoint_cache,
-                        unused_indices,
-                        unused_vertices,
-                        vertex_rc,
-                    },
-                ))
-                .id();
-            chunks.push(chunk);
+/// Rebuilds the view of planets whose look was edited, like when the solar system is reloaded
+pub fn on_planet_changed(
+    mut commands: Commands,
+    planets: Query<(Entity, Ref<Planet>, Has<CurrentPlanet>), Changed<Planet>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut standard_materials: ResMut<Assets<StandardMaterial>>,
+    mut planet_materials: ResMut<Assets<material::PlanetMaterial>>,
+) {
+    for (entity, planet, current) in planets.iter() {
+        // The focused planet gets its view when it is loaded
+        if planet.is_added() && current {
+            continue;
         }
+        if current {
+            spawn_chunks(
+                &mut commands,
+                entity,
+                &planet,
+                &mut meshes,
+                &mut planet_materials,
+            );
+        } else {
+            spawn_low_res_view(
+                &mut commands,
+                entity,
+                &planet,
+                &mut meshes,
+                &mut standard_materials,
+            );
+        }
+    }
+}
+
+/// Replaces the view of the planet with a plain sphere, for planets far from the camera
+fn spawn_low_res_view(
+    commands: &mut Commands,
+    planet: Entity,
+    planet_config: &Planet,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) {
+    let mesh = meshes.add(Sphere::new(planet_config.radius));
+    let low_res_view = commands
+        .spawn((
+            Mesh3d(mesh),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color: planet_config.color.into(),
+                unlit: true,
+                ..default()
+            })),
+        ))
+        .id();
+
+    commands
+        .get_entity(planet)
+        .unwrap()
+        .despawn_related::<Children>()
+        .add_child(low_res_view);
+}
 
-        let mut entity = commands.get_entity(entity).unwrap();
-        entity.despawn_related::<Children>();
-        for chunk in chunks {
-            entity.add_child(chunk);
+/// Replaces the view of the planet with the tessellated chunks of its surface
+fn spawn_chunks(
+    commands: &mut Commands,
+    entity: Entity,
+    planet: &Planet,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<material::PlanetMaterial>,
+) {
+    // Spawn all the chunks
+    let mut chunks = Vec::with_capacity(20);
+    for (v1, v2, v3) in ICOSAHEDRON_VERTEX_POSITIONS {
+        let mut vertices = vec![v1, v2, v3];
+        for vertex in &mut vertices {
+            for coord in vertex.iter_mut() {
+                *coord *= planet.radius;
+            }
         }
+        let mut indices = vec![];
+        let mut unused_indices = UnusedIndices::default();
+        let mut unused_vertices = UnusedVertices::default();
+        let mut vertex_rc = VertexRc::default();
+        let mut midpoint_cache = MidpointIndexCache::default();
+
+        let chunk = Chunk::new(
+            true,
+            1,
+            planet.radius,
+            [0, 1, 2],
+            &mut indices,
+            &mut vertices,
+            &mut unused_indices,
+            &mut unused_vertices,
+            &mut vertex_rc,
+            &mut midpoint_cache,
+        );
+
+        let mesh = Mesh::new(
+            bevy::render::mesh::PrimitiveTopology::TriangleList,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vertices)
+        .with_inserted_indices(Indices::U32(indices));
+
+        let chunk = commands
+            .spawn((
+                Mesh3d(meshes.add(mesh)),
+                MeshMaterial3d(materials.add(material::PlanetMaterial {
+                    data: PlanetUniforms::new(planet.radius, 8800.0),
+                    deep_water_color: planet.deep_water_color,
+                    water_color: planet.water_color,
+                    sand_color: planet.sand_color,
+                    grass_color: planet.grass_color,
+                    mountain_color: planet.mountains_color,
+                    snow_color: planet.snow_color,
+                })),
+                Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)),
+                PlanetViewBundle {
+                    chunk,
+                    midpoint_cache,
+                    unused_indices,
+                    unused_vertices,
+                    vertex_rc,
+                },
+            ))
+            .id();
+        chunks.push(chunk);
+    }
+
+    let mut entity = commands.get_entity(entity).unwrap();
+    entity.despawn_related::<Children>();
+    for chunk in chunks {
+        entity.add_child(chunk);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn appearance() -> Appearance {
+        Appearance(ron::from_str("(radius: 10.0, color: (0.2, 0.4, 0.8))").unwrap())
+    }
+
+    #[test]
+    fn single_focused_planet() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_systems(Update, (update_planet_views, focus_active_planet).chain());
+        let world = app.world_mut();
+        let active = world
+            .spawn((Body::new(1.0e24), appearance(), ActiveBody))
+            .id();
+        let planet = world.spawn((Body::new(1.0e24), appearance())).id();
+        let barycenter = world.spawn(Body::new(1.0e24)).id();
+        app.update();
+
+        let world = app.world_mut();
+        assert!(world.get::<CurrentPlanet>(active).is_some());
+        assert!(world.get::<CurrentPlanet>(planet).is_none());
+        assert!(world.get::<Planet>(planet).is_some());
+        assert!(world.get::<Planet>(barycenter).is_none());
+        assert!(world.get::<Transform>(barycenter).is_some());
+
+        // Like a reload adding another active body
+        let new_active = world
+            .spawn((Body::new(1.0e24), appearance(), ActiveBody))
+            .id();
+        world.entity_mut(planet).remove::<Appearance>();
+        app.update();
+
+        let world = app.world_mut();
+        assert!(world.get::<CurrentPlanet>(new_active).is_none());
+        assert!(world.get::<Planet>(planet).is_none());
+        let mut focused = world.query_filtered::<Entity, With<CurrentPlanet>>();
+        assert_eq!(focused.iter(world).collect::<Vec<_>>(), vec![active]);
     }
 }
@@ -0,0 +1,98 @@
+use bevy::{input::common_conditions::input_just_pressed, prelude::*};
+use orbits::{Attitude, Orbit};
+
+use crate::gameplay::{CurrentShip, Ship};
+use crate::render::{CameraCenter, CameraUp, MainCamera, OrbitDistance};
+
+pub struct ShipViewPlugin;
+
+impl Plugin for ShipViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_state(CameraMode::Map).add_systems(
+            Update,
+            (
+                spawn_ship_views,
+                update_ship_rotations,
+                update_ship_camera_orbit.run_if(in_state(CameraMode::Close)),
+                togle_camera_mode.run_if(input_just_pressed(KeyCode::KeyM)),
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Gives a mesh to the ships spawned by the simulation
+fn spawn_ship_views(
+    mut commands: Commands,
+    ships: Query<Entity, Added<Ship>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for ship in ships.iter() {
+        let mesh = meshes.add(Cuboid::new(10.0, 10.0, 20.0));
+        let material = materials.add(StandardMaterial::from_color(Color::srgb_u8(128, 0, 128)));
+        commands
+            .entity(ship)
+            .insert((Mesh3d(mesh), MeshMaterial3d(material)));
+    }
+}
+
+/// Turns the ship meshes to the attitude of the ships
+fn update_ship_rotations(mut ships: Query<(&Attitude, &mut Transform), With<Ship>>) {
+    for (attitude, mut transform) in ships.iter_mut() {
+        let orientation = attitude.orientation();
+        transform.rotation = Quat::from_xyzw(
+            orientation.i as f32,
+            orientation.j as f32,
+            orientation.k as f32,
+            orientation.w as f32,
+        );
+    }
+}
+
+/// Keeps the close camera on the current ship, it follows the ship when switching
+fn update_ship_camera_orbit(
+    query: Query<&Orbit, With<CurrentShip>>,
+    mut camera_center: ResMut<CameraCenter>,
+    mut camera_up: ResMut<CameraUp>,
+) {
+    let Ok(orbit) = query.single() else {
+        return;
+    };
+    let ship_position = orbit.position();
+    camera_center.0.x = ship_position.x as f32;
+    camera_center.0.y = ship_position.y as f32;
+    camera_center.0.z = ship_position.z as f32;
+    camera_up.0 = camera_center.0.normalize();
+}
+
+#[derive(States, Debug, Hash, Eq, PartialEq, Clone)]
+pub(super) enum CameraMode {
+    Map,
+    Close,
+}
+
+fn togle_camera_mode(
+    state: Res<State<CameraMode>>,
+    mut next_state: ResMut<NextState<CameraMode>>,
+    mut orbit_distance: Query<&mut OrbitDistance, With<MainCamera>>,
+    mut camera_center: ResMut<CameraCenter>,
+    mut camera_up: ResMut<CameraUp>,
+) {
+    match state.get() {
+        CameraMode::Map => {
+            // TODO: Make this dependant on planet size and also cache the old positions
+            orbit_distance.single_mut().unwrap().0 = 50.;
+
+            next_state.set(CameraMode::Close);
+        }
+        CameraMode::Close => {
+            // TODO: Make this dependant on planet size and also cache the old positions
+            orbit_distance.single_mut().unwrap().0 = 10000000.;
+            camera_center.0 = Vec3::ZERO;
+            camera_up.0 = Vec3::Y;
+
+            next_state.set(CameraMode::Map);
+        }
+    }
+}
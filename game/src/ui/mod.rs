@@ -1,4 +1,5 @@
 mod time;
+mod vessel;
 
 use bevy::prelude::*;
 use bevy_egui::{EguiContexts, EguiPrimaryContextPass};
@@ -7,8 +8,16 @@ pub struct UiPlugin;
 
 impl Plugin for UiPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, init)
-            .add_systems(EguiPrimaryContextPass, time::time_ui);
+        app.add_systems(Startup, init).add_systems(
+            EguiPrimaryContextPass,
+            (
+                time::time_ui,
+                time::warp_to_ui,
+                time::history_ui,
+                vessel::vessel_ui,
+                vessel::vessel_list_ui,
+            ),
+        );
     }
 }
 
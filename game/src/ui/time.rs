@@ -3,7 +3,12 @@ use bevy_egui::{
     EguiContexts,
     egui::{self, Vec2},
 };
-use orbits::TimeSpeed;
+use orbits::{
+    History, Rewind, SimulationClock, TimeSpeed, TimeWarpReduced, WarpEvent, WarpTo,
+    julian_date_from_utc,
+};
+
+use crate::gameplay::CurrentShip;
 
 const WARPS_AMMOUNT: usize = 14;
 const WAPRPS: [f64; WARPS_AMMOUNT] = [
@@ -11,16 +16,33 @@ const WAPRPS: [f64; WARPS_AMMOUNT] = [
 ];
 
 const DEFAULT_WARP_IDX: usize = 9;
+/// Real seconds the reason of the last warp reduction stays on screen
+const WARP_REDUCTION_MESSAGE_SECONDS: f64 = 5.0;
 
 pub fn time_ui(
     mut egui_context: EguiContexts,
     mut time_speed: ResMut<TimeSpeed>,
-    mut enabled: Local<Option<usize>>,
+    mut clock: ResMut<SimulationClock>,
+    mut initialized: Local<bool>,
+    mut warp_reductions: EventReader<TimeWarpReduced>,
+    mut last_reduction: Local<Option<(String, f64)>>,
+    time: Res<Time>,
 ) {
-    let enabled = enabled.get_or_insert_with(|| {
+    if !*initialized {
         time_speed.0 = WAPRPS[DEFAULT_WARP_IDX];
-        DEFAULT_WARP_IDX
-    });
+        *initialized = true;
+    }
+    // The speed is also changed by the warp policy, warps to events and loaded games
+    let enabled = WAPRPS
+        .iter()
+        .rposition(|warp| *warp <= time_speed.0)
+        .unwrap_or(0);
+    if let Some(reduction) = warp_reductions.read().last() {
+        *last_reduction = Some((
+            format!("Warp reduced to {:.0}x: {}", reduction.to, reduction.reason),
+            time.elapsed_secs_f64(),
+        ));
+    }
 
     let ctx = egui_context.ctx_mut().expect("Could not get egui context");
     egui::Area::new(egui::Id::new("time"))
@@ -33,8 +55,12 @@ pub fn time_ui(
             egui::Image::new("https://space-game.asempere.net/assets/ui/time_background.png")
                 .paint_at(ui, ui.max_rect().expand2(Vec2::new(10.0, 5.0)));
             ui.horizontal(|ui| {
+                let pause_label = if clock.is_paused() { "▶" } else { "⏸" };
+                if ui.button(pause_label).clicked() {
+                    clock.toggle_pause();
+                }
                 for (i, warp) in WAPRPS.iter().enumerate() {
-                    let img_path = if *enabled == i {
+                    let img_path = if enabled == i {
                         if cfg!(target_arch = "wasm32") {
                             "https://space-game.asempere.net/assets/ui/timewarp_arrow_on.png"
                         } else {
@@ -50,10 +76,119 @@ pub fn time_ui(
                     let img = egui::Image::new(img_path).max_size(Vec2::new(20.0, 20.0));
                     let button = egui::ImageButton::new(img).frame(false);
                     if ui.add(button).clicked() {
-                        *enabled = i;
                         time_speed.0 = *warp;
+                        clock.cancel_warp();
+                    }
+                }
+                ui.label(clock.utc());
+            });
+            let recent_reduction = last_reduction.as_ref().filter(|(_, shown_at)| {
+                time.elapsed_secs_f64() - shown_at < WARP_REDUCTION_MESSAGE_SECONDS
+            });
+            if let Some((message, _)) = recent_reduction {
+                ui.label(message);
+            }
+        });
+}
+
+/// Buttons to warp the current ship to the next event of its orbit, or to a date
+pub fn warp_to_ui(
+    mut egui_context: EguiContexts,
+    ship: Query<Entity, With<CurrentShip>>,
+    clock: Res<SimulationClock>,
+    mut warp_to: EventWriter<WarpTo>,
+    mut date: Local<String>,
+) {
+    let ctx = egui_context.ctx_mut().expect("Could not get egui context");
+    egui::Area::new(egui::Id::new("warp_to"))
+        .fixed_pos((10.0, 60.0))
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Warp to");
+                if let Ok(ship) = ship.single() {
+                    for (label, event) in [
+                        ("Pe", WarpEvent::Periapsis(ship)),
+                        ("Ap", WarpEvent::Apoapsis(ship)),
+                        ("AN", WarpEvent::AscendingNode(ship)),
+                        ("SOI", WarpEvent::SphereOfInfluence(ship)),
+                        ("Node", WarpEvent::Maneuver(ship)),
+                    ] {
+                        if ui.button(label).clicked() {
+                            warp_to.write(WarpTo(event));
+                        }
+                    }
+                }
+
+                if date.is_empty() {
+                    *date = clock.utc()[..10].to_string();
+                }
+                ui.add(egui::TextEdit::singleline(&mut *date).desired_width(80.0));
+                if ui.button("Date").clicked() {
+                    match parse_date(&date) {
+                        Some(julian_date) => {
+                            warp_to.write(WarpTo(WarpEvent::Date(julian_date)));
+                        }
+                        None => warn!("Could not parse date {}, expected YYYY-MM-DD", *date),
                     }
                 }
-            })
+            });
+        });
+}
+
+/// Julian date of the start of a `YYYY-MM-DD` day
+fn parse_date(date: &str) -> Option<f64> {
+    let mut parts = date.trim().splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts
+        .next()?
+        .parse()
+        .ok()
+        .filter(|month| (1..=12).contains(month))?;
+    let day = parts
+        .next()?
+        .parse()
+        .ok()
+        .filter(|day| (1..=days_in_month(year, month)).contains(day))?;
+    Some(julian_date_from_utc(year, month, day, 0, 0, 0.0))
+}
+
+/// Gregorian calendar
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Slider over the recorded snapshots, moving it rewinds the simulation to that moment
+pub fn history_ui(
+    mut egui_context: EguiContexts,
+    history: Res<History>,
+    clock: Res<SimulationClock>,
+    mut rewind: EventWriter<Rewind>,
+) {
+    if history.is_empty() {
+        return;
+    }
+
+    let ctx = egui_context.ctx_mut().expect("Could not get egui context");
+    egui::Area::new(egui::Id::new("history"))
+        .fixed_pos((10.0, 90.0))
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let latest = history.len() - 1;
+                let mut selected = history.cursor().unwrap_or(latest);
+                let slider = egui::Slider::new(&mut selected, 0..=latest)
+                    .show_value(false)
+                    .text("History");
+                if ui.add(slider).changed() {
+                    rewind.write(Rewind(selected));
+                }
+                if let Some(snapshot) = history.get(selected) {
+                    ui.label(clock.utc_at(snapshot.epoch));
+                }
+            });
         });
 }
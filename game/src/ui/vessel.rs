@@ -0,0 +1,87 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+use orbits::{Frame, Orbit, StabilityAssist, Vessel};
+
+use crate::gameplay::{CurrentShip, Ship, SwitchShip};
+
+/// Stability assist modes that can be picked from the buttons
+const ASSIST_MODES: [(&str, StabilityAssist); 9] = [
+    ("Off", StabilityAssist::Off),
+    ("SAS", StabilityAssist::Stability),
+    ("Pro", StabilityAssist::Prograde),
+    ("Retro", StabilityAssist::Retrograde),
+    ("Nml", StabilityAssist::Normal),
+    ("Anti", StabilityAssist::AntiNormal),
+    ("Rad+", StabilityAssist::RadialOut),
+    ("Rad-", StabilityAssist::RadialIn),
+    ("Node", StabilityAssist::Maneuver),
+];
+
+/// Throttle and stability assist of the current ship along with its propellant and remaining
+/// delta-v
+pub fn vessel_ui(
+    mut egui_context: EguiContexts,
+    mut ship: Query<(&mut Vessel, Option<&mut StabilityAssist>), With<CurrentShip>>,
+) {
+    let Ok((mut vessel, assist)) = ship.single_mut() else {
+        return;
+    };
+
+    let ctx = egui_context.ctx_mut().expect("Could not get egui context");
+    egui::Area::new(egui::Id::new("vessel"))
+        .fixed_pos((10.0, 120.0))
+        .show(ctx, |ui| {
+            let mut throttle = vessel.throttle();
+            let slider = egui::Slider::new(&mut throttle, 0.0..=1.0).text("Throttle");
+            if ui.add(slider).changed()
+                && let Err(error) = vessel.set_throttle(throttle)
+            {
+                warn!("Could not set the throttle: {error}");
+            }
+            ui.label(format!(
+                "Mass {:.0} kg, propellant {:.0} kg",
+                vessel.mass(),
+                vessel.propellant_mass()
+            ));
+            ui.label(format!("Δv {:.1} m/s", vessel.delta_v()));
+            if vessel.is_burning() {
+                ui.label(format!("Burn time {:.0} s", vessel.burn_time()));
+            }
+            if let Some(mut assist) = assist {
+                ui.horizontal(|ui| {
+                    for (label, mode) in ASSIST_MODES {
+                        if ui.selectable_label(*assist == mode, label).clicked() {
+                            *assist = mode;
+                        }
+                    }
+                });
+            }
+        });
+}
+
+/// List of every ship, clicking one makes it the current ship
+pub fn vessel_list_ui(
+    mut egui_context: EguiContexts,
+    ships: Query<(Entity, &Name, &Orbit, Has<CurrentShip>), With<Ship>>,
+    mut switch: EventWriter<SwitchShip>,
+) {
+    let mut ships: Vec<_> = ships.iter().collect();
+    ships.sort_by(|(a, a_name, ..), (b, b_name, ..)| a_name.cmp(b_name).then(a.cmp(b)));
+
+    let ctx = egui_context.ctx_mut().expect("Could not get egui context");
+    egui::Area::new(egui::Id::new("vessel_list"))
+        .anchor(egui::Align2::RIGHT_TOP, (-10.0, 5.0))
+        .show(ctx, |ui| {
+            ui.label("Vessels [ ]");
+            for (ship, name, orbit, current) in ships {
+                let frame = match orbit.frame() {
+                    Frame::Free(_) => "free",
+                    Frame::Orbit { .. } => "rails",
+                };
+                let label = format!("{name} ({frame})");
+                if ui.selectable_label(current, label).clicked() && !current {
+                    switch.write(SwitchShip::To(ship));
+                }
+            }
+        });
+}
@@ -0,0 +1,67 @@
+//! Measures how long a frame of orbit propagation takes as the amount of on-rails objects grows.
+//! Run with `cargo run --release -p orbits --example propagation_benchmark`
+
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use orbits::{Body, Orbit, OrbitPlugin, OrbitsAround, SimulationClock, TimeSpeed};
+
+const OBJECT_COUNTS: [usize; 4] = [100, 1_000, 5_000, 10_000];
+const WARMUP_FRAMES: u32 = 10;
+const MEASURED_FRAMES: u32 = 200;
+
+fn main() {
+    println!(
+        "{:>8} {:>14} {:>18}",
+        "objects", "frame (us)", "per object (ns)"
+    );
+    for count in OBJECT_COUNTS {
+        let frame = measure(count);
+        println!(
+            "{:>8} {:>14.1} {:>18.1}",
+            count,
+            frame.as_secs_f64() * 1e6,
+            frame.as_secs_f64() * 1e9 / count as f64
+        );
+    }
+}
+
+/// Average duration of a frame with `count` asteroids orbiting a sun
+fn measure(count: usize) -> Duration {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, OrbitPlugin))
+        .insert_resource(TimeSpeed(1.0e6));
+
+    let sun_body = Body::new(1.989e30);
+    let sun = app.world_mut().spawn(sun_body).id();
+
+    // Deterministic spread of asteroid belt like orbits
+    let asteroids = (0..count)
+        .map(|i| {
+            let t = i as f64 / count as f64;
+            let orbit = Orbit::new_orbit(
+                3.0e11 + 2.0e11 * t,
+                0.3 * ((i * 7919) % 1000) as f64 / 1000.0,
+                std::f64::consts::TAU * t,
+                0.2 * ((i * 104729) % 1000) as f64 / 1000.0,
+                std::f64::consts::TAU * ((i * 31) % 97) as f64 / 97.0,
+                &sun_body,
+                &SimulationClock::default(),
+                0.0,
+            )
+            .expect("Benchmark orbits should be valid");
+            (orbit, OrbitsAround(sun))
+        })
+        .collect::<Vec<_>>();
+    app.world_mut().spawn_batch(asteroids);
+
+    for _ in 0..WARMUP_FRAMES {
+        app.update();
+    }
+
+    let start = Instant::now();
+    for _ in 0..MEASURED_FRAMES {
+        app.update();
+    }
+    start.elapsed() / MEASURED_FRAMES
+}
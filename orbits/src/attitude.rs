@@ -0,0 +1,418 @@
+use bevy::prelude::*;
+use nalgebra::{UnitQuaternion, Vector3};
+
+use crate::{AbsolutePosition, DeltaTime, ManeuverNode, Orbit, Vessel, VesselError};
+
+/// Longest step used to integrate the rotation, longer frames are split in substeps
+const MAX_ATTITUDE_STEP: f64 = 0.05;
+/// Frames that would need more substeps than this are too warped to simulate the rotation,
+/// stability assist snaps to its direction and free rotation keeps its angular velocity
+const MAX_ATTITUDE_SUBSTEPS: usize = 100;
+/// How fast stability assist turns towards its direction, in rad/s
+const STABILITY_ASSIST_FREQUENCY: f64 = 1.0;
+
+/// Orientation and rotation of a rigid object.
+/// Body axes: +Z is the nose, where the engine pushes, +Y is up and +X is right.
+/// Pitch turns around X, yaw around Y and roll around Z.
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq)]
+pub struct Attitude {
+    /// Rotation from body axes to world axes
+    #[reflect(ignore)]
+    orientation: UnitQuaternion<f64>,
+    /// In body axes, rad/s
+    #[reflect(ignore)]
+    angular_velocity: Vector3<f64>,
+    /// Principal moments of inertia around the body axes, kg·m²
+    #[reflect(ignore)]
+    moment_of_inertia: Vector3<f64>,
+    /// Largest torque of the reaction wheels and RCS around each body axis, N·m
+    #[reflect(ignore)]
+    max_torque: Vector3<f64>,
+    /// Pilot input around each body axis, from -1 to 1
+    #[reflect(ignore)]
+    control: Vector3<f64>,
+}
+
+impl Attitude {
+    /// Object at rest with its nose along the world's Z axis
+    pub fn new(
+        moment_of_inertia: Vector3<f64>,
+        max_torque: Vector3<f64>,
+    ) -> Result<Self, VesselError> {
+        if !moment_of_inertia
+            .iter()
+            .all(|inertia| inertia.is_finite() && *inertia > 0.0)
+        {
+            return Err(VesselError::InvalidMomentOfInertia);
+        }
+        if !max_torque
+            .iter()
+            .all(|torque| torque.is_finite() && *torque >= 0.0)
+        {
+            return Err(VesselError::InvalidTorque);
+        }
+
+        Ok(Self {
+            orientation: UnitQuaternion::identity(),
+            angular_velocity: Vector3::zeros(),
+            moment_of_inertia,
+            max_torque,
+            control: Vector3::zeros(),
+        })
+    }
+
+    pub fn with_orientation(mut self, orientation: UnitQuaternion<f64>) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    /// In body axes
+    pub fn with_angular_velocity(mut self, angular_velocity: Vector3<f64>) -> Self {
+        self.angular_velocity = angular_velocity;
+        self
+    }
+
+    pub fn orientation(&self) -> UnitQuaternion<f64> {
+        self.orientation
+    }
+
+    /// In body axes
+    pub fn angular_velocity(&self) -> Vector3<f64> {
+        self.angular_velocity
+    }
+
+    pub fn moment_of_inertia(&self) -> Vector3<f64> {
+        self.moment_of_inertia
+    }
+
+    pub fn max_torque(&self) -> Vector3<f64> {
+        self.max_torque
+    }
+
+    /// Direction of the nose in world axes
+    pub fn forward(&self) -> Vector3<f64> {
+        self.orientation * Vector3::z()
+    }
+
+    /// Direction of the top in world axes
+    pub fn up(&self) -> Vector3<f64> {
+        self.orientation * Vector3::y()
+    }
+
+    pub fn control(&self) -> Vector3<f64> {
+        self.control
+    }
+
+    /// Pilot input, each axis is clamped between -1 and 1. It overrides stability assist on the
+    /// axes where it is not zero.
+    pub fn set_control(&mut self, pitch: f64, yaw: f64, roll: f64) {
+        self.control = Vector3::new(pitch, yaw, roll).map(|input| {
+            if input.is_finite() {
+                input.clamp(-1.0, 1.0)
+            } else {
+                0.0
+            }
+        });
+    }
+
+    /// Integrates Euler's rotation equations for the given torque in body axes
+    fn step(&mut self, torque: Vector3<f64>, seconds: f64) {
+        let inertia = self.moment_of_inertia;
+        let angular_momentum = inertia.component_mul(&self.angular_velocity);
+        let angular_acceleration =
+            (torque - self.angular_velocity.cross(&angular_momentum)).component_div(&inertia);
+        self.angular_velocity += angular_acceleration * seconds;
+        self.orientation *= UnitQuaternion::from_scaled_axis(self.angular_velocity * seconds);
+    }
+
+    /// Torque in body axes that turns the nose towards `direction` and damps the rotation.
+    /// Without a direction it only damps the rotation.
+    fn assist_torque(&self, direction: Option<Vector3<f64>>) -> Vector3<f64> {
+        let error = direction
+            .and_then(|direction| direction.try_normalize(0.0))
+            .map(|direction| {
+                // Rotation that takes the nose to the direction, in body axes
+                let target = self.orientation.inverse() * direction;
+                UnitQuaternion::rotation_between(&Vector3::z(), &target)
+                    .map(|rotation| rotation.scaled_axis())
+                    // Pointing backwards, any perpendicular axis works
+                    .unwrap_or(Vector3::x() * std::f64::consts::PI)
+            })
+            .unwrap_or_default();
+        let acceleration = error * STABILITY_ASSIST_FREQUENCY.powi(2)
+            - self.angular_velocity * 2.0 * STABILITY_ASSIST_FREQUENCY;
+        self.moment_of_inertia.component_mul(&acceleration)
+    }
+
+    /// Torque in body axes for the pilot input, with stability assist on the idle axes
+    fn torque(&self, assist: Option<Vector3<f64>>) -> Vector3<f64> {
+        let mut torque = self.control.component_mul(&self.max_torque);
+        if let Some(assist) = assist {
+            for axis in 0..3 {
+                if self.control[axis] == 0.0 {
+                    torque[axis] =
+                        assist[axis].clamp(-self.max_torque[axis], self.max_torque[axis]);
+                }
+            }
+        }
+        torque
+    }
+}
+
+impl Default for Attitude {
+    /// Unit inertia and no torque, so it keeps spinning as it is
+    fn default() -> Self {
+        Self {
+            orientation: UnitQuaternion::identity(),
+            angular_velocity: Vector3::zeros(),
+            moment_of_inertia: Vector3::repeat(1.0),
+            max_torque: Vector3::zeros(),
+            control: Vector3::zeros(),
+        }
+    }
+}
+
+/// Direction held by the stability assist, relative to the object's `Orbit`
+#[derive(Component, Reflect, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[require(Attitude)]
+pub enum StabilityAssist {
+    /// Rotation is only controlled by the pilot
+    #[default]
+    Off,
+    /// Damps any rotation
+    Stability,
+    /// Along the velocity relative to the parent
+    Prograde,
+    Retrograde,
+    /// Perpendicular to the orbital plane, along the angular momentum
+    Normal,
+    AntiNormal,
+    /// Away from the parent, perpendicular to the velocity in the orbital plane
+    RadialOut,
+    RadialIn,
+    /// Towards another entity
+    Target(Entity),
+    /// Along the change of velocity of the `ManeuverNode` of the object
+    Maneuver,
+}
+
+impl StabilityAssist {
+    /// Direction in world axes that should be held, `None` if it only damps the rotation or it
+    /// is not defined for the current state
+    pub fn direction(
+        &self,
+        orbit: &Orbit,
+        position: Vector3<f64>,
+        target_position: Option<Vector3<f64>>,
+        maneuver: Option<&ManeuverNode>,
+    ) -> Option<Vector3<f64>> {
+        let state = orbit.state_vectors();
+        let prograde = state.velocity.try_normalize(0.0);
+        let normal = state.position.cross(&state.velocity).try_normalize(0.0);
+        let radial = prograde
+            .zip(normal)
+            .map(|(prograde, normal)| prograde.cross(&normal));
+        match self {
+            Self::Off | Self::Stability => None,
+            Self::Prograde => prograde,
+            Self::Retrograde => prograde.map(|prograde| -prograde),
+            Self::Normal => normal,
+            Self::AntiNormal => normal.map(|normal| -normal),
+            Self::RadialOut => radial,
+            Self::RadialIn => radial.map(|radial| -radial),
+            Self::Target(_) => target_position.map(|target| target - position),
+            Self::Maneuver => maneuver.map(|maneuver| maneuver.delta_v),
+        }
+    }
+}
+
+type RotatingObject = (
+    &'static mut Attitude,
+    Option<&'static StabilityAssist>,
+    Option<&'static Orbit>,
+    Option<&'static AbsolutePosition>,
+    Option<&'static ManeuverNode>,
+    Option<&'static mut Vessel>,
+);
+
+/// Rotates every object with an `Attitude` and points the engines of vessels along their nose.
+/// Runs before propagation so burns use the new orientation.
+pub fn update_attitudes(
+    mut objects: Query<RotatingObject>,
+    positions: Query<&AbsolutePosition>,
+    delta_time: Res<DeltaTime>,
+) {
+    let seconds = delta_time.seconds();
+    if seconds <= 0.0 {
+        return;
+    }
+    let substeps = (seconds / MAX_ATTITUDE_STEP).ceil() as usize;
+
+    for (mut attitude, assist, orbit, position, maneuver, vessel) in objects.iter_mut() {
+        let assist = assist.copied().unwrap_or_default();
+        let direction = match (assist, orbit) {
+            (StabilityAssist::Off, _) | (_, None) => None,
+            (_, Some(orbit)) => {
+                let target = match assist {
+                    StabilityAssist::Target(target) => positions.get(target).ok(),
+                    _ => None,
+                };
+                assist.direction(
+                    orbit,
+                    position.map(|position| position.0).unwrap_or_default(),
+                    target.map(|target| target.0),
+                    maneuver,
+                )
+            }
+        };
+
+        if substeps > MAX_ATTITUDE_SUBSTEPS {
+            // Warping: hold the direction exactly or keep spinning
+            if let Some(direction) = direction {
+                let target = direction.normalize();
+                let rotation = UnitQuaternion::rotation_between(&attitude.forward(), &target)
+                    .unwrap_or_else(|| {
+                        UnitQuaternion::from_axis_angle(
+                            &nalgebra::Unit::new_normalize(attitude.up()),
+                            std::f64::consts::PI,
+                        )
+                    });
+                attitude.orientation = rotation * attitude.orientation;
+                attitude.angular_velocity = Vector3::zeros();
+            } else if assist != StabilityAssist::Off {
+                attitude.angular_velocity = Vector3::zeros();
+            } else {
+                let angular_velocity = attitude.angular_velocity;
+                attitude.orientation *=
+                    UnitQuaternion::from_scaled_axis(angular_velocity * seconds);
+            }
+        } else {
+            let step = seconds / substeps as f64;
+            for _ in 0..substeps {
+                let assist =
+                    (assist != StabilityAssist::Off).then(|| attitude.assist_torque(direction));
+                let torque = attitude.torque(assist);
+                attitude.step(torque, step);
+            }
+        }
+
+        if let Some(mut vessel) = vessel
+            && let Err(error) = vessel.set_thrust_direction(attitude.forward())
+        {
+            error!("Could not point the engine: {error}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::{Body, OrbitPlugin, OrbitsAround, SimulationClock, TimeSpeed};
+
+    fn app(frame_millis: u64) -> (App, Entity, Body) {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, OrbitPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                frame_millis,
+            )));
+        let earth_body = Body::new(5.97219e24).with_radius(6.371e6);
+        let earth = app.world_mut().spawn(earth_body).id();
+        (app, earth, earth_body)
+    }
+
+    fn attitude() -> Attitude {
+        Attitude::new(Vector3::new(1.25e5, 1.25e5, 5.0e4), Vector3::repeat(5.0e4)).unwrap()
+    }
+
+    #[test]
+    fn constant_torque() {
+        let mut attitude = attitude();
+        attitude.set_control(0.0, 0.0, 2.0);
+        for _ in 0..100 {
+            let torque = attitude.torque(None);
+            attitude.step(torque, 0.01);
+        }
+        // 1 rad/s² around the roll axis for one second
+        assert!((attitude.angular_velocity() - Vector3::new(0.0, 0.0, 1.0)).magnitude() < 1e-9);
+        // Rolling keeps the nose in place
+        assert!((attitude.forward() - Vector3::z()).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn holds_prograde_and_points_the_engine() {
+        let (mut app, earth, earth_body) = app(50);
+        let orbit = Orbit::new_free(
+            Vector3::new(7.0e6, 0.0, 0.0),
+            Vector3::new(0.0, 7.5e3, 0.0),
+            &earth_body,
+        )
+        .unwrap();
+        let ship = app
+            .world_mut()
+            .spawn((
+                orbit,
+                OrbitsAround(earth),
+                attitude(),
+                StabilityAssist::Prograde,
+                Vessel::new(1000.0, 100.0, 10.0, 300.0).unwrap(),
+            ))
+            .id();
+
+        // Twenty seconds
+        for _ in 0..400 {
+            app.update();
+        }
+
+        let prograde = app
+            .world()
+            .get::<Orbit>(ship)
+            .unwrap()
+            .state_vectors()
+            .velocity
+            .normalize();
+        let attitude = app.world().get::<Attitude>(ship).unwrap();
+        assert!(attitude.forward().angle(&prograde) < 1.0_f64.to_radians());
+        assert!(attitude.angular_velocity().magnitude() < 1e-2);
+        let vessel = app.world().get::<Vessel>(ship).unwrap();
+        assert!((vessel.thrust_direction() - attitude.forward()).magnitude() < 1e-12);
+    }
+
+    #[test]
+    fn snaps_under_warp() {
+        let (mut app, earth, earth_body) = app(100);
+        app.insert_resource(TimeSpeed(1000.0));
+        let orbit = Orbit::new_orbit(
+            7.0e6,
+            0.0,
+            0.0,
+            0.3,
+            0.0,
+            &earth_body,
+            &SimulationClock::default(),
+            0.0,
+        )
+        .unwrap();
+        let ship = app
+            .world_mut()
+            .spawn((
+                orbit,
+                OrbitsAround(earth),
+                attitude(),
+                StabilityAssist::Normal,
+            ))
+            .id();
+
+        app.update();
+        app.update();
+
+        let state = app.world().get::<Orbit>(ship).unwrap().state_vectors();
+        let normal = state.position.cross(&state.velocity).normalize();
+        let forward = app.world().get::<Attitude>(ship).unwrap().forward();
+        // Held at the start of the frame, the plane does not change on rails
+        assert!(forward.angle(&normal) < 1e-9);
+    }
+}
@@ -1,391 +1,574 @@
-use std::sync::{Arc, RwLock};
-
-use crate::{Body, Frame, Orbit};
+use crate::{
+    Body, ClassicalElements, EquinoctialElements, Frame, Orbit, OrbitError, SimulationClock,
+    StateVectors, equinoctial::world_from_reference,
+};
 
+use nalgebra::{Rotation3, Vector3};
 use std::f64::consts::PI;
 
-const ECCENTRIC_ANOMALY_TOLERANCE: f64 = 1e-6;
-const ECCENTRIC_ANOMALY_MAX_ITERATIONS: u32 = 100;
+/// Angles closer than this are considered the same point of the orbit
+const ANOMALY_TOLERANCE: f64 = 1e-9;
+/// Orbits with tan(i / 2) below this are considered equatorial, smaller values come from
+/// round-off when converting state vectors
+const EQUATORIAL_TOLERANCE: f64 = 1e-12;
 
 impl Orbit {
     pub fn new_free(
-        x: f64,
-        y: f64,
-        z: f64,
-        vx: f64,
-        vy: f64,
-        vz: f64,
-        parent: Arc<RwLock<Body>>,
-    ) -> Self {
-        Self {
-            x,
-            y,
-            z,
-            vx: Some(vx),
-            vy: Some(vy),
-            vz: Some(vz),
-            velocity: (vx.powi(2) + vy.powi(2) + vz.powi(2)).sqrt(),
-
-            semimajor_axis: None,
-            eccentricity: None,
-            argument_of_periapsis: None,
-            inclination: None,
-            longitude_of_ascending_node: None,
-
-            mean_movement: None,
-
-            current_mean_anomaly: 0.0,
-            current_eccentric_anomaly: 0.0,
-            radius: 0.0,
-            frame: Frame::Free,
-            epoch: 0.0,
-            parent: parent,
+        position: Vector3<f64>,
+        velocity: Vector3<f64>,
+        parent: &Body,
+    ) -> Result<Self, OrbitError> {
+        finite_vector(position, "position")?;
+        finite_vector(velocity, "velocity")?;
+        validate_parent(parent)?;
+        if position == Vector3::zeros() {
+            return Err(OrbitError::ZeroRadius);
         }
+
+        Ok(Self {
+            frame: Frame::Free(StateVectors { position, velocity }),
+            orbital_plane_rotation: Rotation3::identity(),
+            parent_gravitational_parameter: parent.standard_gravitational_parameter,
+            gravitational_parameter_factor: 1.0,
+            epoch: 0.0,
+        })
     }
 
+    /// The object is at the periapsis on the starting epoch, measured in seconds of the clock, and
+    /// is moved along the orbit up to the clock's current time.
     pub fn new_orbit(
         semimajor_axis: f64,
         eccentricity: f64,
         argument_of_periapsis: f64,
         inclination: f64,
         longitude_of_ascending_node: f64,
-        parent: Arc<RwLock<Body>>,
+        parent: &Body,
+        clock: &SimulationClock,
+        starting_epoch: f64,
+    ) -> Result<Self, OrbitError> {
+        Self::new_orbit_at(
+            semimajor_axis,
+            eccentricity,
+            argument_of_periapsis,
+            inclination,
+            longitude_of_ascending_node,
+            parent,
+            clock.seconds(),
+            starting_epoch,
+        )
+    }
+
+    fn new_orbit_at(
+        semimajor_axis: f64,
+        eccentricity: f64,
+        argument_of_periapsis: f64,
+        inclination: f64,
+        longitude_of_ascending_node: f64,
+        parent: &Body,
         current_epoch: f64,
         starting_epoch: f64,
-    ) -> Self {
+    ) -> Result<Self, OrbitError> {
+        finite(semimajor_axis, "semimajor axis")?;
+        finite(eccentricity, "eccentricity")?;
+        finite(argument_of_periapsis, "argument of periapsis")?;
+        finite(inclination, "inclination")?;
+        finite(longitude_of_ascending_node, "longitude of ascending node")?;
+        finite(current_epoch, "current epoch")?;
+        finite(starting_epoch, "starting epoch")?;
+        validate_parent(parent)?;
+        validate_eccentricity(eccentricity)?;
+        if semimajor_axis <= 0.0 {
+            return Err(OrbitError::InvalidSemimajorAxis(semimajor_axis));
+        }
+
+        // Starts at the periapsis on the starting epoch
+        let elements = EquinoctialElements::from_classical(&ClassicalElements {
+            semimajor_axis,
+            eccentricity,
+            inclination,
+            longitude_of_ascending_node,
+            argument_of_periapsis,
+            true_anomaly: 0.0,
+        })?;
+
         let mut orbit = Self {
-            x: 0.0,
-            y: 0.0,
-            z: 0.0,
-            vx: None,
-            vy: None,
-            vz: None,
-            velocity: 0.0,
-
-            semimajor_axis: Some(semimajor_axis),
-            eccentricity: Some(eccentricity),
-            argument_of_periapsis: Some(argument_of_periapsis),
-            inclination: Some(inclination),
-            longitude_of_ascending_node: Some(longitude_of_ascending_node),
-
-            mean_movement: Some(mean_movement(semimajor_axis, &parent)),
-
-            current_mean_anomaly: 0.0,
-            current_eccentric_anomaly: 0.0,
-            radius: 0.0,
-            frame: Frame::Orbit,
+            frame: Frame::Free(StateVectors::default()),
+            orbital_plane_rotation: Rotation3::identity(),
+            parent_gravitational_parameter: parent.standard_gravitational_parameter,
+            gravitational_parameter_factor: 1.0,
             epoch: starting_epoch,
-            parent: parent,
         };
+        orbit.put_on_rails(elements);
+        orbit.step(current_epoch - starting_epoch)?;
+        Ok(orbit)
+    }
 
-        orbit.step(current_epoch - starting_epoch);
-        orbit
+    /// Creates the orbits of both members of a binary system around their common barycenter.
+    /// The elements describe the relative orbit of the secondary around the primary, each member
+    /// gets a mass-weighted share of the separation and the secondary sits opposite the primary.
+    /// The parent should be created with `Body::new_barycenter` so that other objects orbiting it
+    /// feel the combined gravitational parameter.
+    /// https://en.wikipedia.org/wiki/Two-body_problem
+    pub fn new_binary_pair(
+        separation: f64,
+        eccentricity: f64,
+        argument_of_periapsis: f64,
+        inclination: f64,
+        longitude_of_ascending_node: f64,
+        primary_mass: f64,
+        secondary_mass: f64,
+        barycenter: &Body,
+        clock: &SimulationClock,
+        starting_epoch: f64,
+    ) -> Result<(Self, Self), OrbitError> {
+        finite(primary_mass, "primary mass")?;
+        finite(secondary_mass, "secondary mass")?;
+        let total_mass = primary_mass + secondary_mass;
+        let primary_share = secondary_mass / total_mass;
+        let secondary_share = primary_mass / total_mass;
+
+        let mut primary = Self::new_orbit_at(
+            separation * primary_share,
+            eccentricity,
+            argument_of_periapsis,
+            inclination,
+            longitude_of_ascending_node,
+            barycenter,
+            starting_epoch,
+            starting_epoch,
+        )?;
+        let mut secondary = Self::new_orbit_at(
+            separation * secondary_share,
+            eccentricity,
+            (argument_of_periapsis + PI) % (2.0 * PI),
+            inclination,
+            longitude_of_ascending_node,
+            barycenter,
+            starting_epoch,
+            starting_epoch,
+        )?;
+
+        // Each member is pulled only by its companion, which seen from the barycenter is equivalent
+        // to the combined gravitational parameter scaled by the cube of the companion's share
+        for (orbit, share) in [
+            (&mut primary, primary_share),
+            (&mut secondary, secondary_share),
+        ] {
+            orbit.gravitational_parameter_factor = share.powi(3);
+            let standard_gravitational_parameter = orbit.standard_gravitational_parameter();
+            if let Frame::Orbit {
+                elements,
+                mean_movement: orbit_mean_movement,
+                ..
+            } = &mut orbit.frame
+            {
+                *orbit_mean_movement =
+                    mean_movement(elements.semimajor_axis(), standard_gravitational_parameter);
+            }
+            orbit.step(clock.seconds() - starting_epoch)?;
+        }
+
+        Ok((primary, secondary))
     }
 
-    /// https://downloads.rene-schwarz.com/download/M001-Keplerian_Orbit_Elements_to_Cartesian_State_Vectors.pdf
+    /// Switches to simulating the movement dynamically, keeping the current position and velocity
     pub fn set_free(&mut self) {
-        if self.frame == Frame::Free {
-            return;
-        }
+        self.frame = Frame::Free(self.state_vectors());
+    }
+
+    /// Changes the velocity relative to the parent, the object switches to free movement
+    pub fn add_velocity(&mut self, delta_v: Vector3<f64>) -> Result<(), OrbitError> {
+        finite_vector(delta_v, "delta_v")?;
+        let mut state_vectors = self.state_vectors();
+        state_vectors.velocity += delta_v;
+        self.frame = Frame::Free(state_vectors);
+        Ok(())
+    }
 
-        let eccentricity = self
-            .eccentricity
-            .expect("Selected orbit mode should have eccentricity defined");
-        let standard_gravitational_parameter =
-            self.parent.read().unwrap().standard_gravitational_parameter;
-        let semimajor_axis = self
-            .semimajor_axis
-            .expect("Selected orbit mode should have semimajor axis defined");
-
-        let constant = (standard_gravitational_parameter * semimajor_axis).sqrt() / self.radius;
-        let vx = -constant * self.current_eccentric_anomaly.sin();
-        let vz =
-            constant * ((1.0 - eccentricity.powi(2)).sqrt() * self.current_eccentric_anomaly.cos());
-
-        let argument_of_periapsis = self
-            .argument_of_periapsis
-            .expect("Selected orbit mode should have argument of periapsis defined");
-        let longitude_of_ascending_node = self
-            .longitude_of_ascending_node
-            .expect("Selected orbit mode should have longitude of ascending node defined");
-        let inclination = -self
-            .inclination
-            .expect("Selected orbit mode should have inclination defined");
-
-        let cos_arg_per = argument_of_periapsis.cos();
-        let sin_arg_per = argument_of_periapsis.sin();
-
-        let cos_long_asc_node = longitude_of_ascending_node.cos();
-        let sin_long_asc_node = longitude_of_ascending_node.sin();
-
-        let sin_inclination = inclination.sin();
-        let cos_inclination = inclination.cos();
-
-        let rotated_vx = vx
-            * (cos_arg_per * cos_long_asc_node - sin_arg_per * cos_inclination * sin_long_asc_node)
-            - vz * (sin_arg_per * cos_long_asc_node
-                + cos_arg_per * cos_inclination * sin_long_asc_node);
-        let rotated_vy = vx * sin_arg_per * sin_inclination + vz * cos_arg_per * sin_inclination;
-        let rotated_vz = vx
-            * (cos_arg_per * cos_long_asc_node + sin_arg_per * cos_inclination * sin_long_asc_node)
-            + vz * (cos_arg_per * cos_inclination * cos_long_asc_node
-                - sin_arg_per * sin_long_asc_node);
-
-        self.vx = Some(rotated_vx);
-        self.vy = Some(rotated_vy);
-        self.vz = Some(rotated_vz);
-        self.frame = Frame::Free;
-    }
-
-    /// https://downloads.rene-schwarz.com/download/M002-Cartesian_State_Vectors_to_Keplerian_Orbit_Elements.pdf
-    pub fn set_orbit(&mut self, current_epoch: f64) {
-        if self.frame == Frame::Orbit {
-            return;
+    /// Puts the object on rails, computing the orbit described by the current position and velocity.
+    /// The orbit is left untouched if the state vectors do not describe a supported orbit.
+    pub fn set_orbit(&mut self, clock: &SimulationClock) -> Result<(), OrbitError> {
+        if matches!(self.frame, Frame::Orbit { .. }) {
+            return Ok(());
         }
 
-        let vx = self.vx.expect("Selected orbit mode should have vx defined");
-        let vy = self.vy.expect("Selected orbit mode should have vy defined");
-        let vz = self.vz.expect("Selected orbit mode should have vz defined");
-        let standard_gravitational_parameter =
-            self.parent.read().unwrap().standard_gravitational_parameter;
-
-        self.epoch = current_epoch;
-
-        let position = nalgebra::Vector3::new(self.x, self.y, self.z);
-        self.radius = position.magnitude();
-        let velocity = nalgebra::Vector3::new(vx, vy, vz);
-        let momentum = position.cross(&velocity);
-
-        let eccentricity_vector =
-            velocity.cross(&momentum) / standard_gravitational_parameter - position / self.radius;
-        let n = nalgebra::Vector3::new(-momentum.y, momentum.x, 0.0);
-        let n_norm = n.magnitude();
-
-        let true_anomaly = if position.dot(&velocity) >= 0.0 {
-            (eccentricity_vector.dot(&position)
-                / (eccentricity_vector.magnitude() * position.magnitude()))
-            .acos()
-        } else {
-            2.0 * PI
-                - (eccentricity_vector.dot(&position)
-                    / (eccentricity_vector.magnitude() * position.magnitude()))
-                .acos()
-        };
+        let elements = self.equinoctial_elements()?;
+        validate_eccentricity(elements.eccentricity())?;
 
-        // Parametros
-        let inclination = (momentum.z / momentum.magnitude()).acos();
-        self.inclination = Some(-inclination);
+        self.epoch = clock.seconds();
+        self.put_on_rails(elements);
+        Ok(())
+    }
 
-        let eccentricity = eccentricity_vector.magnitude();
-        self.eccentricity = Some(eccentricity);
+    /// Object around `parent` in a frame read back from `frame` and `epoch`, like when loading a
+    /// saved game. Members of binary pairs should use `restore` to keep their companion's pull.
+    pub fn from_frame(frame: Frame, epoch: f64, parent: &Body) -> Result<Self, OrbitError> {
+        validate_parent(parent)?;
+        let mut orbit = Self {
+            frame: Frame::Free(StateVectors::default()),
+            orbital_plane_rotation: Rotation3::identity(),
+            parent_gravitational_parameter: parent.standard_gravitational_parameter,
+            gravitational_parameter_factor: 1.0,
+            epoch: 0.0,
+        };
+        orbit.restore(frame, epoch)?;
+        Ok(orbit)
+    }
+
+    /// Puts the object back in a frame read from `frame` and `epoch`, keeping its parent.
+    /// The orbit is left untouched if the frame is not valid.
+    pub fn restore(&mut self, frame: Frame, epoch: f64) -> Result<(), OrbitError> {
+        finite(epoch, "epoch")?;
+        match &frame {
+            Frame::Free(StateVectors { position, velocity }) => {
+                finite_vector(*position, "position")?;
+                finite_vector(*velocity, "velocity")?;
+                if *position == Vector3::zeros() {
+                    return Err(OrbitError::ZeroRadius);
+                }
+            }
+            Frame::Orbit {
+                elements,
+                mean_longitude,
+                mean_movement,
+            } => {
+                for (value, name) in [
+                    (elements.p, "semi-latus rectum"),
+                    (elements.f, "f"),
+                    (elements.g, "g"),
+                    (elements.h, "h"),
+                    (elements.k, "k"),
+                    (elements.true_longitude, "true longitude"),
+                    (*mean_longitude, "mean longitude"),
+                    (*mean_movement, "mean movement"),
+                ] {
+                    finite(value, name)?;
+                }
+                validate_eccentricity(elements.eccentricity())?;
+                if elements.p <= 0.0 {
+                    return Err(OrbitError::InvalidSemimajorAxis(elements.semimajor_axis()));
+                }
+                self.update_orbital_plane_rotation(elements);
+            }
+        }
 
-        let eccentricity_const = ((1.0 + eccentricity) / (1.0 - eccentricity)).sqrt();
-        let eccentric_anomaly = 2.0 * ((true_anomaly / 2.0).tan() / eccentricity_const).atan();
-        self.current_eccentric_anomaly = eccentric_anomaly;
+        self.frame = frame;
+        self.epoch = epoch;
+        Ok(())
+    }
 
-        let longitude_of_ascending_node = if n.y >= 0.0 {
-            (n.x / n_norm).acos()
-        } else {
-            2.0 * PI - (n.x / n_norm).acos()
-        };
-        self.longitude_of_ascending_node = Some(longitude_of_ascending_node);
+    /// When the current orbit started, in seconds of the `SimulationClock`
+    pub fn epoch(&self) -> f64 {
+        self.epoch
+    }
 
-        let argument_of_periapsis = if eccentricity_vector.z >= 0.0 {
-            (n.dot(&eccentricity_vector) / (eccentricity * n_norm)).acos()
-        } else {
-            2.0 * PI - (n.dot(&eccentricity_vector) / (eccentricity * n_norm)).acos()
-        };
-        self.argument_of_periapsis = Some(argument_of_periapsis);
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
 
-        let mean_anomaly = eccentric_anomaly - eccentricity * eccentric_anomaly.sin();
-        self.current_mean_anomaly = mean_anomaly;
+    /// Position and velocity relative to the parent, derived from the elements while on rails
+    pub fn state_vectors(&self) -> StateVectors {
+        match self.frame {
+            Frame::Free(state_vectors) => state_vectors,
+            Frame::Orbit { elements, .. } => {
+                let (position, velocity) =
+                    elements.to_state_vectors(self.standard_gravitational_parameter());
+                StateVectors {
+                    position: world_from_reference() * position,
+                    velocity: world_from_reference() * velocity,
+                }
+            }
+        }
+    }
 
-        let semi_major_axis = 1.0
-            / ((2.0 / self.radius) - (self.velocity.powi(2) / standard_gravitational_parameter));
-        self.semimajor_axis = Some(semi_major_axis);
+    /// Osculating elements of the current movement, derived from the state vectors while free.
+    /// Fails if the state vectors do not describe an orbit, like a radial trajectory.
+    pub fn equinoctial_elements(&self) -> Result<EquinoctialElements, OrbitError> {
+        match self.frame {
+            Frame::Orbit { elements, .. } => Ok(elements),
+            Frame::Free(StateVectors { position, velocity }) => {
+                let reference_from_world = world_from_reference().inverse();
+                EquinoctialElements::from_state_vectors(
+                    reference_from_world * position,
+                    reference_from_world * velocity,
+                    self.standard_gravitational_parameter(),
+                )
+            }
+        }
+    }
 
-        self.mean_movement = Some(mean_movement(semi_major_axis, &self.parent));
-        self.frame = Frame::Orbit;
+    /// Classical elements of the current movement, for display
+    pub fn classical_elements(&self) -> Result<ClassicalElements, OrbitError> {
+        self.equinoctial_elements()
+            .map(|elements| elements.to_classical())
     }
 
     /// Moves the body according to the elapsed time
-    pub fn step(&mut self, seconds: f64) {
-        match self.frame {
-            Frame::Orbit => self.step_orbit(seconds),
-            Frame::Free => self.step_free(seconds),
+    pub fn step(&mut self, seconds: f64) -> Result<(), OrbitError> {
+        self.step_perturbed(seconds, |_, _| Vector3::zeros())
+    }
+
+    /// Like `step`, adding the acceleration returned by `perturbation` to the parent's gravity.
+    /// It receives the state relative to the parent and the seconds elapsed since the start of
+    /// the step. Orbits on rails stay Keplerian and ignore it.
+    pub fn step_perturbed(
+        &mut self,
+        seconds: f64,
+        perturbation: impl Fn(&StateVectors, f64) -> Vector3<f64>,
+    ) -> Result<(), OrbitError> {
+        let standard_gravitational_parameter = self.standard_gravitational_parameter();
+        match &mut self.frame {
+            Frame::Orbit {
+                elements,
+                mean_longitude,
+                mean_movement,
+            } => step_orbit(elements, mean_longitude, *mean_movement, seconds),
+            Frame::Free(state_vectors) => {
+                step_free(
+                    state_vectors,
+                    standard_gravitational_parameter,
+                    seconds,
+                    perturbation,
+                );
+                Ok(())
+            }
         }
     }
 
-    /// https://en.wikipedia.org/wiki/Verlet_integration
-    /// Since this method is reasonably cheap, it can be changed to use a fixed timestep integration if future
-    fn step_free(&mut self, seconds: f64) {
-        let vx = self
-            .vx
-            .as_mut()
-            .expect("Selected orbit mode should have vx defined");
-        let vy = self
-            .vy
-            .as_mut()
-            .expect("Selected orbit mode should have vy defined");
-        let vz = self
-            .vz
-            .as_mut()
-            .expect("Selected orbit mode should have vz defined");
-        let standard_gravitational_parameter =
-            self.parent.read().unwrap().standard_gravitational_parameter;
-
-        let r_squared = self.x.powi(2) + self.y.powi(2) + self.z.powi(2);
-        let gravitational_acceleration = standard_gravitational_parameter / r_squared;
-
-        let r = r_squared.sqrt();
-
-        let gravitational_acceleration_x = (-self.x / r) * gravitational_acceleration;
-        let gravitational_acceleration_y = (-self.y / r) * gravitational_acceleration;
-        let gravitational_acceleration_z = (-self.z / r) * gravitational_acceleration;
-
-        // Update positions using the current velocities
-        let seconds_squared_halved = seconds.powi(2) / 2.0;
-        self.x += *vx * seconds + gravitational_acceleration_x * seconds_squared_halved;
-        self.y += *vy * seconds + gravitational_acceleration_y * seconds_squared_halved;
-        self.z += *vz * seconds + gravitational_acceleration_z * seconds_squared_halved;
-
-        // Calculate the new gravitational acceleration at the updated position
-        let r_squared_new = self.x.powi(2) + self.y.powi(2) + self.z.powi(2);
-        let gravitational_acceleration_new = standard_gravitational_parameter / r_squared_new;
-
-        let r_new = r_squared_new.sqrt();
-
-        let gravitational_acceleration_x_new = (-self.x / r_new) * gravitational_acceleration_new;
-        let gravitational_acceleration_y_new = (-self.y / r_new) * gravitational_acceleration_new;
-        let gravitational_acceleration_z_new = (-self.z / r_new) * gravitational_acceleration_new;
-
-        // Update velocities based on the average of the old and new accelerations
-        *vx += 0.5 * (gravitational_acceleration_x + gravitational_acceleration_x_new) * seconds;
-        *vy += 0.5 * (gravitational_acceleration_y + gravitational_acceleration_y_new) * seconds;
-        *vz += 0.5 * (gravitational_acceleration_z + gravitational_acceleration_z_new) * seconds;
-        self.velocity = (vx.powi(2) + vy.powi(2) + vz.powi(2)).sqrt()
-    }
-
-    fn step_orbit(&mut self, seconds: f64) {
-        let eccentricity = self
-            .eccentricity
-            .expect("Selected orbit mode should have eccentricity defined");
-        match eccentricity {
-            0.0..1.0 => self.step_eliptical_orbit(seconds),
-            1.0 => todo!("Support parabolic orbits"),
-            0.0.. => todo!("Suport hiperbolic orbits"),
-            _ => unreachable!("Negative eccentricity does not make physical sense"),
+    /// Steps every orbit on rails like `step`, solving all their Kepler equations as one batch.
+    /// Objects in free flight are skipped. Returns the index and error of the orbits that could
+    /// not be stepped, which are left as they were.
+    pub fn step_on_rails(orbits: &mut [&mut Orbit], seconds: f64) -> Vec<(usize, OrbitError)> {
+        let mut errors = Vec::new();
+        let mut indices = Vec::with_capacity(orbits.len());
+        let mut targets = Vec::with_capacity(orbits.len());
+        for (index, orbit) in orbits.iter_mut().enumerate() {
+            let Frame::Orbit {
+                elements,
+                mean_longitude,
+                mean_movement,
+            } = &mut orbit.frame
+            else {
+                continue;
+            };
+            if let Err(error) = validate_eccentricity(elements.eccentricity()) {
+                errors.push((index, error));
+                continue;
+            }
+
+            // https://es.wikipedia.org/wiki/Anomalía_media
+            let stepped_mean_longitude =
+                (*mean_longitude + *mean_movement * seconds).rem_euclid(2.0 * PI);
+            indices.push((index, stepped_mean_longitude));
+            targets.push((elements, stepped_mean_longitude));
+        }
+
+        let results = EquinoctialElements::set_mean_longitudes(&mut targets);
+        for ((index, stepped_mean_longitude), result) in indices.into_iter().zip(results) {
+            match result {
+                Ok(()) => {
+                    if let Frame::Orbit { mean_longitude, .. } = &mut orbits[index].frame {
+                        *mean_longitude = stepped_mean_longitude;
+                    }
+                }
+                Err(error) => errors.push((index, error.into())),
+            }
         }
+        errors
     }
 
-    fn step_eliptical_orbit(&mut self, seconds: f64) {
-        // https://es.wikipedia.org/wiki/Anomalía_media
-        self.current_mean_anomaly = (self.current_mean_anomaly
-            + self
-                .mean_movement
-                .expect("Selected orbit mode should have mean movement defined")
-                * seconds)
-            % (2.0 * PI);
-
-        // https://es.wikipedia.org/wiki/Anomalía_excéntrica
-        let eccentricity = self
-            .eccentricity
-            .expect("Selected orbit mode should have eccentricity defined");
-        let kepler_equation = kepler_equation_zeroed(self.current_mean_anomaly, eccentricity);
-        let kepler_equation_derivative = kepler_equation_zeroed_derivative(eccentricity);
-        let eccentric_anomaly = crate::solver::solve_newton_raphson(
-            kepler_equation,
-            kepler_equation_derivative,
-            self.current_mean_anomaly,
-            ECCENTRIC_ANOMALY_TOLERANCE,
-            ECCENTRIC_ANOMALY_MAX_ITERATIONS,
-        );
+    /// Seconds until the next periapsis passage
+    pub fn time_to_periapsis(&self) -> Result<f64, OrbitError> {
+        self.time_to_true_anomaly(0.0)
+    }
 
-        // https://es.wikipedia.org/wiki/Anomalía_verdadera
-        let constant = ((1.0 + eccentricity) / (1.0 - eccentricity)).sqrt();
-        let mut true_anomaly = (constant * (eccentric_anomaly / 2.0).tan()).atan() * 2.0;
-
-        // https://en.wikipedia.org/wiki/Orbital_mechanics#Ellipse_geometry
-        let semimajor_axis = self
-            .semimajor_axis
-            .expect("Selected orbit mode should have semimajor axis defined");
-        let radius = (semimajor_axis * (1.0 - eccentricity.powi(2)))
-            / (1.0 + eccentricity * true_anomaly.cos());
-
-        // Apply argument of periapsis
-        true_anomaly += self
-            .argument_of_periapsis
-            .expect("Selected orbit mode should have argument of periapsis defined");
-
-        // Polar: (true_anomaly, radius)
-        let mut position = nalgebra::Vector3::new(
-            radius * true_anomaly.cos(),
-            0.0,
-            radius * true_anomaly.sin(),
-        );
+    /// Seconds until the next apoapsis passage
+    pub fn time_to_apoapsis(&self) -> Result<f64, OrbitError> {
+        self.time_to_true_anomaly(PI)
+    }
 
-        // Apply inclitation and longitude of ascending node by rotating the point. COULD BE CACHED
-        let inclination = self
-            .inclination
-            .expect("Selected orbit mode should have inclination defined");
-        let longitude_of_ascending_node = self
-            .longitude_of_ascending_node
-            .expect("Selected orbit mode should have longitude of ascending node defined");
+    /// Seconds until the orbit next crosses the parent's reference plane going north
+    pub fn time_to_ascending_node(&self) -> Result<f64, OrbitError> {
+        let elements = self.equinoctial_elements()?;
+        if elements.h.hypot(elements.k) < EQUATORIAL_TOLERANCE {
+            return Err(OrbitError::UndefinedAscendingNode);
+        }
+        // The true longitude at the node is the longitude of the ascending node
+        let longitude_of_ascending_node = elements.k.atan2(elements.h);
+        self.time_to_true_anomaly(longitude_of_ascending_node - elements.longitude_of_periapsis())
+    }
 
-        let rotation_longitude_of_ascending_node = nalgebra::Rotation3::from_axis_angle(
-            &nalgebra::Vector3::z_axis(),
-            longitude_of_ascending_node,
+    /// Seconds until the object reaches the given true anomaly, following the current orbit
+    fn time_to_true_anomaly(&self, true_anomaly: f64) -> Result<f64, OrbitError> {
+        let elements = self.equinoctial_elements()?;
+        validate_eccentricity(elements.eccentricity())?;
+
+        let current_mean_anomaly =
+            elements.mean_anomaly_at(elements.true_longitude - elements.longitude_of_periapsis());
+        let target_mean_anomaly = elements.mean_anomaly_at(true_anomaly);
+        let mean_movement = mean_movement(
+            elements.semimajor_axis(),
+            self.standard_gravitational_parameter(),
         );
-        let rotation_inclination =
-            nalgebra::Rotation3::from_axis_angle(&nalgebra::Vector3::x_axis(), inclination);
+        let mut phase = (target_mean_anomaly - current_mean_anomaly).rem_euclid(2.0 * PI);
+        // Already there, wait for the next pass
+        if phase < ANOMALY_TOLERANCE {
+            phase += 2.0 * PI;
+        }
+        Ok(phase / mean_movement)
+    }
 
-        let roation = rotation_longitude_of_ascending_node * rotation_inclination;
+    /// Time needed to complete a revolution
+    pub fn period(&self) -> Result<f64, OrbitError> {
+        let elements = self.equinoctial_elements()?;
+        validate_eccentricity(elements.eccentricity())?;
+        Ok(2.0 * PI
+            / mean_movement(
+                elements.semimajor_axis(),
+                self.standard_gravitational_parameter(),
+            ))
+    }
 
-        position = roation * position;
+    /// Distance from the body moving along this orbit where its gravity dominates over the
+    /// parent's.
+    /// https://en.wikipedia.org/wiki/Sphere_of_influence_(astrodynamics)
+    pub fn sphere_of_influence(&self, body: &Body) -> Result<f64, OrbitError> {
+        let semimajor_axis = self.equinoctial_elements()?.semimajor_axis();
+        Ok(semimajor_axis
+            * (body.standard_gravitational_parameter / self.parent_gravitational_parameter)
+                .powf(0.4))
+    }
 
-        self.x = position.x;
-        self.y = position.y;
-        self.z = position.z;
-        // https://en.wikipedia.org/wiki/Vis-viva_equation
-        self.velocity = (self.parent.read().unwrap().standard_gravitational_parameter
-            * (2.0 / radius - 1.0 / semimajor_axis))
-            .sqrt();
-        self.current_eccentric_anomaly = eccentric_anomaly;
-        self.radius = radius;
+    /// Position relative to the parent
+    pub fn position(&self) -> Vector3<f64> {
+        match self.frame {
+            Frame::Free(StateVectors { position, .. }) => position,
+            Frame::Orbit { elements, .. } => {
+                // Polar in the orbital plane: (true_longitude, radius)
+                let radius = elements.radius();
+                let (sin_longitude, cos_longitude) = elements.true_longitude.sin_cos();
+                self.orbital_plane_rotation
+                    * Vector3::new(radius * cos_longitude, radius * sin_longitude, 0.0)
+            }
+        }
     }
 
-    pub fn position(&self) -> (f64, f64, f64) {
-        (self.x, self.y, self.z)
+    /// Speed relative to the parent
+    pub fn speed(&self) -> f64 {
+        match self.frame {
+            Frame::Free(StateVectors { velocity, .. }) => velocity.magnitude(),
+            // https://en.wikipedia.org/wiki/Vis-viva_equation
+            Frame::Orbit { elements, .. } => (self.standard_gravitational_parameter()
+                * (2.0 / elements.radius() - 1.0 / elements.semimajor_axis()))
+            .sqrt(),
+        }
     }
 
-    pub fn absolute_position(&self) -> (f64, f64, f64) {
-        let mut position = (self.x, self.y, self.z);
-        if let Some(parent_orbit) = &self.parent.read().unwrap().orbit {
-            let parent_position = parent_orbit.absolute_position();
+    fn put_on_rails(&mut self, elements: EquinoctialElements) {
+        self.update_orbital_plane_rotation(&elements);
+        self.frame = Frame::Orbit {
+            elements,
+            mean_longitude: elements.mean_longitude(),
+            mean_movement: mean_movement(
+                elements.semimajor_axis(),
+                self.standard_gravitational_parameter(),
+            ),
+        };
+    }
 
-            position.0 += parent_position.0;
-            position.1 += parent_position.1;
-            position.2 += parent_position.2;
-        }
-        position
+    /// Rotation from the orbital plane to the parent's frame.
+    /// Only depends on the orbit orientation, so it is computed when it changes instead of every step
+    fn update_orbital_plane_rotation(&mut self, elements: &EquinoctialElements) {
+        self.orbital_plane_rotation =
+            world_from_reference() * Rotation3::from_matrix_unchecked(elements.basis());
+    }
+
+    /// Gravitational parameter this object is attracted with
+    fn standard_gravitational_parameter(&self) -> f64 {
+        self.parent_gravitational_parameter * self.gravitational_parameter_factor
     }
 }
 
+/// https://en.wikipedia.org/wiki/Verlet_integration
+/// Since this method is reasonably cheap, it can be changed to use a fixed timestep integration if future
+/// Perturbations are evaluated with the velocity at the start of the step, so velocity dependent
+/// forces like drag are only first order accurate
+fn step_free(
+    state_vectors: &mut StateVectors,
+    standard_gravitational_parameter: f64,
+    seconds: f64,
+    perturbation: impl Fn(&StateVectors, f64) -> Vector3<f64>,
+) {
+    let gravitational_acceleration = |position: Vector3<f64>| {
+        -position.normalize() * standard_gravitational_parameter / position.magnitude_squared()
+    };
+
+    // Update positions using the current velocities
+    let acceleration =
+        gravitational_acceleration(state_vectors.position) + perturbation(state_vectors, 0.0);
+    state_vectors.position +=
+        state_vectors.velocity * seconds + acceleration * (seconds.powi(2) / 2.0);
+
+    // Update velocities based on the average of the old and new accelerations
+    let new_acceleration =
+        gravitational_acceleration(state_vectors.position) + perturbation(state_vectors, seconds);
+    state_vectors.velocity += 0.5 * (acceleration + new_acceleration) * seconds;
+}
+
+fn step_orbit(
+    elements: &mut EquinoctialElements,
+    mean_longitude: &mut f64,
+    mean_movement: f64,
+    seconds: f64,
+) -> Result<(), OrbitError> {
+    validate_eccentricity(elements.eccentricity())?;
+
+    // https://es.wikipedia.org/wiki/Anomalía_media
+    *mean_longitude = (*mean_longitude + mean_movement * seconds).rem_euclid(2.0 * PI);
+    elements.set_mean_longitude(*mean_longitude)?;
+    Ok(())
+}
+
 /// https://es.wikipedia.org/wiki/Movimiento_medio_diario
 /// https://es.wikipedia.org/wiki/Leyes_de_Kepler
 /// 2*PI / T
-fn mean_movement(semimajor_axis: f64, parent: &Arc<RwLock<Body>>) -> f64 {
-    (parent.read().unwrap().standard_gravitational_parameter / semimajor_axis.powi(3)).sqrt()
+fn mean_movement(semimajor_axis: f64, standard_gravitational_parameter: f64) -> f64 {
+    (standard_gravitational_parameter / semimajor_axis.powi(3)).sqrt()
+}
+
+fn finite(value: f64, name: &'static str) -> Result<f64, OrbitError> {
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(OrbitError::NonFinite(name))
+    }
 }
 
-/// https://es.wikipedia.org/wiki/Ecuación_de_Kepler
-/// 0 = E - e*sen(E) - M
-fn kepler_equation_zeroed(mean_anomaly: f64, eccentricity: f64) -> impl Fn(f64) -> f64 {
-    move |eccentric_anomaly| {
-        eccentric_anomaly - eccentricity * eccentric_anomaly.sin() - mean_anomaly
+fn finite_vector(value: Vector3<f64>, name: &'static str) -> Result<Vector3<f64>, OrbitError> {
+    if value.iter().all(|component| component.is_finite()) {
+        Ok(value)
+    } else {
+        Err(OrbitError::NonFinite(name))
     }
 }
 
-fn kepler_equation_zeroed_derivative(eccentricity: f64) -> impl Fn(f64) -> f64 {
-    move |eccentric_anomaly| 1.0 - eccentricity * eccentric_anomaly.cos()
+fn validate_eccentricity(eccentricity: f64) -> Result<(), OrbitError> {
+    if eccentricity < 0.0 {
+        Err(OrbitError::NegativeEccentricity(eccentricity))
+    } else if eccentricity >= 1.0 {
+        Err(OrbitError::UnsupportedEccentricity(eccentricity))
+    } else {
+        Ok(())
+    }
+}
+
+fn validate_parent(parent: &Body) -> Result<(), OrbitError> {
+    let standard_gravitational_parameter = parent.standard_gravitational_parameter;
+    if standard_gravitational_parameter.is_finite() && standard_gravitational_parameter > 0.0 {
+        Ok(())
+    } else {
+        Err(OrbitError::InvalidGravitationalParameter(
+            standard_gravitational_parameter,
+        ))
+    }
 }
@@ -0,0 +1,235 @@
+use bevy::prelude::*;
+
+use crate::{Body, Orbit, OrbitError, SimulationClock};
+
+/// https://en.wikipedia.org/wiki/Astronomical_unit
+pub const ASTRONOMICAL_UNIT: f64 = 149_597_870_700.0;
+const DAYS_PER_CENTURY: f64 = 36_525.0;
+
+/// Planets with mean elements in the embedded ephemeris tables
+#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlanetEphemeris {
+    Mercury,
+    Venus,
+    /// The tables follow the Earth-Moon barycenter, the Earth itself is at most 4700 km away
+    Earth,
+    Mars,
+    Jupiter,
+    Saturn,
+    Uranus,
+    Neptune,
+}
+
+/// Heliocentric mean elements at a given date, referred to the J2000 ecliptic and equinox.
+/// Lengths are in meters and angles in radians.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeanElements {
+    pub semimajor_axis: f64,
+    pub eccentricity: f64,
+    pub inclination: f64,
+    pub mean_longitude: f64,
+    pub longitude_of_perihelion: f64,
+    pub longitude_of_ascending_node: f64,
+}
+
+/// Values at J2000 and rates per Julian century, in AU and degrees as published
+struct ElementTable {
+    semimajor_axis: [f64; 2],
+    eccentricity: [f64; 2],
+    inclination: [f64; 2],
+    mean_longitude: [f64; 2],
+    longitude_of_perihelion: [f64; 2],
+    longitude_of_ascending_node: [f64; 2],
+}
+
+impl PlanetEphemeris {
+    pub const ALL: [Self; 8] = [
+        Self::Mercury,
+        Self::Venus,
+        Self::Earth,
+        Self::Mars,
+        Self::Jupiter,
+        Self::Saturn,
+        Self::Uranus,
+        Self::Neptune,
+    ];
+
+    /// Approximate positions of the major planets, valid from 1800 AD to 2050 AD.
+    /// The error is below a few arcminutes for the inner planets inside that range.
+    /// https://ssd.jpl.nasa.gov/planets/approx_pos.html
+    fn table(self) -> ElementTable {
+        match self {
+            Self::Mercury => ElementTable {
+                semimajor_axis: [0.38709927, 0.00000037],
+                eccentricity: [0.20563593, 0.00001906],
+                inclination: [7.00497902, -0.00594749],
+                mean_longitude: [252.25032350, 149472.67411175],
+                longitude_of_perihelion: [77.45779628, 0.16047689],
+                longitude_of_ascending_node: [48.33076593, -0.12534081],
+            },
+            Self::Venus => ElementTable {
+                semimajor_axis: [0.72333566, 0.00000390],
+                eccentricity: [0.00677672, -0.00004107],
+                inclination: [3.39467605, -0.00078890],
+                mean_longitude: [181.97909950, 58517.81538729],
+                longitude_of_perihelion: [131.60246718, 0.00268329],
+                longitude_of_ascending_node: [76.67984255, -0.27769418],
+            },
+            Self::Earth => ElementTable {
+                semimajor_axis: [1.00000261, 0.00000562],
+                eccentricity: [0.01671123, -0.00004392],
+                inclination: [-0.00001531, -0.01294668],
+                mean_longitude: [100.46457166, 35999.37244981],
+                longitude_of_perihelion: [102.93768193, 0.32327364],
+                longitude_of_ascending_node: [0.0, 0.0],
+            },
+            Self::Mars => ElementTable {
+                semimajor_axis: [1.52371034, 0.00001847],
+                eccentricity: [0.09339410, 0.00007882],
+                inclination: [1.84969142, -0.00813131],
+                mean_longitude: [-4.55343205, 19140.30268499],
+                longitude_of_perihelion: [-23.94362959, 0.44441088],
+                longitude_of_ascending_node: [49.55953891, -0.29257343],
+            },
+            Self::Jupiter => ElementTable {
+                semimajor_axis: [5.20288700, -0.00011607],
+                eccentricity: [0.04838624, -0.00013253],
+                inclination: [1.30439695, -0.00183714],
+                mean_longitude: [34.39644051, 3034.74612775],
+                longitude_of_perihelion: [14.72847983, 0.21252668],
+                longitude_of_ascending_node: [100.47390909, 0.20469106],
+            },
+            Self::Saturn => ElementTable {
+                semimajor_axis: [9.53667594, -0.00125060],
+                eccentricity: [0.05386179, -0.00050991],
+                inclination: [2.48599187, 0.00193609],
+                mean_longitude: [49.95424423, 1222.49362201],
+                longitude_of_perihelion: [92.59887831, -0.41897216],
+                longitude_of_ascending_node: [113.66242448, -0.28867794],
+            },
+            Self::Uranus => ElementTable {
+                semimajor_axis: [19.18916464, -0.00196176],
+                eccentricity: [0.04725744, -0.00004397],
+                inclination: [0.77263783, -0.00242939],
+                mean_longitude: [313.23810451, 428.48202785],
+                longitude_of_perihelion: [170.95427630, 0.40805281],
+                longitude_of_ascending_node: [74.01692503, 0.04240589],
+            },
+            Self::Neptune => ElementTable {
+                semimajor_axis: [30.06992276, 0.00026291],
+                eccentricity: [0.00859048, 0.00005105],
+                inclination: [1.77004347, 0.00035372],
+                mean_longitude: [-55.12002969, 218.45945325],
+                longitude_of_perihelion: [44.96476227, -0.32241464],
+                longitude_of_ascending_node: [131.78422574, -0.00508664],
+            },
+        }
+    }
+
+    /// Mean elements at the given Julian date
+    pub fn mean_elements(self, julian_date: f64) -> MeanElements {
+        let centuries = (julian_date - crate::J2000_JULIAN_DATE) / DAYS_PER_CENTURY;
+        let table = self.table();
+        let at_date = |[value, rate]: [f64; 2]| value + rate * centuries;
+
+        MeanElements {
+            semimajor_axis: at_date(table.semimajor_axis) * ASTRONOMICAL_UNIT,
+            eccentricity: at_date(table.eccentricity),
+            inclination: at_date(table.inclination).to_radians(),
+            mean_longitude: at_date(table.mean_longitude).to_radians(),
+            longitude_of_perihelion: at_date(table.longitude_of_perihelion).to_radians(),
+            longitude_of_ascending_node: at_date(table.longitude_of_ascending_node).to_radians(),
+        }
+    }
+}
+
+impl Orbit {
+    /// Heliocentric orbit of a planet with its real position at the clock's date.
+    /// The elements are frozen at that date, from there on the planet follows a Keplerian orbit
+    /// around the given sun. The ecliptic is the reference plane.
+    pub fn from_ephemeris(
+        planet: PlanetEphemeris,
+        sun: &Body,
+        clock: &SimulationClock,
+    ) -> Result<Self, OrbitError> {
+        let elements = planet.mean_elements(clock.julian_date());
+        let mean_anomaly = elements.mean_longitude - elements.longitude_of_perihelion;
+        let mean_movement =
+            (sun.standard_gravitational_parameter / elements.semimajor_axis.powi(3)).sqrt();
+
+        // The orbit starts at the periapsis, so the starting epoch is the last perihelion passage
+        let starting_epoch =
+            clock.seconds() - mean_anomaly.rem_euclid(std::f64::consts::TAU) / mean_movement;
+        Self::new_orbit(
+            elements.semimajor_axis,
+            elements.eccentricity,
+            elements.longitude_of_perihelion - elements.longitude_of_ascending_node,
+            elements.inclination,
+            elements.longitude_of_ascending_node,
+            sun,
+            clock,
+            starting_epoch,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{equinoctial::world_from_reference, julian_date_from_utc};
+
+    const SUN_MASS: f64 = 1.98892e30;
+
+    /// Heliocentric ecliptic longitude in degrees
+    fn longitude(orbit: &Orbit) -> f64 {
+        let position = world_from_reference().inverse() * orbit.position();
+        position.y.atan2(position.x).to_degrees().rem_euclid(360.0)
+    }
+
+    fn angle_between(a: f64, b: f64) -> f64 {
+        let difference = (a - b).rem_euclid(360.0);
+        difference.min(360.0 - difference)
+    }
+
+    #[test]
+    fn earth_at_j2000() {
+        let sun = Body::new(SUN_MASS);
+        let earth =
+            Orbit::from_ephemeris(PlanetEphemeris::Earth, &sun, &SimulationClock::default())
+                .unwrap();
+
+        // The Sun is seen at 280.4º, so the Earth is on the opposite side
+        assert!(angle_between(longitude(&earth), 100.4) < 0.1);
+        // Perihelion is in early January
+        let distance = earth.position().magnitude() / ASTRONOMICAL_UNIT;
+        assert!((distance - 0.9833).abs() < 1e-3, "{distance}");
+    }
+
+    #[test]
+    fn mars_oppositions() {
+        let sun = Body::new(SUN_MASS);
+        for (year, month, day) in [(2003, 8, 28), (2018, 7, 27), (2020, 10, 13)] {
+            let clock =
+                SimulationClock::starting_at(julian_date_from_utc(year, month, day, 0, 0, 0.0));
+            let earth = Orbit::from_ephemeris(PlanetEphemeris::Earth, &sun, &clock).unwrap();
+            let mars = Orbit::from_ephemeris(PlanetEphemeris::Mars, &sun, &clock).unwrap();
+
+            // Earth sits between the Sun and Mars
+            let separation = angle_between(longitude(&earth), longitude(&mars));
+            assert!(separation < 1.0, "{year}-{month}-{day}: {separation}");
+        }
+    }
+
+    #[test]
+    fn all_planets_are_valid() {
+        let sun = Body::new(SUN_MASS);
+        let clock = SimulationClock::starting_at(julian_date_from_utc(2030, 1, 1, 0, 0, 0.0));
+        for planet in PlanetEphemeris::ALL {
+            let orbit = Orbit::from_ephemeris(planet, &sun, &clock).unwrap();
+            let elements = planet.mean_elements(clock.julian_date());
+            let distance = orbit.position().magnitude();
+            assert!(distance > elements.semimajor_axis * (1.0 - elements.eccentricity) * 0.999);
+            assert!(distance < elements.semimajor_axis * (1.0 + elements.eccentricity) * 1.001);
+        }
+    }
+}
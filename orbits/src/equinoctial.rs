@@ -0,0 +1,410 @@
+use std::f64::consts::PI;
+
+use bevy::prelude::*;
+use nalgebra::{Matrix3, Rotation3, Vector3};
+
+use crate::{
+    OrbitError,
+    solver::{KeplerError, KeplerSolution},
+};
+
+const ECCENTRIC_ANOMALY_TOLERANCE: f64 = 1e-12;
+const ECCENTRIC_ANOMALY_MAX_ITERATIONS: u32 = 100;
+/// Relative size of the angular momentum below which a trajectory is considered radial
+const RADIAL_TRAJECTORY_TOLERANCE: f64 = 1e-12;
+/// How close to 180º the inclination can get before the elements become singular
+const RETROGRADE_EQUATORIAL_TOLERANCE: f64 = 1e-9;
+
+/// Modified equinoctial elements.
+/// Unlike the classical elements they stay well defined for circular and equatorial orbits, the
+/// only singularity is an inclination of exactly 180º.
+/// Angles are measured in the reference frame, where Z is the north pole.
+/// https://doi.org/10.1007/BF01227757
+#[derive(Reflect, Debug, Clone, Copy, PartialEq)]
+pub struct EquinoctialElements {
+    /// Semi-latus rectum, a * (1 - e^2)
+    pub p: f64,
+    /// e * cos(argument of periapsis + longitude of ascending node)
+    pub f: f64,
+    /// e * sin(argument of periapsis + longitude of ascending node)
+    pub g: f64,
+    /// tan(i / 2) * cos(longitude of ascending node)
+    pub h: f64,
+    /// tan(i / 2) * sin(longitude of ascending node)
+    pub k: f64,
+    /// Longitude of ascending node + argument of periapsis + true anomaly
+    pub true_longitude: f64,
+}
+
+/// Classical Keplerian elements, derived from the equinoctial ones for display.
+/// For circular orbits the argument of periapsis is 0 and for equatorial orbits the longitude of
+/// the ascending node is 0, the angle is carried over to the next defined one.
+#[derive(Reflect, Debug, Clone, Copy, PartialEq)]
+pub struct ClassicalElements {
+    /// https://es.wikipedia.org/wiki/Semieje_mayor
+    pub semimajor_axis: f64,
+    /// https://es.wikipedia.org/wiki/Excentricidad_orbital
+    pub eccentricity: f64,
+    /// https://es.wikipedia.org/wiki/Inclinaci%C3%B3n_orbital
+    pub inclination: f64,
+    /// https://es.wikipedia.org/wiki/Longitud_del_nodo_ascendente
+    pub longitude_of_ascending_node: f64,
+    /// https://es.wikipedia.org/wiki/Argumento_del_periastro
+    pub argument_of_periapsis: f64,
+    /// https://es.wikipedia.org/wiki/Anomalía_verdadera
+    pub true_anomaly: f64,
+}
+
+impl EquinoctialElements {
+    pub fn from_classical(elements: &ClassicalElements) -> Result<Self, OrbitError> {
+        if (elements.inclination.rem_euclid(2.0 * PI) - PI).abs() < RETROGRADE_EQUATORIAL_TOLERANCE
+        {
+            return Err(OrbitError::RetrogradeEquatorial);
+        }
+
+        let longitude_of_periapsis =
+            elements.argument_of_periapsis + elements.longitude_of_ascending_node;
+        let half_inclination_tangent = (elements.inclination / 2.0).tan();
+
+        Ok(Self {
+            p: elements.semimajor_axis * (1.0 - elements.eccentricity.powi(2)),
+            f: elements.eccentricity * longitude_of_periapsis.cos(),
+            g: elements.eccentricity * longitude_of_periapsis.sin(),
+            h: half_inclination_tangent * elements.longitude_of_ascending_node.cos(),
+            k: half_inclination_tangent * elements.longitude_of_ascending_node.sin(),
+            true_longitude: (longitude_of_periapsis + elements.true_anomaly).rem_euclid(2.0 * PI),
+        })
+    }
+
+    pub fn to_classical(&self) -> ClassicalElements {
+        let eccentricity = self.eccentricity();
+        let longitude_of_ascending_node = self.k.atan2(self.h).rem_euclid(2.0 * PI);
+        let longitude_of_periapsis = self.longitude_of_periapsis();
+
+        ClassicalElements {
+            semimajor_axis: self.semimajor_axis(),
+            eccentricity,
+            inclination: 2.0 * (self.h.powi(2) + self.k.powi(2)).sqrt().atan(),
+            longitude_of_ascending_node,
+            argument_of_periapsis: (longitude_of_periapsis - longitude_of_ascending_node)
+                .rem_euclid(2.0 * PI),
+            true_anomaly: (self.true_longitude - longitude_of_periapsis).rem_euclid(2.0 * PI),
+        }
+    }
+
+    /// Position and velocity must be in the reference frame
+    pub fn from_state_vectors(
+        position: Vector3<f64>,
+        velocity: Vector3<f64>,
+        standard_gravitational_parameter: f64,
+    ) -> Result<Self, OrbitError> {
+        let radius = position.magnitude();
+        if radius == 0.0 {
+            return Err(OrbitError::ZeroRadius);
+        }
+        let momentum = position.cross(&velocity);
+        let momentum_norm = momentum.magnitude();
+        if momentum_norm <= RADIAL_TRAJECTORY_TOLERANCE * radius * velocity.magnitude() {
+            return Err(OrbitError::RadialTrajectory);
+        }
+
+        let normal = momentum / momentum_norm;
+        if 1.0 + normal.z < RETROGRADE_EQUATORIAL_TOLERANCE {
+            return Err(OrbitError::RetrogradeEquatorial);
+        }
+        let h = -normal.y / (1.0 + normal.z);
+        let k = normal.x / (1.0 + normal.z);
+
+        let mut elements = Self {
+            p: momentum_norm.powi(2) / standard_gravitational_parameter,
+            f: 0.0,
+            g: 0.0,
+            h,
+            k,
+            true_longitude: 0.0,
+        };
+
+        let eccentricity_vector =
+            velocity.cross(&momentum) / standard_gravitational_parameter - position / radius;
+        let basis = elements.basis();
+        let f_axis = basis.column(0);
+        let g_axis = basis.column(1);
+
+        elements.f = eccentricity_vector.dot(&f_axis);
+        elements.g = eccentricity_vector.dot(&g_axis);
+        elements.true_longitude = position
+            .dot(&g_axis)
+            .atan2(position.dot(&f_axis))
+            .rem_euclid(2.0 * PI);
+
+        Ok(elements)
+    }
+
+    /// Position and velocity in the reference frame
+    pub fn to_state_vectors(
+        &self,
+        standard_gravitational_parameter: f64,
+    ) -> (Vector3<f64>, Vector3<f64>) {
+        let basis = self.basis();
+        let f_axis = basis.column(0);
+        let g_axis = basis.column(1);
+
+        let (sin_longitude, cos_longitude) = self.true_longitude.sin_cos();
+        let radius = self.radius();
+        let position = radius * (cos_longitude * f_axis + sin_longitude * g_axis);
+
+        let speed_constant = (standard_gravitational_parameter / self.p).sqrt();
+        let velocity = speed_constant
+            * (-(sin_longitude + self.g) * f_axis + (cos_longitude + self.f) * g_axis);
+
+        (position, velocity)
+    }
+
+    /// Columns are the equinoctial frame axes, f and g span the orbital plane and w is its normal
+    pub fn basis(&self) -> Matrix3<f64> {
+        let h_squared = self.h.powi(2);
+        let k_squared = self.k.powi(2);
+        let s_squared = 1.0 + h_squared + k_squared;
+        let alpha_squared = h_squared - k_squared;
+        let hk = self.h * self.k;
+
+        Matrix3::new(
+            1.0 + alpha_squared,
+            2.0 * hk,
+            2.0 * self.k,
+            2.0 * hk,
+            1.0 - alpha_squared,
+            -2.0 * self.h,
+            -2.0 * self.k,
+            2.0 * self.h,
+            1.0 - h_squared - k_squared,
+        ) / s_squared
+    }
+
+    pub fn eccentricity(&self) -> f64 {
+        (self.f.powi(2) + self.g.powi(2)).sqrt()
+    }
+
+    pub fn semimajor_axis(&self) -> f64 {
+        self.p / (1.0 - self.f.powi(2) - self.g.powi(2))
+    }
+
+    /// Longitude of ascending node + argument of periapsis, 0 for circular orbits
+    pub fn longitude_of_periapsis(&self) -> f64 {
+        self.g.atan2(self.f)
+    }
+
+    /// Distance to the parent at the current true longitude
+    pub fn radius(&self) -> f64 {
+        self.p / (1.0 + self.f * self.true_longitude.cos() + self.g * self.true_longitude.sin())
+    }
+
+    /// Longitude of periapsis + mean anomaly, grows linearly with time
+    pub fn mean_longitude(&self) -> f64 {
+        let longitude_of_periapsis = self.longitude_of_periapsis();
+        let mean_anomaly = self.mean_anomaly_at(self.true_longitude - longitude_of_periapsis);
+
+        (longitude_of_periapsis + mean_anomaly).rem_euclid(2.0 * PI)
+    }
+
+    /// Mean anomaly at the given true anomaly of this orbit
+    pub fn mean_anomaly_at(&self, true_anomaly: f64) -> f64 {
+        let eccentricity = self.eccentricity();
+
+        // https://en.wikipedia.org/wiki/Eccentric_anomaly
+        let eccentric_anomaly = 2.0
+            * ((1.0 - eccentricity).sqrt() * (true_anomaly / 2.0).sin())
+                .atan2((1.0 + eccentricity).sqrt() * (true_anomaly / 2.0).cos());
+        eccentric_anomaly - eccentricity * eccentric_anomaly.sin()
+    }
+
+    /// Moves the object along the orbit so that it is at the given mean longitude
+    pub fn set_mean_longitude(&mut self, mean_longitude: f64) -> Result<(), KeplerError> {
+        let solution = crate::solver::solve_kepler(
+            mean_longitude - self.longitude_of_periapsis(),
+            self.eccentricity(),
+            ECCENTRIC_ANOMALY_TOLERANCE,
+            ECCENTRIC_ANOMALY_MAX_ITERATIONS,
+        );
+        self.set_eccentric_anomaly(usable_eccentric_anomaly(solution)?);
+        Ok(())
+    }
+
+    /// Like `set_mean_longitude` for many orbits at once, solving their Kepler equations together.
+    /// Orbits whose equation could not be solved are left where they were.
+    pub fn set_mean_longitudes(
+        targets: &mut [(&mut EquinoctialElements, f64)],
+    ) -> Vec<Result<(), KeplerError>> {
+        let problems: Vec<_> = targets
+            .iter()
+            .map(|(elements, mean_longitude)| {
+                (
+                    mean_longitude - elements.longitude_of_periapsis(),
+                    elements.eccentricity(),
+                )
+            })
+            .collect();
+        let solutions = crate::solver::solve_kepler_batch(
+            &problems,
+            ECCENTRIC_ANOMALY_TOLERANCE,
+            ECCENTRIC_ANOMALY_MAX_ITERATIONS,
+        );
+
+        targets
+            .iter_mut()
+            .zip(solutions)
+            .map(|((elements, _), solution)| {
+                elements.set_eccentric_anomaly(usable_eccentric_anomaly(solution)?);
+                Ok(())
+            })
+            .collect()
+    }
+
+    fn set_eccentric_anomaly(&mut self, eccentric_anomaly: f64) {
+        let eccentricity = self.eccentricity();
+        // https://es.wikipedia.org/wiki/Anomalía_verdadera
+        let true_anomaly = 2.0
+            * ((1.0 + eccentricity).sqrt() * (eccentric_anomaly / 2.0).sin())
+                .atan2((1.0 - eccentricity).sqrt() * (eccentric_anomaly / 2.0).cos());
+        self.true_longitude = (self.longitude_of_periapsis() + true_anomaly).rem_euclid(2.0 * PI);
+    }
+}
+
+/// Eccentric anomaly of a Kepler solution, or its best estimate when it did not converge
+fn usable_eccentric_anomaly(
+    solution: Result<KeplerSolution, KeplerError>,
+) -> Result<f64, KeplerError> {
+    match solution {
+        Ok(solution) => Ok(solution.eccentric_anomaly),
+        Err(KeplerError::NotConverged { best_estimate, .. }) => {
+            // The estimate is bracketed, so it is still close to the root
+            warn!("Kepler solver did not converge, using best estimate");
+            Ok(best_estimate)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// The orbital math uses a right handed frame with Z as the north pole, while the game world is
+/// Y up. Reference X maps to world X, reference Y to world Z and reference Z to world -Y.
+pub(crate) fn world_from_reference() -> Rotation3<f64> {
+    Rotation3::from_matrix_unchecked(Matrix3::new(
+        1.0, 0.0, 0.0, //
+        0.0, 0.0, -1.0, //
+        0.0, 1.0, 0.0,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUN_GRAVITATIONAL_PARAMETER: f64 = 1.32712440018e20;
+
+    fn assert_vectors_close(a: Vector3<f64>, b: Vector3<f64>, tolerance: f64) {
+        assert!(
+            (a - b).magnitude() <= tolerance * b.magnitude(),
+            "{a:?} differs from {b:?}"
+        );
+    }
+
+    fn classical(
+        eccentricity: f64,
+        inclination: f64,
+        longitude_of_ascending_node: f64,
+        argument_of_periapsis: f64,
+        true_anomaly: f64,
+    ) -> ClassicalElements {
+        ClassicalElements {
+            semimajor_axis: 1.5e11,
+            eccentricity,
+            inclination,
+            longitude_of_ascending_node,
+            argument_of_periapsis,
+            true_anomaly,
+        }
+    }
+
+    #[test]
+    fn state_vectors_round_trip() {
+        let cases = [
+            // Circular and equatorial, where the classical elements are singular
+            classical(0.0, 0.0, 0.0, 0.0, 1.0),
+            classical(0.0, 0.3, 1.2, 0.0, 4.0),
+            classical(0.2, 0.0, 0.0, 2.0, 3.0),
+            // Regular, polar and highly eccentric
+            classical(0.0167, 0.1, 0.5, 1.5, 0.3),
+            classical(0.5, PI / 2.0, 2.0, 5.0, 6.0),
+            classical(0.99, 2.5, 4.0, 1.0, 0.1),
+        ];
+
+        for elements in cases {
+            let equinoctial = EquinoctialElements::from_classical(&elements).unwrap();
+            let (position, velocity) = equinoctial.to_state_vectors(SUN_GRAVITATIONAL_PARAMETER);
+            let recovered = EquinoctialElements::from_state_vectors(
+                position,
+                velocity,
+                SUN_GRAVITATIONAL_PARAMETER,
+            )
+            .unwrap();
+            let (recovered_position, recovered_velocity) =
+                recovered.to_state_vectors(SUN_GRAVITATIONAL_PARAMETER);
+
+            assert_vectors_close(recovered_position, position, 1e-12);
+            assert_vectors_close(recovered_velocity, velocity, 1e-12);
+
+            let recovered_classical = recovered.to_classical();
+            assert!((recovered_classical.semimajor_axis / 1.5e11 - 1.0).abs() < 1e-9);
+            assert!((recovered_classical.eccentricity - elements.eccentricity).abs() < 1e-12);
+            assert!((recovered_classical.inclination - elements.inclination).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn circular_equatorial_orbit_has_no_nans() {
+        let position = Vector3::new(1.5e11, 0.0, 0.0);
+        let speed = (SUN_GRAVITATIONAL_PARAMETER / 1.5e11).sqrt();
+        let velocity = Vector3::new(0.0, speed, 0.0);
+
+        let elements = EquinoctialElements::from_state_vectors(
+            position,
+            velocity,
+            SUN_GRAVITATIONAL_PARAMETER,
+        )
+        .unwrap();
+        let classical = elements.to_classical();
+
+        assert!(elements.f.abs() < 1e-12 && elements.g.abs() < 1e-12);
+        assert_eq!((elements.h, elements.k), (0.0, 0.0));
+        for value in [
+            classical.semimajor_axis,
+            classical.eccentricity,
+            classical.inclination,
+            classical.longitude_of_ascending_node,
+            classical.argument_of_periapsis,
+            classical.true_anomaly,
+        ] {
+            assert!(value.is_finite());
+        }
+    }
+
+    #[test]
+    fn mean_longitude_round_trip() {
+        let mut elements =
+            EquinoctialElements::from_classical(&classical(0.6, 0.4, 1.0, 2.0, 0.0)).unwrap();
+        for i in 0..100 {
+            let mean_longitude = i as f64 * 2.0 * PI / 100.0;
+            elements.set_mean_longitude(mean_longitude).unwrap();
+            let difference = (elements.mean_longitude() - mean_longitude).rem_euclid(2.0 * PI);
+            assert!(difference < 1e-10 || 2.0 * PI - difference < 1e-10);
+        }
+    }
+
+    #[test]
+    fn rejects_retrograde_equatorial() {
+        assert_eq!(
+            EquinoctialElements::from_classical(&classical(0.1, PI, 0.0, 0.0, 0.0)),
+            Err(OrbitError::RetrogradeEquatorial)
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use crate::solver::KeplerError;
+
+/// Everything that can go wrong when creating, converting or stepping an `Orbit`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrbitError {
+    /// An input was NaN or infinite, holds the name of the input
+    NonFinite(&'static str),
+    /// Eccentricity below zero does not make physical sense
+    NegativeEccentricity(f64),
+    /// Only closed (elliptic) orbits can be propagated on rails for now
+    UnsupportedEccentricity(f64),
+    /// Semimajor axis must be positive for elliptic orbits
+    InvalidSemimajorAxis(f64),
+    /// The parent body has no mass, nothing to orbit around
+    InvalidGravitationalParameter(f64),
+    /// The object sits exactly on its parent's center
+    ZeroRadius,
+    /// Position and velocity are parallel, the trajectory is a straight line through the parent
+    RadialTrajectory,
+    /// The orbit is equatorial and retrograde, the equinoctial elements are singular there
+    RetrogradeEquatorial,
+    /// The orbit lies on the reference plane, so it never crosses it
+    UndefinedAscendingNode,
+    /// Kepler's equation could not be solved
+    Kepler(KeplerError),
+}
+
+impl std::fmt::Display for OrbitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NonFinite(name) => write!(f, "{name} is not a finite number"),
+            Self::NegativeEccentricity(eccentricity) => {
+                write!(f, "eccentricity {eccentricity} is negative")
+            }
+            Self::UnsupportedEccentricity(eccentricity) => write!(
+                f,
+                "eccentricity {eccentricity} describes an open trajectory, only elliptic orbits are supported"
+            ),
+            Self::InvalidSemimajorAxis(semimajor_axis) => {
+                write!(f, "semimajor axis {semimajor_axis} must be positive")
+            }
+            Self::InvalidGravitationalParameter(standard_gravitational_parameter) => write!(
+                f,
+                "parent gravitational parameter {standard_gravitational_parameter} must be positive"
+            ),
+            Self::ZeroRadius => write!(f, "object is at the center of its parent"),
+            Self::RadialTrajectory => write!(f, "trajectory is radial, it has no orbital plane"),
+            Self::RetrogradeEquatorial => {
+                write!(f, "retrograde equatorial orbits are not supported")
+            }
+            Self::UndefinedAscendingNode => {
+                write!(f, "equatorial orbits have no ascending node")
+            }
+            Self::Kepler(error) => write!(f, "could not solve Kepler's equation: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for OrbitError {}
+
+impl From<KeplerError> for OrbitError {
+    fn from(error: KeplerError) -> Self {
+        Self::Kepler(error)
+    }
+}
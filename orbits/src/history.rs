@@ -0,0 +1,406 @@
+use std::collections::{HashSet, VecDeque};
+
+use bevy::prelude::*;
+
+use crate::{Attitude, AutoRails, Frame, Orbit, SimulationClock, StabilityAssist, Vessel};
+
+/// State of the orbits that can leave their rails at an instant, along with the propellant,
+/// throttle and attitude of the objects that have them so that a burn can be retried from the
+/// same state. Orbits that stay on rails are not copied, rewinding steps them back instead.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// Seconds of the `SimulationClock` when it was taken
+    pub epoch: f64,
+    objects: Vec<(Entity, RecordedObject)>,
+}
+
+#[derive(Debug, Clone)]
+struct RecordedObject {
+    orbit: Orbit,
+    vessel: Option<Vessel>,
+    attitude: Option<Attitude>,
+    stability_assist: Option<StabilityAssist>,
+}
+
+impl Snapshot {
+    pub fn orbits(&self) -> impl Iterator<Item = (Entity, &Orbit)> {
+        self.objects
+            .iter()
+            .map(|(entity, object)| (*entity, &object.orbit))
+    }
+
+    pub fn vessels(&self) -> impl Iterator<Item = (Entity, &Vessel)> {
+        self.objects
+            .iter()
+            .filter_map(|(entity, object)| Some((*entity, object.vessel.as_ref()?)))
+    }
+}
+
+/// Components recorded in a `Snapshot`
+type RecordedComponents = (
+    Entity,
+    &'static Orbit,
+    Option<&'static Vessel>,
+    Option<&'static Attitude>,
+    Option<&'static StabilityAssist>,
+    Has<AutoRails>,
+);
+
+/// Components put back by a `Rewind`
+type RestoredComponents = (
+    Entity,
+    &'static mut Orbit,
+    Option<&'static mut Vessel>,
+    Option<&'static mut Attitude>,
+    Option<&'static mut StabilityAssist>,
+    Has<AutoRails>,
+);
+
+/// Objects on rails that nothing can take off them, their state only depends on the clock
+fn stays_on_rails(orbit: &Orbit, vessel: bool, attitude: bool, auto_rails: bool) -> bool {
+    matches!(orbit.frame(), Frame::Orbit { .. }) && !vessel && !attitude && !auto_rails
+}
+
+/// Ring buffer of snapshots taken periodically while the clock is running.
+/// After rewinding, the snapshots newer than the restored one are kept until the clock runs
+/// again, so it is possible to scrub back and forth before resuming.
+#[derive(Resource)]
+pub struct History {
+    snapshots: VecDeque<Snapshot>,
+    /// Snapshots kept before the oldest ones are dropped
+    capacity: usize,
+    /// Real seconds between snapshots
+    interval: f64,
+    since_last_snapshot: f64,
+    /// Snapshot restored by the last rewind
+    cursor: Option<usize>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(600, 1.0)
+    }
+}
+
+impl History {
+    pub fn new(capacity: usize, interval: f64) -> Self {
+        Self {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+            interval,
+            since_last_snapshot: 0.0,
+            cursor: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// Oldest snapshot first
+    pub fn get(&self, index: usize) -> Option<&Snapshot> {
+        self.snapshots.get(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Snapshot> {
+        self.snapshots.iter()
+    }
+
+    /// Snapshot restored by the last rewind, if the clock has not run since
+    pub fn cursor(&self) -> Option<usize> {
+        self.cursor
+    }
+
+    fn push(&mut self, snapshot: Snapshot) {
+        // Resuming after a rewind starts a new timeline
+        if let Some(cursor) = self.cursor.take() {
+            self.snapshots.truncate(cursor + 1);
+        }
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+}
+
+/// Restores the snapshot at the given index of the `History`, the clock is paused on it
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rewind(pub usize);
+
+/// Takes a snapshot every `History::interval` real seconds while the clock is running
+pub fn record_history(
+    mut history: ResMut<History>,
+    clock: Res<SimulationClock>,
+    time: Res<Time>,
+    objects: Query<RecordedComponents>,
+) {
+    if clock.is_paused() {
+        return;
+    }
+    history.since_last_snapshot += time.delta_secs_f64();
+    let latest_epoch = history
+        .cursor
+        .and_then(|cursor| history.get(cursor))
+        .or(history.snapshots.back())
+        .map(|snapshot| snapshot.epoch);
+    if let Some(epoch) = latest_epoch
+        && (epoch == clock.seconds() || history.since_last_snapshot < history.interval)
+    {
+        return;
+    }
+
+    history.since_last_snapshot = 0.0;
+    history.push(Snapshot {
+        epoch: clock.seconds(),
+        objects: objects
+            .iter()
+            .filter(|(_, orbit, vessel, attitude, _, auto_rails)| {
+                !stays_on_rails(orbit, vessel.is_some(), attitude.is_some(), *auto_rails)
+            })
+            .map(|(entity, orbit, vessel, attitude, stability_assist, _)| {
+                let object = RecordedObject {
+                    orbit: orbit.clone(),
+                    vessel: vessel.copied(),
+                    attitude: attitude.copied(),
+                    stability_assist: stability_assist.copied(),
+                };
+                (entity, object)
+            })
+            .collect(),
+    });
+}
+
+/// Puts every recorded object and the clock back to the requested snapshot, objects that stay
+/// on rails are stepped back to its epoch. Entities that no longer exist are skipped, the ones
+/// that can leave their rails and were created afterwards are left untouched.
+pub fn apply_rewind(
+    mut requests: EventReader<Rewind>,
+    mut history: ResMut<History>,
+    mut clock: ResMut<SimulationClock>,
+    mut objects: Query<RestoredComponents>,
+) {
+    let Some(Rewind(index)) = requests.read().last().copied() else {
+        return;
+    };
+    let Some(snapshot) = history.get(index) else {
+        warn!("There is no snapshot {index} to rewind to");
+        return;
+    };
+
+    for (entity, recorded) in &snapshot.objects {
+        let Ok((_, mut orbit, vessel, attitude, stability_assist, _)) = objects.get_mut(*entity)
+        else {
+            continue;
+        };
+        *orbit = recorded.orbit.clone();
+        if let (Some(mut vessel), Some(recorded)) = (vessel, recorded.vessel) {
+            *vessel = recorded;
+        }
+        if let (Some(mut attitude), Some(recorded)) = (attitude, recorded.attitude) {
+            *attitude = recorded;
+        }
+        if let (Some(mut stability_assist), Some(recorded)) =
+            (stability_assist, recorded.stability_assist)
+        {
+            *stability_assist = recorded;
+        }
+    }
+
+    let recorded: HashSet<Entity> = snapshot.objects.iter().map(|(entity, _)| *entity).collect();
+    let mut on_rails: Vec<(Entity, Mut<Orbit>)> = objects
+        .iter_mut()
+        .filter(|(entity, orbit, vessel, attitude, _, auto_rails)| {
+            !recorded.contains(entity)
+                && stays_on_rails(orbit, vessel.is_some(), attitude.is_some(), *auto_rails)
+        })
+        .map(|(entity, orbit, ..)| (entity, orbit))
+        .collect();
+    let mut orbits: Vec<&mut Orbit> = on_rails.iter_mut().map(|(_, orbit)| &mut **orbit).collect();
+    for (index, error) in Orbit::step_on_rails(&mut orbits, snapshot.epoch - clock.seconds()) {
+        warn!(
+            "Could not rewind the orbit of {}: {error}",
+            on_rails[index].0
+        );
+    }
+    clock.rewind_to(snapshot.epoch);
+    history.cursor = Some(index);
+    history.since_last_snapshot = 0.0;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use nalgebra::Vector3;
+
+    use super::*;
+    use crate::{Body, OrbitPlugin, OrbitsAround, SimulationClock, TimeSpeed};
+
+    /// Virtual time never advances more than 250 ms per frame.
+    /// The satellite can leave its rails, the moon can not.
+    fn app() -> (App, Entity, Entity) {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, OrbitPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                250,
+            )))
+            .insert_resource(History::new(3, 0.25))
+            .insert_resource(TimeSpeed(100.0));
+        let earth_body = Body::new(5.97219e24);
+        let earth = app.world_mut().spawn(earth_body).id();
+        let orbit = Orbit::new_orbit(
+            2.0e7,
+            0.2,
+            0.0,
+            0.0,
+            0.0,
+            &earth_body,
+            &SimulationClock::default(),
+            0.0,
+        )
+        .unwrap();
+        let satellite = app
+            .world_mut()
+            .spawn((orbit.clone(), OrbitsAround(earth), AutoRails::default()))
+            .id();
+        let moon = app.world_mut().spawn((orbit, OrbitsAround(earth))).id();
+        (app, satellite, moon)
+    }
+
+    #[test]
+    fn keeps_the_latest_snapshots() {
+        let (mut app, _, _) = app();
+        for _ in 0..10 {
+            app.update();
+        }
+
+        let history = app.world().resource::<History>();
+        assert_eq!(history.len(), 3);
+        let epochs: Vec<f64> = history.iter().map(|snapshot| snapshot.epoch).collect();
+        assert!(epochs.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(
+            *epochs.last().unwrap(),
+            app.world().resource::<SimulationClock>().seconds()
+        );
+    }
+
+    #[test]
+    fn rewinds_and_resumes() {
+        let (mut app, satellite, _) = app();
+        for _ in 0..3 {
+            app.update();
+        }
+        let snapshot = app.world().resource::<History>().get(1).unwrap().clone();
+        let (_, recorded) = snapshot
+            .orbits()
+            .find(|(entity, _)| *entity == satellite)
+            .unwrap();
+        let recorded_position = recorded.position();
+
+        app.world_mut().send_event(Rewind(1));
+        app.update();
+
+        let clock = app.world().resource::<SimulationClock>();
+        assert!(clock.is_paused());
+        assert_eq!(clock.seconds(), snapshot.epoch);
+        let orbit = app.world().get::<Orbit>(satellite).unwrap();
+        assert_eq!(orbit.position(), recorded_position);
+        // Newer snapshots are kept while paused
+        assert_eq!(app.world().resource::<History>().len(), 3);
+
+        app.world_mut().resource_mut::<SimulationClock>().resume();
+        app.update();
+        let history = app.world().resource::<History>();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.cursor(), None);
+        assert_eq!(history.get(1).unwrap().epoch, snapshot.epoch);
+        assert!(history.get(2).unwrap().epoch > snapshot.epoch);
+    }
+
+    #[test]
+    fn rewinding_refills_the_tank() {
+        let (mut app, satellite, _) = app();
+        let mut vessel = Vessel::new(1000.0, 500.0, 2.0e4, 300.0).unwrap();
+        vessel.set_throttle(1.0).unwrap();
+        app.world_mut().entity_mut(satellite).insert((
+            vessel,
+            Attitude::new(Vector3::repeat(1.0e3), Vector3::repeat(1.0e2)).unwrap(),
+            StabilityAssist::Prograde,
+        ));
+        // Snapshot taken on the first frame of the burn
+        app.update();
+        let snapshot = app.world().resource::<History>().get(0).unwrap().clone();
+        let (_, recorded) = snapshot.vessels().next().unwrap();
+        let recorded = *recorded;
+
+        app.update();
+        app.update();
+        app.world_mut()
+            .entity_mut(satellite)
+            .insert(StabilityAssist::Retrograde);
+        app.world_mut()
+            .get_mut::<Vessel>(satellite)
+            .unwrap()
+            .set_throttle(0.0)
+            .unwrap();
+        let burnt = *app.world().get::<Vessel>(satellite).unwrap();
+        assert!(burnt.propellant_mass() < recorded.propellant_mass());
+
+        app.world_mut().send_event(Rewind(0));
+        app.update();
+
+        assert_eq!(*app.world().get::<Vessel>(satellite).unwrap(), recorded);
+        assert_eq!(
+            app.world().get::<Vessel>(satellite).unwrap().throttle(),
+            1.0
+        );
+        assert_eq!(
+            *app.world().get::<StabilityAssist>(satellite).unwrap(),
+            StabilityAssist::Prograde
+        );
+        let (_, orbit) = snapshot
+            .orbits()
+            .find(|(entity, _)| *entity == satellite)
+            .unwrap();
+        assert_eq!(
+            app.world().get::<Orbit>(satellite).unwrap().position(),
+            orbit.position()
+        );
+    }
+
+    #[test]
+    fn steps_back_orbits_on_rails() {
+        let (mut app, satellite, moon) = app();
+        app.update();
+        app.update();
+        let moon_position = app.world().get::<Orbit>(moon).unwrap().position();
+        let epoch = app.world().resource::<SimulationClock>().seconds();
+        app.update();
+        app.update();
+        let history = app.world().resource::<History>();
+        let index = history
+            .iter()
+            .position(|snapshot| snapshot.epoch == epoch)
+            .unwrap();
+        assert!(history.iter().all(|snapshot| {
+            snapshot.orbits().any(|(entity, _)| entity == satellite)
+                && snapshot.orbits().all(|(entity, _)| entity != moon)
+        }));
+        assert_ne!(
+            app.world().get::<Orbit>(moon).unwrap().position(),
+            moon_position
+        );
+
+        app.world_mut().send_event(Rewind(index));
+        app.update();
+
+        let rewound = app.world().get::<Orbit>(moon).unwrap().position();
+        assert!((rewound - moon_position).norm() < 1.0e-6 * moon_position.norm());
+    }
+}
@@ -1,88 +1,155 @@
 use bevy::prelude::*;
 
+/// Position and velocity relative to the parent, in world axes
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct StateVectors {
+    pub position: nalgebra::Vector3<f64>,
+    pub velocity: nalgebra::Vector3<f64>,
+}
+
 /// Represents the type of reference frame for the movement.
-/// Determines how the object's movement is interpreted in relation to a reference frame.
-#[derive(Reflect, PartialEq)]
+/// Determines how the object's movement is interpreted in relation to a reference frame, each
+/// variant holds the state that is simulated in it. The other representation can always be derived
+/// from it with `Orbit::state_vectors` and `Orbit::equinoctial_elements`.
+#[derive(Reflect, PartialEq, Debug, Clone, Copy)]
 pub enum Frame {
     /// Simulates movement dynamicaly.
-    Free,
+    Free(#[reflect(ignore)] StateVectors),
     /// Simulates movement based on orbital mechanics.
-    Orbit,
+    Orbit {
+        /// Stored as equinoctial elements so circular and equatorial orbits have no singularities.
+        /// The true longitude is the current position along the orbit
+        elements: EquinoctialElements,
+        /// Advances linearly with time, the true longitude is solved from it
+        mean_longitude: f64,
+        /// https://es.wikipedia.org/wiki/Movimiento_medio_diario
+        mean_movement: f64,
+    },
 }
 
 /// Represents a movement within the game.
 /// The object's position and movement can be updated over time, relative to its parent's position and motion.
-#[derive(Component, Reflect)]
+/// The parent is the entity pointed by the `OrbitsAround` relationship.
+#[derive(Component, Reflect, Debug, Clone)]
+#[require(AbsolutePosition)]
 pub struct Orbit {
-    x: f64,
-    y: f64,
-    z: f64,
-    vx: Option<f64>,
-    vy: Option<f64>,
-    vz: Option<f64>,
-    velocity: f64,
-
-    /// https://es.wikipedia.org/wiki/Semieje_mayor
-    semimajor_axis: Option<f64>,
-    /// https://es.wikipedia.org/wiki/Excentricidad_orbital
-    eccentricity: Option<f64>,
-    /// https://es.wikipedia.org/wiki/Argumento_del_periastro
-    argument_of_periapsis: Option<f64>,
-    /// https://es.wikipedia.org/wiki/Inclinaci%C3%B3n_orbital
-    inclination: Option<f64>,
-    /// https://es.wikipedia.org/wiki/Longitud_del_nodo_ascendente
-    longitude_of_ascending_node: Option<f64>,
-
-    mean_movement: Option<f64>,
-
-    current_mean_anomaly: f64,
-    current_eccentric_anomaly: f64,
-    radius: f64,
-    /// How the object should behave
+    /// How the object should behave, along with its current state
     frame: Frame,
+    /// Cached rotation from the orbital plane to the parent's frame, only meaningful on rails
+    #[reflect(ignore)]
+    orbital_plane_rotation: nalgebra::Rotation3<f64>,
+    /// Gravitational parameter of the parent body, copied when the orbit is created so that
+    /// propagation does not need to look the parent up
+    parent_gravitational_parameter: f64,
+    /// Scales the parent's gravitational parameter. It is 1 for regular orbits, members of a binary
+    /// system use it to feel only their companion's pull while orbiting the shared barycenter
+    gravitational_parameter_factor: f64,
     /// When did this movement start
     epoch: f64,
-    #[reflect(ignore)]
-    parent: std::sync::Arc<std::sync::RwLock<Body>>,
 }
 
 /// Represents the central object that an orbiting object revolves around.
 /// Usualy a Star/Planet/Moon
 /// This object has properties like mass and rotation period that influence the orbit.
-#[derive(Reflect, Default)]
+/// A body that moves has an `Orbit` component on the same entity.
+#[derive(Component, Reflect, Debug, Default, Clone, Copy)]
+#[require(AbsolutePosition)]
 pub struct Body {
     standard_gravitational_parameter: f64,
-    pub orbit: Option<Orbit>,
+    /// Mean radius of the surface, 0 for points like barycenters
+    radius: f64,
 }
 
-/// Wrapper component arround a body, it represents any body that does not change
-#[derive(Component)]
-pub struct Planet(pub std::sync::Arc<std::sync::RwLock<Body>>);
+/// Points to the body an entity orbits around.
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+#[relationship(relationship_target = Satellites)]
+pub struct OrbitsAround(pub Entity);
+
+/// Every entity orbiting this body.
+#[derive(Component, Reflect, Debug, Default)]
+#[relationship_target(relationship = OrbitsAround)]
+pub struct Satellites(Vec<Entity>);
+
+impl Satellites {
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+/// Planned change of velocity of the entity it is attached to.
+/// Nothing executes it yet, it is used to stop time warp before reaching it.
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq)]
+pub struct ManeuverNode {
+    /// When the burn should happen, in seconds of the `SimulationClock`
+    pub epoch: f64,
+    /// Change of velocity in the parent's frame
+    #[reflect(ignore)]
+    pub delta_v: nalgebra::Vector3<f64>,
+}
+
+/// Position relative to the root of the hierarchy.
+/// Computed once per frame, after the orbits have been stepped.
+#[derive(Component, Default, Clone, Copy, Debug)]
+pub struct AbsolutePosition(pub nalgebra::Vector3<f64>);
 
 impl Body {
-    pub fn new(mass: f64, orbit: Option<Orbit>) -> Self {
+    pub fn new(mass: f64) -> Self {
         Self {
             standard_gravitational_parameter: mass * G,
-            orbit,
+            radius: 0.0,
         }
     }
-}
 
-impl Planet {
-    pub fn new(mass: f64, orbit: Option<Orbit>) -> Self {
-        Self(std::sync::Arc::new(std::sync::RwLock::new(Body::new(
-            mass, orbit,
-        ))))
+    pub fn with_radius(mut self, radius: f64) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    /// Common center of mass of a binary system.
+    /// Objects orbiting it feel the combined gravitational parameter of both members.
+    pub fn new_barycenter(primary_mass: f64, secondary_mass: f64) -> Self {
+        Self::new(primary_mass + secondary_mass)
     }
 }
 
+mod attitude;
 mod basics;
+mod ephemeris;
+mod equinoctial;
+mod error;
+mod history;
+mod perturbation;
 mod plugin;
+mod radiation;
+mod rails;
 mod solver;
 mod time;
+mod vessel;
+mod warp;
+mod warp_to;
 
-pub use crate::plugin::OrbitPlugin;
-pub use crate::time::{DeltaTime, TimeSpeed};
+pub use crate::attitude::{Attitude, StabilityAssist};
+pub use crate::ephemeris::{ASTRONOMICAL_UNIT, MeanElements, PlanetEphemeris};
+pub use crate::equinoctial::{ClassicalElements, EquinoctialElements};
+pub use crate::error::OrbitError;
+pub use crate::history::{History, Rewind, Snapshot};
+pub use crate::perturbation::{
+    GlobalPerturbations, Perturbation, PerturbationContext, Perturbations,
+};
+pub use crate::plugin::{OrbitPlugin, OrbitSet};
+pub use crate::radiation::{Illumination, LightSource, RadiationPressure, Shadow, eclipse};
+pub use crate::rails::{AutoRails, RailsPolicy};
+pub use crate::solver::{KeplerError, KeplerSolution, solve_kepler, solve_kepler_batch};
+pub use crate::time::{
+    DeltaTime, J2000_JULIAN_DATE, SimulationClock, TimeSpeed, julian_date_from_utc,
+};
+pub use crate::vessel::{STANDARD_GRAVITY, Vessel, VesselError};
+pub use crate::warp::{TimeWarpReduced, WarpLimitReason, WarpPolicy};
+pub use crate::warp_to::{WarpEvent, WarpTo, WarpToError, time_to_sphere_of_influence_change};
 
 /// https://es.wikipedia.org/wiki/Constante_de_gravitación_universal
 const G: f64 = 6.67430e-11;
@@ -90,17 +157,451 @@ const G: f64 = 6.67430e-11;
 #[cfg(test)]
 mod tests {
     use std::f64::consts::PI;
-    use std::sync::{Arc, RwLock};
 
     use super::*;
 
+    fn mean_movement(orbit: &Orbit) -> f64 {
+        match orbit.frame() {
+            Frame::Orbit { mean_movement, .. } => *mean_movement,
+            Frame::Free(_) => panic!("Orbit should be on rails"),
+        }
+    }
+
     #[test]
     fn create_orbit() {
-        let sun = Arc::new(RwLock::new(Body::new(1.9891e30, None)));
-        let mut earth = Orbit::new_orbit(149_598_023e3, 0.017, PI / 2.0, 0.0, 0.0, sun, 0.0, 0.0);
+        let sun = Body::new(1.9891e30);
+        let mut earth = Orbit::new_orbit(
+            149_598_023e3,
+            0.017,
+            PI / 2.0,
+            0.0,
+            0.0,
+            &sun,
+            &SimulationClock::default(),
+            0.0,
+        )
+        .unwrap();
 
-        assert_eq!(Some(1.9913261148403696e-7), earth.mean_movement);
+        assert_eq!(1.9913261148403696e-7, mean_movement(&earth));
         // Step about a year
-        earth.step(3.154e7);
+        earth.step(3.154e7).unwrap();
+    }
+
+    #[test]
+    fn binary_orbits_barycenter() {
+        let primary_mass = 5.0e24;
+        let secondary_mass = 1.0e24;
+        let barycenter = Body::new_barycenter(primary_mass, secondary_mass);
+        let (mut primary, mut secondary) = Orbit::new_binary_pair(
+            1.0e9,
+            0.2,
+            0.3,
+            0.1,
+            0.5,
+            primary_mass,
+            secondary_mass,
+            &barycenter,
+            &SimulationClock::default(),
+            0.0,
+        )
+        .unwrap();
+
+        for _ in 0..10 {
+            primary.step(1.0e4).unwrap();
+            secondary.step(1.0e4).unwrap();
+
+            // The center of mass stays on the barycenter
+            let center_of_mass =
+                primary.position() * primary_mass + secondary.position() * secondary_mass;
+            assert!(center_of_mass.amax() < 1e24);
+        }
+
+        // Both members share the period of the relative orbit
+        let total_gravitational_parameter = (primary_mass + secondary_mass) * G;
+        let expected_mean_movement = (total_gravitational_parameter / 1.0e9_f64.powi(3)).sqrt();
+        assert!((mean_movement(&primary) - expected_mean_movement).abs() < 1e-15);
+        assert!((mean_movement(&secondary) - expected_mean_movement).abs() < 1e-15);
+    }
+
+    #[test]
+    fn batched_steps_match_single_steps() {
+        let sun = Body::new(1.989e30);
+        let clock = SimulationClock::default();
+        let mut single: Vec<Orbit> = (0..50)
+            .map(|i| {
+                Orbit::new_orbit(
+                    1.0e11 + 1.0e9 * i as f64,
+                    0.019 * i as f64,
+                    0.1 * i as f64,
+                    0.03 * i as f64,
+                    0.2 * i as f64,
+                    &sun,
+                    &clock,
+                    0.0,
+                )
+                .unwrap()
+            })
+            .collect();
+        single.push(
+            Orbit::new_free(
+                nalgebra::Vector3::new(1.0e11, 0.0, 0.0),
+                nalgebra::Vector3::new(0.0, 0.0, 3.0e4),
+                &sun,
+            )
+            .unwrap(),
+        );
+        let mut batched = single.clone();
+
+        for _ in 0..20 {
+            for orbit in single
+                .iter_mut()
+                .filter(|orbit| matches!(orbit.frame(), Frame::Orbit { .. }))
+            {
+                orbit.step(1.0e6).unwrap();
+            }
+            let mut orbits: Vec<&mut Orbit> = batched.iter_mut().collect();
+            assert!(Orbit::step_on_rails(&mut orbits, 1.0e6).is_empty());
+        }
+
+        for (single, batched) in single.iter().zip(&batched) {
+            assert_eq!(single.frame(), batched.frame());
+        }
+    }
+
+    #[test]
+    fn absolute_positions_follow_hierarchy() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, OrbitPlugin));
+
+        let sun_body = Body::new(1.989e30);
+        let sun = app.world_mut().spawn(sun_body).id();
+
+        let earth_body = Body::new(5.97219e24);
+        let earth_orbit = Orbit::new_orbit(
+            149.598e9,
+            0.0167,
+            0.0,
+            0.0,
+            0.0,
+            &sun_body,
+            &SimulationClock::default(),
+            0.0,
+        )
+        .unwrap();
+        let earth = app
+            .world_mut()
+            .spawn((earth_body, earth_orbit, OrbitsAround(sun)))
+            .id();
+
+        let moon_orbit = Orbit::new_orbit(
+            384.4e6,
+            0.0549,
+            0.0,
+            0.09,
+            0.0,
+            &earth_body,
+            &SimulationClock::default(),
+            0.0,
+        )
+        .unwrap();
+        let moon = app
+            .world_mut()
+            .spawn((Body::new(7.34767309e22), moon_orbit, OrbitsAround(earth)))
+            .id();
+
+        let ship_orbit = Orbit::new_free(
+            nalgebra::Vector3::new(0.0, 0.0, 1.8e6),
+            nalgebra::Vector3::new(1.6e3, 0.0, 0.0),
+            &Body::new(7.34767309e22),
+        )
+        .unwrap();
+        let ship = app.world_mut().spawn((ship_orbit, OrbitsAround(moon))).id();
+
+        app.update();
+
+        let world = app.world();
+        let absolute = |entity| world.get::<AbsolutePosition>(entity).unwrap().0;
+        let relative = |entity| world.get::<Orbit>(entity).unwrap().position();
+
+        assert_eq!(absolute(sun), nalgebra::Vector3::zeros());
+        assert_eq!(absolute(earth), relative(earth));
+        assert_eq!(absolute(moon), absolute(earth) + relative(moon));
+        assert_eq!(absolute(ship), absolute(moon) + relative(ship));
+    }
+
+    #[test]
+    fn rejects_invalid_orbits() {
+        let sun = Body::new(1.989e30);
+
+        assert_eq!(
+            Orbit::new_orbit(
+                1.0e11,
+                -0.1,
+                0.0,
+                0.0,
+                0.0,
+                &sun,
+                &SimulationClock::default(),
+                0.0
+            )
+            .err(),
+            Some(OrbitError::NegativeEccentricity(-0.1))
+        );
+        assert_eq!(
+            Orbit::new_orbit(
+                1.0e11,
+                1.5,
+                0.0,
+                0.0,
+                0.0,
+                &sun,
+                &SimulationClock::default(),
+                0.0
+            )
+            .err(),
+            Some(OrbitError::UnsupportedEccentricity(1.5))
+        );
+        assert_eq!(
+            Orbit::new_orbit(
+                0.0,
+                0.1,
+                0.0,
+                0.0,
+                0.0,
+                &sun,
+                &SimulationClock::default(),
+                0.0
+            )
+            .err(),
+            Some(OrbitError::InvalidSemimajorAxis(0.0))
+        );
+        assert_eq!(
+            Orbit::new_orbit(
+                1.0e11,
+                0.1,
+                f64::NAN,
+                0.0,
+                0.0,
+                &sun,
+                &SimulationClock::default(),
+                0.0
+            )
+            .err(),
+            Some(OrbitError::NonFinite("argument of periapsis"))
+        );
+        assert_eq!(
+            Orbit::new_orbit(
+                1.0e11,
+                0.1,
+                0.0,
+                0.0,
+                0.0,
+                &Body::new(0.0),
+                &SimulationClock::default(),
+                0.0
+            )
+            .err(),
+            Some(OrbitError::InvalidGravitationalParameter(0.0))
+        );
+        assert_eq!(
+            Orbit::new_free(
+                nalgebra::Vector3::zeros(),
+                nalgebra::Vector3::new(1.0, 0.0, 0.0),
+                &sun
+            )
+            .err(),
+            Some(OrbitError::ZeroRadius)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_conversions() {
+        let sun = Body::new(1.989e30);
+
+        let mut radial = Orbit::new_free(
+            nalgebra::Vector3::new(1.0e11, 0.0, 0.0),
+            nalgebra::Vector3::new(1.0e4, 0.0, 0.0),
+            &sun,
+        )
+        .unwrap();
+        assert_eq!(
+            radial.set_orbit(&SimulationClock::default()),
+            Err(OrbitError::RadialTrajectory)
+        );
+        // A failed conversion keeps the object in its previous frame
+        assert!(matches!(radial.frame(), Frame::Free(_)));
+
+        let mut escaping = Orbit::new_free(
+            nalgebra::Vector3::new(1.0e11, 0.0, 0.0),
+            nalgebra::Vector3::new(0.0, 1.0e5, 1.0e5),
+            &sun,
+        )
+        .unwrap();
+        assert!(matches!(
+            escaping.set_orbit(&SimulationClock::default()),
+            Err(OrbitError::UnsupportedEccentricity(_))
+        ));
+    }
+
+    #[test]
+    fn circular_equatorial_free_to_rails() {
+        let earth = Body::new(5.97219e24);
+        let radius = 7.0e6;
+        let speed = (earth.standard_gravitational_parameter / radius).sqrt();
+        let mut orbit = Orbit::new_free(
+            nalgebra::Vector3::new(radius, 0.0, 0.0),
+            nalgebra::Vector3::new(0.0, 0.0, speed),
+            &earth,
+        )
+        .unwrap();
+
+        orbit.set_orbit(&SimulationClock::default()).unwrap();
+        let elements = orbit.classical_elements().unwrap();
+        assert!(elements.eccentricity < 1e-12);
+        assert!(elements.inclination.abs() < 1e-12);
+        assert!((elements.semimajor_axis / radius - 1.0).abs() < 1e-12);
+
+        // A quarter of a period later it should be a quarter of a turn away
+        let period = 2.0 * PI / mean_movement(&orbit);
+        orbit.step(period / 4.0).unwrap();
+        let position = orbit.position();
+        assert!(position.x.abs() < 1e-3 && position.y.abs() < 1e-3);
+        assert!((position.z - radius).abs() < 1e-3);
+
+        orbit.set_free();
+        assert!((orbit.speed() - speed).abs() < 1e-9);
+        assert!((orbit.state_vectors().velocity.x + speed).abs() < 1e-9);
+    }
+
+    #[test]
+    fn nearly_equatorial_orbit_has_no_ascending_node() {
+        let earth = Body::new(5.97219e24);
+        let radius = 7.0e6;
+        let speed = (earth.standard_gravitational_parameter / radius).sqrt();
+        // Off the reference plane only by round-off
+        let mut orbit = Orbit::new_free(
+            nalgebra::Vector3::new(radius, 0.0, 0.0),
+            nalgebra::Vector3::new(0.0, speed * 1e-16, speed),
+            &earth,
+        )
+        .unwrap();
+        orbit.set_orbit(&SimulationClock::default()).unwrap();
+
+        let elements = orbit.equinoctial_elements().unwrap();
+        assert!(elements.h != 0.0 || elements.k != 0.0);
+        assert_eq!(
+            orbit.time_to_ascending_node(),
+            Err(OrbitError::UndefinedAscendingNode)
+        );
+    }
+
+    #[test]
+    fn adding_velocity_frees_the_orbit() {
+        let earth = Body::new(5.97219e24);
+        let mut orbit = Orbit::new_orbit(
+            7.0e6,
+            0.1,
+            0.0,
+            0.2,
+            0.0,
+            &earth,
+            &SimulationClock::default(),
+            0.0,
+        )
+        .unwrap();
+        let before = orbit.state_vectors();
+
+        let delta_v = nalgebra::Vector3::new(1.0, -2.0, 3.0);
+        orbit.add_velocity(delta_v).unwrap();
+        assert!(matches!(orbit.frame(), Frame::Free(_)));
+        let after = orbit.state_vectors();
+        assert_eq!(after.position, before.position);
+        assert!((after.velocity - before.velocity - delta_v).magnitude() < 1e-9);
+        assert_eq!(
+            orbit.add_velocity(nalgebra::Vector3::new(f64::NAN, 0.0, 0.0)),
+            Err(OrbitError::NonFinite("delta_v"))
+        );
+    }
+
+    #[test]
+    fn both_representations_agree() {
+        let earth = Body::new(5.97219e24);
+        let mut clock = SimulationClock::default();
+        clock.advance(1.0e3);
+        let on_rails = Orbit::new_orbit(8.0e6, 0.1, 1.0, 0.5, 2.0, &earth, &clock, 0.0).unwrap();
+
+        // Elements derived from the state vectors of a free copy match the rails ones
+        let mut free = Orbit::new_free(
+            on_rails.state_vectors().position,
+            on_rails.state_vectors().velocity,
+            &earth,
+        )
+        .unwrap();
+        let rails_elements = on_rails.classical_elements().unwrap();
+        let free_elements = free.classical_elements().unwrap();
+        assert!((free_elements.semimajor_axis / rails_elements.semimajor_axis - 1.0).abs() < 1e-9);
+        assert!((free_elements.eccentricity - rails_elements.eccentricity).abs() < 1e-9);
+        assert!((free_elements.inclination - rails_elements.inclination).abs() < 1e-9);
+        assert!((free.position() - on_rails.position()).magnitude() < 1e-3);
+        assert!((free.speed() - on_rails.speed()).abs() < 1e-6);
+
+        // Going back on rails keeps the same state
+        free.set_orbit(&clock).unwrap();
+        assert!((free.position() - on_rails.position()).magnitude() < 1e-3);
+    }
+
+    #[test]
+    fn restored_frames_continue_identically() {
+        let earth = Body::new(5.97219e24);
+        let clock = SimulationClock::default();
+        let on_rails = Orbit::new_orbit(8.0e6, 0.1, 1.0, 0.5, 2.0, &earth, &clock, -300.0).unwrap();
+        let free = Orbit::new_free(
+            nalgebra::Vector3::new(7.0e6, 0.0, 0.0),
+            nalgebra::Vector3::new(0.0, 0.0, 7.5e3),
+            &earth,
+        )
+        .unwrap();
+        for mut original in [on_rails, free] {
+            let mut restored =
+                Orbit::from_frame(*original.frame(), original.epoch(), &earth).unwrap();
+            assert_eq!(restored.frame(), original.frame());
+            assert_eq!(restored.epoch(), original.epoch());
+            assert_eq!(restored.position(), original.position());
+
+            original.step(600.0).unwrap();
+            restored.step(600.0).unwrap();
+            assert_eq!(restored.state_vectors(), original.state_vectors());
+        }
+
+        // Binary members keep feeling only their companion
+        let barycenter = Body::new_barycenter(5.0e24, 1.0e24);
+        let (primary, _) = Orbit::new_binary_pair(
+            1.0e9,
+            0.2,
+            0.3,
+            0.1,
+            0.5,
+            5.0e24,
+            1.0e24,
+            &barycenter,
+            &clock,
+            0.0,
+        )
+        .unwrap();
+        let mut restored = primary.clone();
+        restored.set_free();
+        restored.restore(*primary.frame(), primary.epoch()).unwrap();
+        assert_eq!(restored.state_vectors(), primary.state_vectors());
+
+        // Invalid frames leave the orbit as it was
+        assert_eq!(
+            restored.restore(Frame::Free(StateVectors::default()), 0.0),
+            Err(OrbitError::ZeroRadius)
+        );
+        assert_eq!(restored.frame(), primary.frame());
+        assert_eq!(
+            Orbit::from_frame(*primary.frame(), f64::NAN, &earth).err(),
+            Some(OrbitError::NonFinite("epoch"))
+        );
     }
 }
@@ -0,0 +1,172 @@
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use nalgebra::Vector3;
+
+use crate::{Body, StateVectors};
+
+/// What a perturbation knows about the object it is accelerating
+#[derive(Debug, Clone, Copy)]
+pub struct PerturbationContext<'a> {
+    /// Object being stepped
+    pub entity: Entity,
+    /// Position and velocity relative to the parent
+    pub state: &'a StateVectors,
+    /// Seconds of the `SimulationClock`
+    pub epoch: f64,
+    /// Body the object orbits around
+    pub parent: Entity,
+    pub parent_body: &'a Body,
+    /// Absolute position of the parent at the start of the step
+    pub parent_position: Vector3<f64>,
+}
+
+/// Extra acceleration applied to free objects on top of the gravity of their parent, for
+/// effects like radiation pressure, low-thrust engines or non-spherical gravity fields.
+/// Objects on rails stay Keplerian and are not affected.
+pub trait Perturbation: Send + Sync + 'static {
+    /// Acceleration in m/s², in world axes
+    fn acceleration(&self, context: &PerturbationContext) -> Vector3<f64>;
+}
+
+impl<F> Perturbation for F
+where
+    F: Fn(&PerturbationContext) -> Vector3<f64> + Send + Sync + 'static,
+{
+    fn acceleration(&self, context: &PerturbationContext) -> Vector3<f64> {
+        self(context)
+    }
+}
+
+/// Perturbations that only affect the entity they are attached to.
+/// They are summed with the `GlobalPerturbations`.
+#[derive(Component, Default, Clone)]
+pub struct Perturbations(Vec<Arc<dyn Perturbation>>);
+
+/// Perturbations that affect every free object
+#[derive(Resource, Default, Clone)]
+pub struct GlobalPerturbations(Vec<Arc<dyn Perturbation>>);
+
+impl Perturbations {
+    pub fn new(perturbation: impl Perturbation) -> Self {
+        Self(vec![Arc::new(perturbation)])
+    }
+
+    pub fn with(mut self, perturbation: impl Perturbation) -> Self {
+        self.push(perturbation);
+        self
+    }
+
+    pub fn push(&mut self, perturbation: impl Perturbation) {
+        self.0.push(Arc::new(perturbation));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Perturbation> {
+        self.0.iter().map(|perturbation| perturbation.as_ref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl GlobalPerturbations {
+    pub fn push(&mut self, perturbation: impl Perturbation) {
+        self.0.push(Arc::new(perturbation));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Perturbation> {
+        self.0.iter().map(|perturbation| perturbation.as_ref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::{Orbit, OrbitPlugin, OrbitsAround, TimeSpeed};
+
+    /// A parent so light that its gravity is negligible
+    fn app() -> (App, Entity, Body) {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, OrbitPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .insert_resource(TimeSpeed(10.0));
+        let body = Body::new(1.0);
+        let parent = app.world_mut().spawn(body).id();
+        (app, parent, body)
+    }
+
+    #[test]
+    fn constant_acceleration() {
+        let (mut app, parent, body) = app();
+        let free = Orbit::new_free(Vector3::new(1.0e6, 0.0, 0.0), Vector3::zeros(), &body).unwrap();
+        let object = app
+            .world_mut()
+            .spawn((
+                free,
+                OrbitsAround(parent),
+                Perturbations::new(|_: &PerturbationContext| Vector3::new(0.0, 1.0, 0.0)),
+            ))
+            .id();
+        app.world_mut()
+            .resource_mut::<GlobalPerturbations>()
+            .push(|_: &PerturbationContext| Vector3::new(0.0, 0.0, 2.0));
+
+        for _ in 0..10 {
+            app.update();
+        }
+
+        // The first frame has no delta time
+        let seconds = app.world().resource::<crate::SimulationClock>().seconds();
+        assert!(seconds > 0.0);
+        let state = app.world().get::<Orbit>(object).unwrap().state_vectors();
+        let expected = Vector3::new(0.0, 1.0, 2.0) * seconds;
+        assert!((state.velocity - expected).magnitude() < 1e-9);
+        assert!((state.position.y - 0.5 * seconds.powi(2)).abs() < 1e-6);
+        assert!((state.position.z - seconds.powi(2)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rails_are_not_perturbed() {
+        let (mut app, parent, body) = app();
+        let orbit = Orbit::new_orbit(
+            1.0e3,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            &body,
+            &crate::SimulationClock::default(),
+            0.0,
+        )
+        .unwrap();
+        let mut unperturbed = orbit.clone();
+        let object = app
+            .world_mut()
+            .spawn((
+                orbit,
+                OrbitsAround(parent),
+                Perturbations::new(|_: &PerturbationContext| Vector3::new(1.0, 0.0, 0.0)),
+            ))
+            .id();
+
+        for _ in 0..5 {
+            app.update();
+        }
+
+        let seconds = app.world().resource::<crate::SimulationClock>().seconds();
+        unperturbed.step(seconds).unwrap();
+        let orbit = app.world().get::<Orbit>(object).unwrap();
+        assert!((orbit.position() - unperturbed.position()).magnitude() < 1e-6);
+    }
+}
@@ -1,32 +1,221 @@
 use crate::{
-    Orbit, Planet,
-    time::{DeltaTime, TimeSpeed},
+    AbsolutePosition, Body, Frame, Orbit, OrbitsAround, Satellites,
+    attitude::{Attitude, StabilityAssist, update_attitudes},
+    history::{History, Rewind, apply_rewind, record_history},
+    perturbation::{GlobalPerturbations, PerturbationContext, Perturbations},
+    radiation::{Illumination, LightSource, RadiationPressure, update_illumination},
+    rails::{AutoRails, RailsPolicy, switch_frames},
+    time::{DeltaTime, SimulationClock, TimeSpeed},
+    vessel::Vessel,
+    warp::{TimeWarpReduced, WarpPolicy, limit_time_warp},
+    warp_to::{WarpTo, schedule_warp_to},
 };
-use bevy::prelude::*;
+use bevy::{
+    ecs::batching::BatchingStrategy,
+    prelude::*,
+    tasks::{ComputeTaskPool, ParallelSliceMut},
+};
+
+/// Smallest amount of orbits stepped by a single task, stepping an orbit is cheap so tiny batches
+/// would spend more time scheduling than propagating
+const ORBIT_MIN_BATCH_SIZE: usize = 256;
+/// Orbits on rails whose Kepler equations are solved together by a single task
+const RAILS_BATCH_SIZE: usize = 1024;
 
 pub struct OrbitPlugin;
 
+/// Stages of the orbit simulation, systems that read positions should run after `AbsolutePositions`
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum OrbitSet {
+    /// Rotates objects with an `Attitude`
+    Attitude,
+    /// Switches objects with `AutoRails` between free flight and rails
+    Rails,
+    /// Steps every `Orbit` relative to its parent
+    Propagate,
+    /// Walks the body hierarchy filling `AbsolutePosition`
+    AbsolutePositions,
+}
+
 impl Plugin for OrbitPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<crate::Orbit>()
+            .register_type::<crate::Body>()
+            .register_type::<crate::OrbitsAround>()
+            .register_type::<crate::Satellites>()
             .insert_resource(TimeSpeed::new())
+            .register_type::<SimulationClock>()
             .insert_resource(DeltaTime::new())
-            .add_systems(First, crate::time::update_delta_time)
-            .add_systems(PreUpdate, update_planets)
-            .add_systems(Update, update_orbits);
+            .init_resource::<SimulationClock>()
+            .configure_sets(
+                PreUpdate,
+                (
+                    OrbitSet::Attitude,
+                    OrbitSet::Rails,
+                    OrbitSet::Propagate,
+                    OrbitSet::AbsolutePositions,
+                )
+                    .chain(),
+            )
+            .register_type::<crate::ManeuverNode>()
+            .register_type::<WarpPolicy>()
+            .init_resource::<WarpPolicy>()
+            .add_event::<TimeWarpReduced>()
+            .add_event::<WarpTo>()
+            .init_resource::<History>()
+            .init_resource::<GlobalPerturbations>()
+            .register_type::<LightSource>()
+            .register_type::<Illumination>()
+            .register_type::<RadiationPressure>()
+            .register_type::<Vessel>()
+            .register_type::<Attitude>()
+            .register_type::<StabilityAssist>()
+            .register_type::<RailsPolicy>()
+            .init_resource::<RailsPolicy>()
+            .register_type::<AutoRails>()
+            .add_event::<Rewind>()
+            .add_systems(
+                First,
+                (
+                    apply_rewind,
+                    schedule_warp_to,
+                    limit_time_warp,
+                    crate::time::update_delta_time,
+                )
+                    .chain(),
+            )
+            .add_systems(PreUpdate, update_illumination.before(OrbitSet::Propagate))
+            .add_systems(PreUpdate, update_attitudes.in_set(OrbitSet::Attitude))
+            .add_systems(PreUpdate, switch_frames.in_set(OrbitSet::Rails))
+            .add_systems(PreUpdate, update_orbits.in_set(OrbitSet::Propagate))
+            .add_systems(
+                PreUpdate,
+                update_absolute_positions.in_set(OrbitSet::AbsolutePositions),
+            )
+            .add_systems(PreUpdate, record_history.after(OrbitSet::Propagate));
     }
 }
 
-fn update_orbits(mut query: Query<&mut Orbit>, delta_time: Res<DeltaTime>) {
-    for mut orbit in query.iter_mut() {
-        orbit.step(delta_time.seconds());
+/// Everything that can affect how an orbit is stepped
+type PropagatedOrbit = (
+    Entity,
+    &'static mut Orbit,
+    Option<&'static OrbitsAround>,
+    Option<&'static Perturbations>,
+    Option<&'static RadiationPressure>,
+    Option<&'static mut Vessel>,
+);
+
+/// Orbits only depend on their own state and the one of their parent at the start of the frame,
+/// so they are stepped in parallel batches. Free orbits are integrated one by one with their
+/// perturbations, while the Kepler equations of the ones on rails are solved in batches.
+fn update_orbits(
+    mut query: Query<PropagatedOrbit>,
+    parents: Query<(&Body, &AbsolutePosition)>,
+    global_perturbations: Res<GlobalPerturbations>,
+    clock: Res<SimulationClock>,
+    delta_time: Res<DeltaTime>,
+) {
+    let seconds = delta_time.seconds();
+    // The clock has already been advanced for this frame
+    let start_epoch = clock.seconds() - seconds;
+    query
+        .par_iter_mut()
+        .batching_strategy(BatchingStrategy::new().min_batch_size(ORBIT_MIN_BATCH_SIZE))
+        .for_each(
+            |(entity, mut orbit, orbits_around, perturbations, radiation_pressure, vessel)| {
+                if !matches!(orbit.frame(), Frame::Free(_)) {
+                    return;
+                }
+                let perturbations = perturbations.filter(|perturbations| !perturbations.is_empty());
+                let radiation_pressure = radiation_pressure.map(RadiationPressure::acceleration);
+                let mut vessel = vessel.filter(|vessel| vessel.is_burning());
+                let engine = vessel
+                    .as_deref()
+                    .map(|vessel| vessel.thrust_acceleration(seconds));
+                let parent = orbits_around.and_then(|orbits_around| {
+                    Some((orbits_around.0, parents.get(orbits_around.0).ok()?))
+                });
+                let result = match parent {
+                    Some((parent, (parent_body, parent_position)))
+                        if perturbations.is_some()
+                            || radiation_pressure.is_some()
+                            || engine.is_some()
+                            || !global_perturbations.is_empty() =>
+                    {
+                        let result = orbit.step_perturbed(seconds, |state, elapsed| {
+                            let context = PerturbationContext {
+                                entity,
+                                state,
+                                epoch: start_epoch + elapsed,
+                                parent,
+                                parent_body,
+                                parent_position: parent_position.0,
+                            };
+                            global_perturbations
+                                .iter()
+                                .chain(perturbations.into_iter().flat_map(Perturbations::iter))
+                                .map(|perturbation| perturbation.acceleration(&context))
+                                .sum::<nalgebra::Vector3<f64>>()
+                                + radiation_pressure.unwrap_or_default()
+                                + engine.unwrap_or_default()
+                        });
+                        // Propellant is only spent when the thrust has been applied
+                        if let (Ok(()), Some(vessel)) = (&result, vessel.as_mut()) {
+                            vessel.burn(seconds);
+                        }
+                        result
+                    }
+                    _ => orbit.step(seconds),
+                };
+                if let Err(error) = result {
+                    error!("Could not step orbit: {error}");
+                }
+            },
+        );
+
+    let mut on_rails: Vec<Mut<Orbit>> = query
+        .iter_mut()
+        .map(|(_, orbit, ..)| orbit)
+        .filter(|orbit| matches!(orbit.frame(), Frame::Orbit { .. }))
+        .collect();
+    let errors =
+        on_rails.par_chunk_map_mut(ComputeTaskPool::get(), RAILS_BATCH_SIZE, |_, batch| {
+            let mut orbits: Vec<&mut Orbit> = batch.iter_mut().map(|orbit| &mut **orbit).collect();
+            Orbit::step_on_rails(&mut orbits, seconds)
+        });
+    for (_, error) in errors.into_iter().flatten() {
+        error!("Could not step orbit: {error}");
     }
 }
 
-fn update_planets(query: Query<&Planet>, delta_time: Res<DeltaTime>) {
-    for body in query.iter() {
-        if let Some(orbit) = &mut body.0.write().unwrap().orbit {
-            orbit.step(delta_time.seconds());
+/// Computes absolute positions from the roots of the hierarchy down, so every parent is
+/// resolved exactly once before its satellites
+fn update_absolute_positions(
+    roots: Query<Entity, (With<Body>, Without<OrbitsAround>)>,
+    satellites: Query<&Satellites>,
+    orbits: Query<&Orbit>,
+    mut positions: Query<&mut AbsolutePosition>,
+) {
+    let mut pending = Vec::new();
+    for root in roots.iter() {
+        pending.push((root, nalgebra::Vector3::zeros()));
+    }
+
+    while let Some((entity, parent_position)) = pending.pop() {
+        let mut position = parent_position;
+        if let Ok(orbit) = orbits.get(entity) {
+            position += orbit.position();
+        }
+
+        if let Ok(mut absolute_position) = positions.get_mut(entity) {
+            absolute_position.0 = position;
+        }
+
+        if let Ok(satellites) = satellites.get(entity) {
+            for satellite in satellites.iter() {
+                pending.push((satellite, position));
+            }
         }
     }
 }
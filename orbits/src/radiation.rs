@@ -0,0 +1,332 @@
+use std::f64::consts::PI;
+
+use bevy::prelude::*;
+use nalgebra::Vector3;
+
+use crate::{AbsolutePosition, Body, Vessel};
+
+/// Speed of light in m/s
+const SPEED_OF_LIGHT: f64 = 299_792_458.0;
+
+/// Marks the `Body` that lights the system. Its radius is used to compute penumbras,
+/// a point light only casts umbras.
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq)]
+#[require(Body)]
+pub struct LightSource {
+    /// Radiated power in W
+    pub luminosity: f64,
+}
+
+impl LightSource {
+    /// https://en.wikipedia.org/wiki/Solar_luminosity
+    pub const SUN: Self = Self {
+        luminosity: 3.828e26,
+    };
+}
+
+impl Default for LightSource {
+    fn default() -> Self {
+        Self::SUN
+    }
+}
+
+/// How the light source is seen from a point
+#[derive(Reflect, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Shadow {
+    /// The whole disk of the light source is visible
+    #[default]
+    Sunlit,
+    /// Part of the disk is hidden behind an occulter
+    Penumbra,
+    /// The occulter is inside the disk, leaving a ring of light
+    Antumbra,
+    /// The light source is completely hidden
+    Umbra,
+}
+
+/// Light received by an object, updated every frame for the entities that have it.
+/// It can be used on its own, for example for solar panels or heating.
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq)]
+#[require(AbsolutePosition)]
+pub struct Illumination {
+    /// Visible fraction of the disk of the light source, from 0 to 1
+    pub fraction: f64,
+    pub shadow: Shadow,
+    /// Body casting the shadow
+    pub occulter: Option<Entity>,
+    /// Distance to the light source
+    pub distance: f64,
+}
+
+impl Default for Illumination {
+    fn default() -> Self {
+        Self {
+            fraction: 1.0,
+            shadow: Shadow::Sunlit,
+            occulter: None,
+            distance: f64::INFINITY,
+        }
+    }
+}
+
+/// Parameters of a free object for solar radiation pressure.
+/// The acceleration is recomputed every frame and applied while propagating free orbits.
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq)]
+#[require(Illumination)]
+pub struct RadiationPressure {
+    /// Cross section facing the light source in m²
+    pub area: f64,
+    /// Fraction of the light that is reflected instead of absorbed, from 0 to 1
+    pub reflectivity: f64,
+    /// Mass in kg, the current one is used instead for entities with a `Vessel`
+    pub mass: f64,
+    #[reflect(ignore)]
+    acceleration: Vector3<f64>,
+}
+
+impl RadiationPressure {
+    pub fn new(area: f64, reflectivity: f64, mass: f64) -> Self {
+        Self {
+            area,
+            reflectivity,
+            mass,
+            acceleration: Vector3::zeros(),
+        }
+    }
+
+    /// Acceleration computed on the last frame, in world axes
+    pub fn acceleration(&self) -> Vector3<f64> {
+        self.acceleration
+    }
+
+    /// Acceleration pushing the object away from the light source.
+    /// `direction` points from the light source to the object and has the distance as magnitude.
+    pub fn acceleration_at(&self, light: &LightSource, direction: Vector3<f64>) -> Vector3<f64> {
+        let distance = direction.magnitude();
+        if self.mass <= 0.0 || distance == 0.0 {
+            return Vector3::zeros();
+        }
+        // https://en.wikipedia.org/wiki/Radiation_pressure
+        let pressure = light.luminosity / (4.0 * PI * distance.powi(2) * SPEED_OF_LIGHT);
+        let coefficient = 1.0 + self.reflectivity.clamp(0.0, 1.0);
+        direction / distance * pressure * coefficient * self.area / self.mass
+    }
+}
+
+/// How much of a light source is seen from `position` with a single spherical occulter in the
+/// way, using the overlap of both apparent disks.
+/// https://en.wikipedia.org/wiki/Umbra,_penumbra_and_antumbra
+pub fn eclipse(
+    position: Vector3<f64>,
+    light_position: Vector3<f64>,
+    light_radius: f64,
+    occulter_position: Vector3<f64>,
+    occulter_radius: f64,
+) -> (Shadow, f64) {
+    let to_light = light_position - position;
+    let to_occulter = occulter_position - position;
+    let light_distance = to_light.magnitude();
+    let occulter_distance = to_occulter.magnitude();
+    if occulter_radius <= 0.0 || occulter_distance >= light_distance {
+        return (Shadow::Sunlit, 1.0);
+    }
+    if occulter_distance <= occulter_radius {
+        return (Shadow::Umbra, 0.0);
+    }
+
+    // Apparent radii and separation of both disks
+    let light = (light_radius / light_distance).min(1.0).asin();
+    let occulter = (occulter_radius / occulter_distance).asin();
+    let separation = to_light
+        .cross(&to_occulter)
+        .magnitude()
+        .atan2(to_light.dot(&to_occulter));
+
+    if separation >= light + occulter {
+        (Shadow::Sunlit, 1.0)
+    } else if separation <= occulter - light {
+        (Shadow::Umbra, 0.0)
+    } else if separation <= light - occulter {
+        (Shadow::Antumbra, 1.0 - (occulter / light).powi(2))
+    } else {
+        // Area of the intersection of two circles
+        let x = (separation.powi(2) + light.powi(2) - occulter.powi(2)) / (2.0 * separation);
+        let y = (light.powi(2) - x.powi(2)).max(0.0).sqrt();
+        let overlap = light.powi(2) * (x / light).clamp(-1.0, 1.0).acos()
+            + occulter.powi(2) * ((separation - x) / occulter).clamp(-1.0, 1.0).acos()
+            - separation * y;
+        (
+            Shadow::Penumbra,
+            (1.0 - overlap / (PI * light.powi(2))).clamp(0.0, 1.0),
+        )
+    }
+}
+
+type IlluminatedObject = (
+    Entity,
+    &'static AbsolutePosition,
+    &'static mut Illumination,
+    Option<&'static mut RadiationPressure>,
+    Option<&'static Vessel>,
+);
+
+/// Computes the `Illumination` of every entity that has one, taking the darkest shadow cast by
+/// any body with a radius, and the radiation pressure of the ones that are affected by it.
+/// Runs before propagation, with the positions of the previous frame.
+pub fn update_illumination(
+    lights: Query<(Entity, &LightSource, &Body, &AbsolutePosition)>,
+    occulters: Query<(Entity, &Body, &AbsolutePosition)>,
+    mut objects: Query<IlluminatedObject>,
+) {
+    let Ok((light_entity, light, light_body, light_position)) = lights.single() else {
+        return;
+    };
+
+    for (entity, position, mut illumination, radiation_pressure, vessel) in objects.iter_mut() {
+        let mut darkest = Illumination {
+            distance: (position.0 - light_position.0).magnitude(),
+            ..default()
+        };
+        for (occulter, body, occulter_position) in occulters.iter() {
+            if occulter == light_entity || occulter == entity {
+                continue;
+            }
+            let (shadow, fraction) = eclipse(
+                position.0,
+                light_position.0,
+                light_body.radius(),
+                occulter_position.0,
+                body.radius(),
+            );
+            if fraction < darkest.fraction {
+                darkest.fraction = fraction;
+                darkest.shadow = shadow;
+                darkest.occulter = Some(occulter);
+            }
+        }
+        *illumination = darkest;
+
+        if let Some(mut radiation_pressure) = radiation_pressure {
+            if let Some(vessel) = vessel {
+                radiation_pressure.mass = vessel.mass();
+            }
+            radiation_pressure.acceleration = illumination.fraction
+                * radiation_pressure.acceleration_at(light, position.0 - light_position.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ASTRONOMICAL_UNIT;
+
+    const SUN_RADIUS: f64 = 6.957e8;
+    const EARTH_RADIUS: f64 = 6.371e6;
+
+    /// Sun at the origin and Earth at 1 AU on the x axis
+    fn earth_shadow(position: Vector3<f64>) -> (Shadow, f64) {
+        eclipse(
+            position,
+            Vector3::zeros(),
+            SUN_RADIUS,
+            Vector3::new(ASTRONOMICAL_UNIT, 0.0, 0.0),
+            EARTH_RADIUS,
+        )
+    }
+
+    #[test]
+    fn shadows_behind_the_earth() {
+        let earth = Vector3::new(ASTRONOMICAL_UNIT, 0.0, 0.0);
+        // Low orbit on the night side
+        assert_eq!(
+            earth_shadow(earth + Vector3::new(7.0e6, 0.0, 0.0)),
+            (Shadow::Umbra, 0.0)
+        );
+        // Day side and above the terminator
+        assert_eq!(
+            earth_shadow(earth - Vector3::new(7.0e6, 0.0, 0.0)),
+            (Shadow::Sunlit, 1.0)
+        );
+        assert_eq!(
+            earth_shadow(earth + Vector3::new(0.0, 7.0e6, 0.0)),
+            (Shadow::Sunlit, 1.0)
+        );
+        // Grazing the edge of the umbra in low orbit
+        let (shadow, fraction) = earth_shadow(earth + Vector3::new(7.0e6, EARTH_RADIUS, 0.0));
+        assert_eq!(shadow, Shadow::Penumbra);
+        assert!(fraction > 0.0 && fraction < 1.0, "{fraction}");
+        // The umbra ends about 1.4 million km behind the Earth, further away it only covers the
+        // center of the Sun
+        let (shadow, fraction) = earth_shadow(earth + Vector3::new(3.0e9, 0.0, 0.0));
+        assert_eq!(shadow, Shadow::Antumbra);
+        let expected =
+            1.0 - (EARTH_RADIUS / 3.0e9 * (ASTRONOMICAL_UNIT + 3.0e9) / SUN_RADIUS).powi(2);
+        assert!((fraction - expected).abs() < 1e-3, "{fraction}");
+    }
+
+    #[test]
+    fn pressure_at_one_astronomical_unit() {
+        let sail = RadiationPressure::new(1.0, 0.0, 1.0);
+        let acceleration =
+            sail.acceleration_at(&LightSource::SUN, Vector3::new(ASTRONOMICAL_UNIT, 0.0, 0.0));
+        // Solar constant of 1361 W/m² divided by the speed of light
+        assert!((acceleration.x - 4.54e-6).abs() < 0.01e-6, "{acceleration}");
+        assert_eq!(acceleration.y, 0.0);
+
+        let mirror = RadiationPressure::new(1.0, 1.0, 1.0);
+        let reflected =
+            mirror.acceleration_at(&LightSource::SUN, Vector3::new(ASTRONOMICAL_UNIT, 0.0, 0.0));
+        assert!((reflected - 2.0 * acceleration).magnitude() < 1e-15);
+    }
+
+    #[test]
+    fn no_pressure_in_the_shadow() {
+        use crate::{Orbit, OrbitPlugin, OrbitsAround};
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, OrbitPlugin));
+        let sun_body = Body::new(1.989e30).with_radius(SUN_RADIUS);
+        let sun = app.world_mut().spawn((sun_body, LightSource::SUN)).id();
+        let earth_body = Body::new(5.97219e24).with_radius(EARTH_RADIUS);
+        let earth_orbit = Orbit::new_free(
+            Vector3::new(ASTRONOMICAL_UNIT, 0.0, 0.0),
+            Vector3::zeros(),
+            &sun_body,
+        )
+        .unwrap();
+        let earth = app
+            .world_mut()
+            .spawn((earth_body, earth_orbit, OrbitsAround(sun)))
+            .id();
+        let mut spawn_sail = |offset: Vector3<f64>| {
+            let orbit = Orbit::new_free(offset, Vector3::zeros(), &earth_body).unwrap();
+            app.world_mut()
+                .spawn((
+                    orbit,
+                    OrbitsAround(earth),
+                    RadiationPressure::new(100.0, 0.5, 10.0),
+                ))
+                .id()
+        };
+        let day = spawn_sail(Vector3::new(-1.0e7, 0.0, 0.0));
+        let night = spawn_sail(Vector3::new(1.0e7, 0.0, 0.0));
+
+        // Positions are known after the first frame
+        app.update();
+        app.update();
+
+        let illumination = |entity| *app.world().get::<Illumination>(entity).unwrap();
+        let pressure = |entity| {
+            app.world()
+                .get::<RadiationPressure>(entity)
+                .unwrap()
+                .acceleration()
+        };
+        assert_eq!(illumination(day).shadow, Shadow::Sunlit);
+        assert!(pressure(day).x > 0.0);
+        assert_eq!(illumination(night).shadow, Shadow::Umbra);
+        assert_eq!(illumination(night).occulter, Some(earth));
+        assert_eq!(pressure(night), Vector3::zeros());
+    }
+}
@@ -0,0 +1,328 @@
+use bevy::prelude::*;
+
+use crate::{Body, Frame, Orbit, OrbitsAround, Perturbations, Satellites, SimulationClock, Vessel};
+
+/// When objects with `AutoRails` switch between free flight and rails.
+/// The distances to leave free flight are larger than the ones to enter it, so objects moving
+/// around a threshold do not switch back and forth every frame.
+#[derive(Resource, Reflect, Debug, Clone, Copy, PartialEq)]
+#[reflect(Resource)]
+pub struct RailsPolicy {
+    /// Objects closer than this factor of the sphere of influence of another body orbiting the
+    /// same parent fly free
+    pub enter_sphere_of_influence: f64,
+    /// They go back on rails once they are further than this factor
+    pub leave_sphere_of_influence: f64,
+    /// Objects lower than this over the surface of their parent fly free
+    pub enter_altitude: f64,
+    /// They go back on rails once they are higher than this
+    pub leave_altitude: f64,
+    /// Simulated seconds an object has to coast before going back on rails
+    pub coast_delay: f64,
+}
+
+impl Default for RailsPolicy {
+    fn default() -> Self {
+        Self {
+            enter_sphere_of_influence: 1.2,
+            leave_sphere_of_influence: 1.5,
+            enter_altitude: 100.0e3,
+            leave_altitude: 120.0e3,
+            coast_delay: 2.0,
+        }
+    }
+}
+
+/// Lets `OrbitPlugin` switch the frame of the object: it flies free while thrusting, perturbed
+/// or near other bodies, and coasts on rails otherwise so it can be warped exactly.
+/// Trajectories that are not closed orbits always stay free.
+/// Global perturbations and radiation pressure are too weak to take objects off rails.
+#[derive(Component, Reflect, Debug, Default, Clone, Copy, PartialEq)]
+pub struct AutoRails {
+    /// Epoch when the object stopped needing free flight
+    coasting_since: Option<f64>,
+    /// Thrust that is not modeled by a `Vessel` was applied since the last switch
+    thrusters_fired: bool,
+}
+
+impl AutoRails {
+    /// Keeps the object in free flight on the next frame, like a burning `Vessel`.
+    /// Call it every frame while applying thrust that `OrbitPlugin` does not model, like RCS.
+    pub fn fire_thrusters(&mut self) {
+        self.thrusters_fired = true;
+    }
+}
+
+/// Whether an object is inside the thresholds of the policy
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Proximity {
+    /// Closer than the enter thresholds
+    Near,
+    /// Between the enter and leave thresholds
+    Around,
+    /// Further than the leave thresholds
+    Far,
+}
+
+type SwitchedObject = (
+    &'static mut Orbit,
+    &'static OrbitsAround,
+    &'static mut AutoRails,
+    Option<&'static Vessel>,
+    Option<&'static Perturbations>,
+);
+
+/// Switches objects with `AutoRails` between free flight and rails.
+/// Runs before propagation, once the engines have been pointed.
+pub fn switch_frames(
+    mut objects: Query<SwitchedObject>,
+    bodies: Query<(&Body, Option<&Satellites>)>,
+    satellites: Query<(&Body, &Orbit), Without<AutoRails>>,
+    policy: Res<RailsPolicy>,
+    clock: Res<SimulationClock>,
+) {
+    for (mut orbit, orbits_around, mut auto_rails, vessel, perturbations) in objects.iter_mut() {
+        let thrusting = std::mem::take(&mut auto_rails.thrusters_fired)
+            || vessel.is_some_and(Vessel::is_burning)
+            || perturbations.is_some_and(|perturbations| !perturbations.is_empty());
+        let proximity = bodies
+            .get(orbits_around.0)
+            .map(|(parent, siblings)| proximity(&orbit, parent, siblings, &satellites, &policy))
+            .unwrap_or(Proximity::Far);
+
+        match orbit.frame() {
+            Frame::Orbit { .. } => {
+                if thrusting || proximity == Proximity::Near {
+                    orbit.set_free();
+                    auto_rails.coasting_since = None;
+                }
+            }
+            Frame::Free(_) => {
+                if thrusting || proximity != Proximity::Far {
+                    auto_rails.coasting_since = None;
+                    continue;
+                }
+                let since = *auto_rails.coasting_since.get_or_insert(clock.seconds());
+                if clock.seconds() - since >= policy.coast_delay && orbit.set_orbit(&clock).is_ok()
+                {
+                    auto_rails.coasting_since = None;
+                }
+            }
+        }
+    }
+}
+
+fn proximity(
+    orbit: &Orbit,
+    parent: &Body,
+    siblings: Option<&Satellites>,
+    satellites: &Query<(&Body, &Orbit), Without<AutoRails>>,
+    policy: &RailsPolicy,
+) -> Proximity {
+    let position = orbit.position();
+    let mut proximity = Proximity::Far;
+    let mut check = |distance: f64, enter: f64, leave: f64| {
+        if distance < enter {
+            proximity = Proximity::Near;
+        } else if distance < leave && proximity == Proximity::Far {
+            proximity = Proximity::Around;
+        }
+    };
+
+    // Only bodies with a surface have an altitude
+    if parent.radius() > 0.0 {
+        check(
+            position.magnitude() - parent.radius(),
+            policy.enter_altitude,
+            policy.leave_altitude,
+        );
+    }
+
+    for sibling in siblings.into_iter().flat_map(Satellites::iter) {
+        let Ok((sibling_body, sibling_orbit)) = satellites.get(sibling) else {
+            continue;
+        };
+        let Ok(sphere_of_influence) = sibling_orbit.sphere_of_influence(sibling_body) else {
+            continue;
+        };
+        check(
+            (position - sibling_orbit.position()).magnitude(),
+            sphere_of_influence * policy.enter_sphere_of_influence,
+            sphere_of_influence * policy.leave_sphere_of_influence,
+        );
+    }
+
+    proximity
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+    use nalgebra::Vector3;
+
+    use super::*;
+    use crate::{OrbitPlugin, StateVectors, TimeSpeed};
+
+    const EARTH_MASS: f64 = 5.97219e24;
+    const EARTH_RADIUS: f64 = 6.371e6;
+
+    fn assert_same_state(a: StateVectors, b: StateVectors) {
+        let position_error = (a.position - b.position).magnitude() / a.position.magnitude();
+        let velocity_error = (a.velocity - b.velocity).magnitude() / a.velocity.magnitude();
+        assert!(position_error < 1e-12, "{position_error}");
+        assert!(velocity_error < 1e-12, "{velocity_error}");
+    }
+
+    #[test]
+    fn round_trip_preserves_state() {
+        let earth = Body::new(EARTH_MASS);
+        let clock = SimulationClock::default();
+        for (semimajor_axis, eccentricity, argument_of_periapsis, inclination, node) in [
+            (7.0e6, 0.0, 0.0, 0.0, 0.0),
+            (7.0e6, 1e-9, 0.0, 1e-9, 0.0),
+            (2.66e7, 0.74, 4.71, 1.1, 0.5),
+            (4.2e7, 0.001, 1.0, 0.001, 2.0),
+            (1.0e8, 0.95, 2.5, 3.0, 5.0),
+        ] {
+            let rails = Orbit::new_orbit(
+                semimajor_axis,
+                eccentricity,
+                argument_of_periapsis,
+                inclination,
+                node,
+                &earth,
+                &clock,
+                -1234.5,
+            )
+            .unwrap();
+            let state = rails.state_vectors();
+
+            // Rails to free and back
+            let mut orbit = rails.clone();
+            orbit.set_free();
+            assert_same_state(orbit.state_vectors(), state);
+            orbit.set_orbit(&clock).unwrap();
+            assert_same_state(orbit.state_vectors(), state);
+
+            // Both keep agreeing after stepping the same time
+            let mut stepped = rails.clone();
+            stepped.step(600.0).unwrap();
+            orbit.step(600.0).unwrap();
+            assert_same_state(orbit.state_vectors(), stepped.state_vectors());
+
+            // Free to rails and back
+            let mut free = Orbit::new_free(state.position, state.velocity, &earth).unwrap();
+            free.set_orbit(&clock).unwrap();
+            free.set_free();
+            assert_same_state(free.state_vectors(), state);
+        }
+    }
+
+    fn app() -> (App, Entity, Body) {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, OrbitPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .insert_resource(TimeSpeed(10.0));
+        let earth_body = Body::new(EARTH_MASS).with_radius(EARTH_RADIUS);
+        let earth = app.world_mut().spawn(earth_body).id();
+        (app, earth, earth_body)
+    }
+
+    fn is_free(app: &App, entity: Entity) -> bool {
+        matches!(
+            app.world().get::<Orbit>(entity).unwrap().frame(),
+            Frame::Free(_)
+        )
+    }
+
+    #[test]
+    fn free_while_burning() {
+        let (mut app, earth, earth_body) = app();
+        let orbit = Orbit::new_orbit(
+            7.0e6,
+            0.0,
+            0.0,
+            0.5,
+            0.0,
+            &earth_body,
+            &SimulationClock::default(),
+            0.0,
+        )
+        .unwrap();
+        let ship = app
+            .world_mut()
+            .spawn((
+                orbit,
+                OrbitsAround(earth),
+                Vessel::new(1000.0, 100.0, 100.0, 300.0).unwrap(),
+            ))
+            .id();
+
+        app.update();
+        assert!(!is_free(&app, ship));
+
+        app.world_mut()
+            .get_mut::<Vessel>(ship)
+            .unwrap()
+            .set_throttle(1.0)
+            .unwrap();
+        app.update();
+        assert!(is_free(&app, ship));
+
+        app.world_mut()
+            .get_mut::<Vessel>(ship)
+            .unwrap()
+            .set_throttle(0.0)
+            .unwrap();
+        // Coasts for a while before going back on rails
+        app.update();
+        assert!(is_free(&app, ship));
+        for _ in 0..3 {
+            app.update();
+        }
+        assert!(!is_free(&app, ship));
+    }
+
+    #[test]
+    fn hysteresis_at_low_altitude() {
+        let (mut app, earth, earth_body) = app();
+        let policy = RailsPolicy::default();
+        let mut spawn = |altitude: f64| {
+            let radius = EARTH_RADIUS + altitude;
+            let speed = (earth_body.standard_gravitational_parameter / radius).sqrt();
+            let orbit = Orbit::new_free(
+                Vector3::new(radius, 0.0, 0.0),
+                Vector3::new(0.0, 0.0, speed),
+                &earth_body,
+            )
+            .unwrap();
+            app.world_mut()
+                .spawn((orbit, OrbitsAround(earth), AutoRails::default()))
+                .id()
+        };
+        let low = spawn(policy.enter_altitude * 0.5);
+        let between = spawn((policy.enter_altitude + policy.leave_altitude) / 2.0);
+        let high = spawn(policy.leave_altitude * 2.0);
+
+        for _ in 0..30 {
+            app.update();
+        }
+        assert!(is_free(&app, low));
+        // Stays in the frame it had until it leaves the band
+        assert!(is_free(&app, between));
+        assert!(!is_free(&app, high));
+
+        // A rails orbit inside the band is not taken off rails either
+        app.world_mut()
+            .get_mut::<Orbit>(between)
+            .unwrap()
+            .set_orbit(&SimulationClock::default())
+            .unwrap();
+        app.update();
+        assert!(!is_free(&app, between));
+    }
+}
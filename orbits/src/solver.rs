@@ -1,31 +1,322 @@
-use bevy::log::warn;
+use std::f64::consts::PI;
 
-/// Generic Newton-Raphson solver
-pub fn solve_newton_raphson<F, Fd>(
-    f: F,                // The function f(x)
-    f_prime: Fd,         // The derivative of the function f'(x)
-    initial_guess: f64,  // Initial guess for the root
-    tolerance: f64,      // Tolerance for convergence
-    max_iterations: u32, // Maximum number of iterations
-) -> f64
-where
-    F: Fn(f64) -> f64,
-    Fd: Fn(f64) -> f64,
-{
-    let mut x = initial_guess;
+/// Converged solution of Kepler's equation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeplerSolution {
+    /// Eccentric anomaly in the range (-PI, PI]
+    pub eccentric_anomaly: f64,
+    /// Newton or bisection steps taken after the starter
+    pub iterations: u32,
+    /// E - e*sin(E) - M at the returned eccentric anomaly
+    pub residual: f64,
+}
+
+/// Reasons why Kepler's equation could not be solved
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeplerError {
+    /// The elliptic equation only has physical meaning for 0 <= e < 1
+    InvalidEccentricity(f64),
+    /// The mean anomaly is NaN or infinite
+    InvalidMeanAnomaly(f64),
+    /// Ran out of iterations, the last estimate is still inside the bracket so it is usable
+    NotConverged {
+        best_estimate: f64,
+        iterations: u32,
+        residual: f64,
+    },
+}
+
+impl std::fmt::Display for KeplerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidEccentricity(eccentricity) => {
+                write!(f, "eccentricity {eccentricity} is not elliptic")
+            }
+            Self::InvalidMeanAnomaly(mean_anomaly) => {
+                write!(f, "mean anomaly {mean_anomaly} is not finite")
+            }
+            Self::NotConverged {
+                best_estimate,
+                iterations,
+                residual,
+            } => write!(
+                f,
+                "did not converge after {iterations} iterations (E = {best_estimate}, residual = {residual})"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for KeplerError {}
+
+/// Solves the elliptic Kepler equation E - e*sin(E) = M for the eccentric anomaly.
+/// Starts from Markley's cubic approximation and refines it with Newton steps, falling back to
+/// bisection whenever a step leaves the bracket where the root is known to be. This keeps it
+/// convergent for eccentricities arbitrarily close to 1.
+/// https://doi.org/10.1007/BF00691917
+pub fn solve_kepler(
+    mean_anomaly: f64,
+    eccentricity: f64,
+    tolerance: f64,
+    max_iterations: u32,
+) -> Result<KeplerSolution, KeplerError> {
+    let mut bracket = Bracket::new(mean_anomaly, eccentricity)?;
+    for iteration in 0..max_iterations {
+        if bracket.converged(tolerance) {
+            return Ok(bracket.solution(iteration));
+        }
+        bracket.refine();
+    }
+    Err(bracket.not_converged(max_iterations))
+}
+
+/// Solves Kepler's equation for every `(mean_anomaly, eccentricity)` pair, like `solve_kepler`.
+/// All the equations are refined together one iteration at a time, so a whole batch of orbits
+/// goes through the same tight loop and the ones that converge early drop out of it.
+pub fn solve_kepler_batch(
+    problems: &[(f64, f64)],
+    tolerance: f64,
+    max_iterations: u32,
+) -> Vec<Result<KeplerSolution, KeplerError>> {
+    let mut results = Vec::with_capacity(problems.len());
+    let mut brackets = Vec::with_capacity(problems.len());
+    for (index, &(mean_anomaly, eccentricity)) in problems.iter().enumerate() {
+        match Bracket::new(mean_anomaly, eccentricity) {
+            Ok(bracket) => {
+                // Placeholder until the bracket converges
+                results.push(Err(bracket.not_converged(0)));
+                brackets.push((index, bracket));
+            }
+            Err(error) => results.push(Err(error)),
+        }
+    }
+
+    for iteration in 0..max_iterations {
+        brackets.retain_mut(|(index, bracket)| {
+            if bracket.converged(tolerance) {
+                results[*index] = Ok(bracket.solution(iteration));
+                return false;
+            }
+            bracket.refine();
+            true
+        });
+        if brackets.is_empty() {
+            break;
+        }
+    }
+
+    for (index, bracket) in brackets {
+        results[index] = Err(bracket.not_converged(max_iterations));
+    }
+    results
+}
+
+/// Search for the root of a single Kepler equation, solved for M in [0, PI] and mirrored back
+/// since the equation is odd
+struct Bracket {
+    sign: f64,
+    mean_anomaly: f64,
+    eccentricity: f64,
+    /// f(low) <= 0 and f(high) >= 0
+    low: f64,
+    high: f64,
+    eccentric_anomaly: f64,
+    residual: f64,
+}
+
+impl Bracket {
+    fn new(mean_anomaly: f64, eccentricity: f64) -> Result<Self, KeplerError> {
+        if !(0.0..1.0).contains(&eccentricity) {
+            return Err(KeplerError::InvalidEccentricity(eccentricity));
+        }
+        if !mean_anomaly.is_finite() {
+            return Err(KeplerError::InvalidMeanAnomaly(mean_anomaly));
+        }
+
+        let wrapped_mean_anomaly = wrap_angle(mean_anomaly);
+        let mean_anomaly = wrapped_mean_anomaly.abs();
+        let low = mean_anomaly;
+        let high = (mean_anomaly + eccentricity).min(PI);
+        let mut bracket = Self {
+            sign: wrapped_mean_anomaly.signum(),
+            mean_anomaly,
+            eccentricity,
+            low,
+            high,
+            eccentric_anomaly: markley_starter(mean_anomaly, eccentricity).clamp(low, high),
+            residual: 0.0,
+        };
+        bracket.residual = bracket.kepler_equation(bracket.eccentric_anomaly);
+        Ok(bracket)
+    }
 
-    for _ in 0..max_iterations {
-        // Newton-Raphson update step
-        let next_x = x - f(x) / f_prime(x);
+    fn kepler_equation(&self, eccentric_anomaly: f64) -> f64 {
+        eccentric_anomaly - self.eccentricity * eccentric_anomaly.sin() - self.mean_anomaly
+    }
+
+    fn converged(&self, tolerance: f64) -> bool {
+        self.residual.abs() <= tolerance || self.high - self.low <= tolerance
+    }
 
-        if (next_x - x).abs() < tolerance {
-            return next_x;
+    /// Takes a Newton step, or bisects if it would leave the bracket
+    fn refine(&mut self) {
+        if self.residual < 0.0 {
+            self.low = self.eccentric_anomaly;
+        } else {
+            self.high = self.eccentric_anomaly;
         }
 
-        x = next_x;
+        let derivative = 1.0 - self.eccentricity * self.eccentric_anomaly.cos();
+        let newton_step = self.eccentric_anomaly - self.residual / derivative;
+        self.eccentric_anomaly = if newton_step > self.low && newton_step < self.high {
+            newton_step
+        } else {
+            0.5 * (self.low + self.high)
+        };
+        self.residual = self.kepler_equation(self.eccentric_anomaly);
     }
 
-    // Return the final approximation after max_iterations
-    warn!("Returning from solver after max iterations");
-    x
+    fn solution(&self, iterations: u32) -> KeplerSolution {
+        KeplerSolution {
+            eccentric_anomaly: self.sign * self.eccentric_anomaly,
+            iterations,
+            residual: self.sign * self.residual,
+        }
+    }
+
+    fn not_converged(&self, iterations: u32) -> KeplerError {
+        KeplerError::NotConverged {
+            best_estimate: self.sign * self.eccentric_anomaly,
+            iterations,
+            residual: self.sign * self.residual,
+        }
+    }
+}
+
+/// Markley's non iterative approximation, expects M in [0, PI]
+fn markley_starter(mean_anomaly: f64, eccentricity: f64) -> f64 {
+    let pi_squared = PI * PI;
+    let alpha = (3.0 * pi_squared + 1.6 * PI * (PI - mean_anomaly) / (1.0 + eccentricity))
+        / (pi_squared - 6.0);
+    let d = 3.0 * (1.0 - eccentricity) + alpha * eccentricity;
+    let q = 2.0 * alpha * d * (1.0 - eccentricity) - mean_anomaly.powi(2);
+    let r = 3.0 * alpha * d * (d - 1.0 + eccentricity) * mean_anomaly + mean_anomaly.powi(3);
+    let w = (r.abs() + (q.powi(3) + r.powi(2)).sqrt()).powf(2.0 / 3.0);
+
+    (2.0 * r * w / (w.powi(2) + w * q + q.powi(2)) + mean_anomaly) / d
+}
+
+/// Wraps an angle into (-PI, PI]
+fn wrap_angle(angle: f64) -> f64 {
+    let wrapped = angle.rem_euclid(2.0 * PI);
+    if wrapped > PI {
+        wrapped - 2.0 * PI
+    } else {
+        wrapped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCE: f64 = 1e-13;
+    const MAX_ITERATIONS: u32 = 100;
+
+    #[test]
+    fn solves_full_grid() {
+        let mut eccentricities: Vec<f64> = (0..100).map(|i| i as f64 / 100.0).collect();
+        eccentricities.extend([0.995, 0.999, 0.9999, 1.0 - 1e-6, 1.0 - 1e-9, 1.0 - 1e-12]);
+
+        for &eccentricity in &eccentricities {
+            for i in -2000..=2000 {
+                let mean_anomaly = i as f64 * 2.0 * PI / 1000.0;
+                let solution = solve_kepler(mean_anomaly, eccentricity, TOLERANCE, MAX_ITERATIONS)
+                    .unwrap_or_else(|error| {
+                        panic!("M = {mean_anomaly}, e = {eccentricity}: {error}")
+                    });
+
+                let eccentric_anomaly = solution.eccentric_anomaly;
+                assert!(eccentric_anomaly > -PI - TOLERANCE && eccentric_anomaly <= PI + TOLERANCE);
+                let residual = eccentric_anomaly
+                    - eccentricity * eccentric_anomaly.sin()
+                    - wrap_angle(mean_anomaly);
+                assert!(
+                    residual.abs() <= 1e-12,
+                    "M = {mean_anomaly}, e = {eccentricity}, residual = {residual}"
+                );
+                assert!(
+                    solution.iterations < 64,
+                    "M = {mean_anomaly}, e = {eccentricity}, iterations = {}",
+                    solution.iterations
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn converges_quickly_for_moderate_eccentricity() {
+        for i in 0..=1000 {
+            let mean_anomaly = i as f64 * PI / 1000.0;
+            let solution = solve_kepler(mean_anomaly, 0.5, TOLERANCE, MAX_ITERATIONS).unwrap();
+            assert!(solution.iterations <= 4, "M = {mean_anomaly}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        assert_eq!(
+            solve_kepler(1.0, 1.0, TOLERANCE, MAX_ITERATIONS),
+            Err(KeplerError::InvalidEccentricity(1.0))
+        );
+        assert_eq!(
+            solve_kepler(1.0, -0.1, TOLERANCE, MAX_ITERATIONS),
+            Err(KeplerError::InvalidEccentricity(-0.1))
+        );
+        assert!(matches!(
+            solve_kepler(f64::NAN, 0.5, TOLERANCE, MAX_ITERATIONS),
+            Err(KeplerError::InvalidMeanAnomaly(_))
+        ));
+    }
+
+    #[test]
+    fn reports_non_convergence() {
+        let Err(KeplerError::NotConverged { best_estimate, .. }) = solve_kepler(1.0, 0.99, 0.0, 3)
+        else {
+            panic!("Solver should run out of iterations");
+        };
+        assert!(best_estimate > 1.0 && best_estimate < 1.99);
+    }
+
+    #[test]
+    fn batch_matches_single_solutions() {
+        let mut problems = Vec::new();
+        for eccentricity in [0.0, 0.1, 0.5, 0.9, 0.999, 1.0, -0.1] {
+            for i in -20..=20 {
+                problems.push((i as f64 * 0.37, eccentricity));
+            }
+        }
+        problems.push((f64::NAN, 0.5));
+
+        let batch = solve_kepler_batch(&problems, TOLERANCE, MAX_ITERATIONS);
+        assert_eq!(batch.len(), problems.len());
+        for (&(mean_anomaly, eccentricity), result) in problems.iter().zip(batch) {
+            let single = solve_kepler(mean_anomaly, eccentricity, TOLERANCE, MAX_ITERATIONS);
+            match (result, single) {
+                (
+                    Err(KeplerError::InvalidMeanAnomaly(_)),
+                    Err(KeplerError::InvalidMeanAnomaly(_)),
+                ) => {}
+                (result, single) => {
+                    assert_eq!(result, single, "M = {mean_anomaly}, e = {eccentricity}")
+                }
+            }
+        }
+
+        let batch = solve_kepler_batch(&[(1.0, 0.99), (1.0, 0.0)], 0.0, 3);
+        assert!(matches!(
+            batch[0],
+            Err(KeplerError::NotConverged { iterations: 3, .. })
+        ));
+    }
 }
@@ -23,10 +23,272 @@ impl DeltaTime {
     }
 }
 
+/// https://en.wikipedia.org/wiki/Epoch_(astronomy)#Julian_years_and_J2000
+pub const J2000_JULIAN_DATE: f64 = 2_451_545.0;
+/// Julian date of 1970-01-01 00:00:00
+const UNIX_EPOCH_JULIAN_DATE: f64 = 2_440_587.5;
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// Absolute simulation time, counted in seconds since a reference epoch.
+/// Orbit epochs are measured in the same seconds, so they can be compared directly.
+/// Days are always 86400 seconds long, leap seconds are ignored.
+#[derive(Resource, Reflect, Debug, Clone, Copy, PartialEq)]
+#[reflect(Resource)]
+pub struct SimulationClock {
+    /// Julian date of the instant where `elapsed` is 0
+    reference_julian_date: f64,
+    /// Seconds since the reference epoch
+    elapsed: f64,
+    paused: bool,
+    /// Epoch the time warp is running to, the clock stops exactly on it
+    warp_target: Option<f64>,
+}
+
+impl Default for SimulationClock {
+    /// Starts at J2000, 2000-01-01 12:00:00
+    fn default() -> Self {
+        Self::new(J2000_JULIAN_DATE)
+    }
+}
+
+impl SimulationClock {
+    pub fn new(reference_julian_date: f64) -> Self {
+        Self {
+            reference_julian_date,
+            elapsed: 0.0,
+            paused: false,
+            warp_target: None,
+        }
+    }
+
+    /// Clock referenced to J2000 that starts at the given instant
+    pub fn starting_at(julian_date: f64) -> Self {
+        Self {
+            elapsed: (julian_date - J2000_JULIAN_DATE) * SECONDS_PER_DAY,
+            ..Self::default()
+        }
+    }
+
+    /// Same reference epoch, moved to the given seconds since it
+    pub fn with_seconds(mut self, seconds: f64) -> Self {
+        self.elapsed = seconds;
+        self
+    }
+
+    /// Seconds since the reference epoch
+    pub fn seconds(&self) -> f64 {
+        self.elapsed
+    }
+
+    pub fn reference_julian_date(&self) -> f64 {
+        self.reference_julian_date
+    }
+
+    /// https://en.wikipedia.org/wiki/Julian_day
+    pub fn julian_date(&self) -> f64 {
+        self.reference_julian_date + self.elapsed / SECONDS_PER_DAY
+    }
+
+    /// Current date formatted as `YYYY-MM-DD HH:MM:SS UTC`
+    pub fn utc(&self) -> String {
+        self.utc_at(self.elapsed)
+    }
+
+    /// Date of an epoch, in seconds since the reference epoch, formatted like `utc`
+    pub fn utc_at(&self, epoch: f64) -> String {
+        let julian_date = self.reference_julian_date + epoch / SECONDS_PER_DAY;
+        let (year, month, day, seconds_of_day) = civil_from_julian_date(julian_date);
+        let seconds_of_day = seconds_of_day as u32;
+        format!(
+            "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+            seconds_of_day / 3600,
+            seconds_of_day / 60 % 60,
+            seconds_of_day % 60
+        )
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Seconds since the reference epoch at the given Julian date
+    pub fn seconds_at(&self, julian_date: f64) -> f64 {
+        (julian_date - self.reference_julian_date) * SECONDS_PER_DAY
+    }
+
+    /// Runs time warp until the given epoch, in seconds since the reference epoch.
+    /// Epochs in the past are ignored.
+    pub fn warp_to(&mut self, epoch: f64) {
+        if epoch > self.elapsed {
+            self.warp_target = Some(epoch);
+            self.paused = false;
+        }
+    }
+
+    pub fn warp_target(&self) -> Option<f64> {
+        self.warp_target
+    }
+
+    pub fn cancel_warp(&mut self) {
+        self.warp_target = None;
+    }
+
+    /// Jumps back to an earlier epoch and pauses there
+    pub(crate) fn rewind_to(&mut self, epoch: f64) {
+        self.elapsed = epoch;
+        self.warp_target = None;
+        self.paused = true;
+    }
+
+    /// Moves the clock forward, does nothing while paused.
+    /// Never goes past the warp target, returns the seconds that actually passed.
+    pub fn advance(&mut self, seconds: f64) -> f64 {
+        if self.paused {
+            return 0.0;
+        }
+
+        let seconds = match self.warp_target {
+            Some(target) if self.elapsed + seconds >= target => {
+                self.warp_target = None;
+                target - self.elapsed
+            }
+            _ => seconds,
+        };
+        self.elapsed += seconds;
+        seconds
+    }
+}
+
+/// Julian date of a UTC instant in the proleptic Gregorian calendar
+pub fn julian_date_from_utc(
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: f64,
+) -> f64 {
+    // https://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let unix_days = era * 146_097 + day_of_era - 719_468;
+
+    let seconds_of_day = hour as f64 * 3600.0 + minute as f64 * 60.0 + second;
+    UNIX_EPOCH_JULIAN_DATE + unix_days as f64 + seconds_of_day / SECONDS_PER_DAY
+}
+
+/// Year, month, day and seconds into the day of a Julian date
+fn civil_from_julian_date(julian_date: f64) -> (i64, u32, u32, f64) {
+    // Rounded to the millisecond so that whole seconds do not print as the previous one
+    let seconds = ((julian_date - UNIX_EPOCH_JULIAN_DATE) * SECONDS_PER_DAY * 1e3).round() / 1e3;
+    let unix_days = (seconds / SECONDS_PER_DAY).floor();
+    let seconds_of_day = seconds - unix_days * SECONDS_PER_DAY;
+
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = unix_days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day, seconds_of_day)
+}
+
+/// Advances the clock, the simulation does not move while it is paused.
+/// The last frame of a warp to an epoch is shortened to land on it, then time goes back to normal.
 pub fn update_delta_time(
     mut deltatime: ResMut<DeltaTime>,
-    time_speed: Res<TimeSpeed>,
+    mut clock: ResMut<SimulationClock>,
+    mut time_speed: ResMut<TimeSpeed>,
     time: Res<Time>,
 ) {
-    deltatime.0 = time.delta_secs_f64() * time_speed.0;
+    let warping = clock.warp_target().is_some();
+    deltatime.0 = clock.advance(time.delta_secs_f64() * time_speed.0);
+    if warping && clock.warp_target().is_none() {
+        time_speed.0 = 1.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn j2000_calendar_date() {
+        let clock = SimulationClock::default();
+        assert_eq!(clock.julian_date(), J2000_JULIAN_DATE);
+        assert_eq!(clock.utc(), "2000-01-01 12:00:00 UTC");
+        assert_eq!(
+            julian_date_from_utc(2000, 1, 1, 12, 0, 0.0),
+            J2000_JULIAN_DATE
+        );
+    }
+
+    #[test]
+    fn calendar_round_trip() {
+        // Leap days, century rules and dates before the Unix epoch
+        for (year, month, day) in [(2024, 2, 29), (1900, 3, 1), (1969, 7, 20), (2100, 12, 31)] {
+            let clock =
+                SimulationClock::starting_at(julian_date_from_utc(year, month, day, 20, 17, 40.0));
+            assert_eq!(
+                clock.utc(),
+                format!("{year:04}-{month:02}-{day:02} 20:17:40 UTC")
+            );
+        }
+    }
+
+    #[test]
+    fn paused_clock_does_not_advance() {
+        let mut clock = SimulationClock::default();
+        clock.advance(86_400.0);
+        assert_eq!(clock.utc(), "2000-01-02 12:00:00 UTC");
+
+        clock.pause();
+        assert_eq!(clock.advance(86_400.0), 0.0);
+        assert_eq!(clock.seconds(), 86_400.0);
+
+        clock.resume();
+        clock.advance(1.0);
+        assert_eq!(clock.seconds(), 86_401.0);
+    }
+
+    #[test]
+    fn warp_lands_on_target() {
+        let mut clock = SimulationClock::default();
+        clock.warp_to(1000.0);
+        assert_eq!(clock.advance(600.0), 600.0);
+        assert_eq!(clock.advance(600.0), 400.0);
+        assert_eq!(clock.seconds(), 1000.0);
+        assert_eq!(clock.warp_target(), None);
+
+        // Targets in the past are ignored
+        clock.warp_to(10.0);
+        assert_eq!(clock.warp_target(), None);
+    }
 }
@@ -0,0 +1,310 @@
+use bevy::prelude::*;
+use nalgebra::Vector3;
+
+use crate::AutoRails;
+
+/// https://en.wikipedia.org/wiki/Standard_gravity
+pub const STANDARD_GRAVITY: f64 = 9.80665;
+
+/// Everything that can go wrong when configuring a `Vessel`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VesselError {
+    /// Dry mass must be positive
+    InvalidDryMass(f64),
+    /// Propellant mass can not be negative
+    InvalidPropellantMass(f64),
+    /// Thrust can not be negative
+    InvalidThrust(f64),
+    /// Specific impulse must be positive
+    InvalidSpecificImpulse(f64),
+    /// Throttle goes from 0 to 1
+    InvalidThrottle(f64),
+    /// The thrust direction has no length
+    ZeroThrustDirection,
+    /// Moments of inertia must be positive
+    InvalidMomentOfInertia,
+    /// Torques can not be negative
+    InvalidTorque,
+}
+
+impl std::fmt::Display for VesselError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidDryMass(mass) => write!(f, "dry mass {mass} must be positive"),
+            Self::InvalidPropellantMass(mass) => {
+                write!(f, "propellant mass {mass} can not be negative")
+            }
+            Self::InvalidThrust(thrust) => write!(f, "thrust {thrust} can not be negative"),
+            Self::InvalidSpecificImpulse(specific_impulse) => {
+                write!(f, "specific impulse {specific_impulse} must be positive")
+            }
+            Self::InvalidThrottle(throttle) => {
+                write!(f, "throttle {throttle} must be between 0 and 1")
+            }
+            Self::ZeroThrustDirection => write!(f, "thrust direction has no length"),
+            Self::InvalidMomentOfInertia => write!(f, "moments of inertia must be positive"),
+            Self::InvalidTorque => write!(f, "torques can not be negative"),
+        }
+    }
+}
+
+impl std::error::Error for VesselError {}
+
+/// Mass and engine of a free object. While the throttle is open the engine pushes the vessel
+/// along the thrust direction and burns propellant, following the rocket equation.
+/// Burns only happen in free flight, `AutoRails` takes burning vessels off rails.
+/// https://en.wikipedia.org/wiki/Tsiolkovsky_rocket_equation
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq)]
+#[require(AutoRails)]
+pub struct Vessel {
+    /// Mass without propellant in kg
+    dry_mass: f64,
+    /// Remaining propellant in kg
+    propellant_mass: f64,
+    /// Force of the engine at full throttle in N
+    thrust: f64,
+    /// Specific impulse of the engine in s
+    specific_impulse: f64,
+    /// Fraction of the thrust in use, from 0 to 1
+    throttle: f64,
+    /// Unit vector in world axes
+    #[reflect(ignore)]
+    thrust_direction: Vector3<f64>,
+}
+
+impl Vessel {
+    /// Vessel with the throttle closed, pointing along the world's X axis
+    pub fn new(
+        dry_mass: f64,
+        propellant_mass: f64,
+        thrust: f64,
+        specific_impulse: f64,
+    ) -> Result<Self, VesselError> {
+        if !(dry_mass.is_finite() && dry_mass > 0.0) {
+            return Err(VesselError::InvalidDryMass(dry_mass));
+        }
+        if !(propellant_mass.is_finite() && propellant_mass >= 0.0) {
+            return Err(VesselError::InvalidPropellantMass(propellant_mass));
+        }
+        if !(thrust.is_finite() && thrust >= 0.0) {
+            return Err(VesselError::InvalidThrust(thrust));
+        }
+        if !(specific_impulse.is_finite() && specific_impulse > 0.0) {
+            return Err(VesselError::InvalidSpecificImpulse(specific_impulse));
+        }
+
+        Ok(Self {
+            dry_mass,
+            propellant_mass,
+            thrust,
+            specific_impulse,
+            throttle: 0.0,
+            thrust_direction: Vector3::x(),
+        })
+    }
+
+    pub fn dry_mass(&self) -> f64 {
+        self.dry_mass
+    }
+
+    pub fn propellant_mass(&self) -> f64 {
+        self.propellant_mass
+    }
+
+    /// Current total mass in kg
+    pub fn mass(&self) -> f64 {
+        self.dry_mass + self.propellant_mass
+    }
+
+    pub fn thrust(&self) -> f64 {
+        self.thrust
+    }
+
+    pub fn specific_impulse(&self) -> f64 {
+        self.specific_impulse
+    }
+
+    pub fn throttle(&self) -> f64 {
+        self.throttle
+    }
+
+    pub fn set_throttle(&mut self, throttle: f64) -> Result<(), VesselError> {
+        if !(0.0..=1.0).contains(&throttle) {
+            return Err(VesselError::InvalidThrottle(throttle));
+        }
+        self.throttle = throttle;
+        Ok(())
+    }
+
+    pub fn thrust_direction(&self) -> Vector3<f64> {
+        self.thrust_direction
+    }
+
+    /// Points the engine, the direction is normalized
+    pub fn set_thrust_direction(&mut self, direction: Vector3<f64>) -> Result<(), VesselError> {
+        let length = direction.magnitude();
+        if !(length.is_finite() && length > 0.0) {
+            return Err(VesselError::ZeroThrustDirection);
+        }
+        self.thrust_direction = direction / length;
+        Ok(())
+    }
+
+    /// https://en.wikipedia.org/wiki/Specific_impulse
+    pub fn exhaust_velocity(&self) -> f64 {
+        self.specific_impulse * STANDARD_GRAVITY
+    }
+
+    /// Propellant burnt per second at the current throttle, in kg/s
+    pub fn mass_flow(&self) -> f64 {
+        self.throttle * self.thrust / self.exhaust_velocity()
+    }
+
+    /// Change of velocity left if all the propellant is burnt
+    pub fn delta_v(&self) -> f64 {
+        self.exhaust_velocity() * (self.mass() / self.dry_mass).ln()
+    }
+
+    /// Seconds until the propellant runs out at the current throttle
+    pub fn burn_time(&self) -> f64 {
+        let mass_flow = self.mass_flow();
+        if mass_flow > 0.0 {
+            self.propellant_mass / mass_flow
+        } else {
+            f64::INFINITY
+        }
+    }
+
+    pub fn is_burning(&self) -> bool {
+        self.throttle > 0.0 && self.thrust > 0.0 && self.propellant_mass > 0.0
+    }
+
+    /// Mean acceleration of the engine over the next `seconds`, so the change of velocity matches
+    /// the rocket equation even if the propellant runs out in between
+    pub fn thrust_acceleration(&self, seconds: f64) -> Vector3<f64> {
+        if !self.is_burning() || seconds <= 0.0 {
+            return Vector3::zeros();
+        }
+        let burnt = self.mass_flow() * seconds.min(self.burn_time());
+        let delta_v = self.exhaust_velocity() * (self.mass() / (self.mass() - burnt)).ln();
+        self.thrust_direction * delta_v / seconds
+    }
+
+    /// Burns propellant for the given seconds at the current throttle, or until it runs out
+    pub fn burn(&mut self, seconds: f64) {
+        if !self.is_burning() {
+            return;
+        }
+        let burnt = self.mass_flow() * seconds.min(self.burn_time());
+        self.propellant_mass = (self.propellant_mass - burnt).max(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::{Body, Orbit, OrbitPlugin, OrbitsAround, TimeSpeed};
+
+    #[test]
+    fn rocket_equation() {
+        let mut vessel = Vessel::new(1000.0, 1000.0, 9806.65, 100.0).unwrap();
+        assert!((vessel.delta_v() - 980.665 * 2.0_f64.ln()).abs() < 1e-9);
+        assert_eq!(vessel.burn_time(), f64::INFINITY);
+
+        vessel.set_throttle(0.5).unwrap();
+        // 5 kg/s
+        assert!((vessel.burn_time() - 200.0).abs() < 1e-9);
+        vessel.burn(100.0);
+        assert!((vessel.propellant_mass() - 500.0).abs() < 1e-9);
+        vessel.burn(1000.0);
+        assert_eq!(vessel.propellant_mass(), 0.0);
+        assert_eq!(vessel.delta_v(), 0.0);
+        assert_eq!(vessel.thrust_acceleration(1.0), Vector3::zeros());
+
+        assert_eq!(
+            vessel.set_throttle(1.5),
+            Err(VesselError::InvalidThrottle(1.5))
+        );
+        assert_eq!(
+            Vessel::new(0.0, 1.0, 1.0, 1.0),
+            Err(VesselError::InvalidDryMass(0.0))
+        );
+    }
+
+    #[test]
+    fn burn_in_free_flight() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, OrbitPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .insert_resource(TimeSpeed(10.0));
+        // Gravity of the parent is negligible
+        let body = Body::new(1.0);
+        let parent = app.world_mut().spawn(body).id();
+        let orbit =
+            Orbit::new_free(Vector3::new(1.0e9, 0.0, 0.0), Vector3::zeros(), &body).unwrap();
+        let mut vessel = Vessel::new(1000.0, 1000.0, 9806.65, 300.0).unwrap();
+        vessel
+            .set_thrust_direction(Vector3::new(0.0, 2.0, 0.0))
+            .unwrap();
+        vessel.set_throttle(1.0).unwrap();
+        let expected_delta_v = vessel.delta_v();
+        // Runs out of propellant after 300 s
+        assert!((vessel.burn_time() - 300.0).abs() < 1e-9);
+        let ship = app
+            .world_mut()
+            .spawn((orbit, vessel, OrbitsAround(parent)))
+            .id();
+
+        for _ in 0..400 {
+            app.update();
+        }
+
+        let vessel = app.world().get::<Vessel>(ship).unwrap();
+        assert_eq!(vessel.propellant_mass(), 0.0);
+        assert_eq!(vessel.delta_v(), 0.0);
+        let velocity = app
+            .world()
+            .get::<Orbit>(ship)
+            .unwrap()
+            .state_vectors()
+            .velocity;
+        assert!(velocity.x.abs() < 1e-6 && velocity.z.abs() < 1e-6);
+        let error = (velocity.y - expected_delta_v) / expected_delta_v;
+        assert!(error.abs() < 1e-9, "{} {expected_delta_v}", velocity.y);
+    }
+
+    #[test]
+    fn no_burn_without_parent() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, OrbitPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .insert_resource(TimeSpeed(10.0));
+        let body = Body::new(1.0);
+        // Not a body, so the thrust can not be integrated relative to it
+        let parent = app.world_mut().spawn_empty().id();
+        let orbit =
+            Orbit::new_free(Vector3::new(1.0e9, 0.0, 0.0), Vector3::zeros(), &body).unwrap();
+        let mut vessel = Vessel::new(1000.0, 1000.0, 9806.65, 300.0).unwrap();
+        vessel.set_throttle(1.0).unwrap();
+        let ship = app
+            .world_mut()
+            .spawn((orbit, vessel, OrbitsAround(parent)))
+            .id();
+
+        for _ in 0..10 {
+            app.update();
+        }
+
+        let vessel = app.world().get::<Vessel>(ship).unwrap();
+        assert_eq!(vessel.propellant_mass(), 1000.0);
+        assert_eq!(vessel.throttle(), 1.0);
+    }
+}
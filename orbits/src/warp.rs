@@ -0,0 +1,410 @@
+use bevy::prelude::*;
+
+use crate::{
+    Body, Frame, ManeuverNode, Orbit, OrbitsAround, Satellites, SimulationClock, TimeSpeed,
+};
+
+/// Limits applied to `TimeSpeed` before every frame is simulated.
+/// Warp is never reduced below real time.
+#[derive(Resource, Reflect, Debug, Clone, Copy, PartialEq)]
+#[reflect(Resource)]
+pub struct WarpPolicy {
+    /// Real seconds a frame is expected to last, used to turn simulated seconds into warp
+    pub frame_duration: f64,
+    /// Largest fraction of its distance to the parent a free object may travel in a single frame
+    pub max_free_step: f64,
+    /// Minimum amount of frames left before reaching an upcoming event
+    pub event_frames: f64,
+    /// Free objects closer than this to the surface of their parent are considered low
+    pub low_altitude: f64,
+    /// Highest warp allowed at low altitude
+    pub low_altitude_warp: f64,
+}
+
+impl Default for WarpPolicy {
+    fn default() -> Self {
+        Self {
+            frame_duration: 1.0 / 60.0,
+            max_free_step: 0.01,
+            event_frames: 10.0,
+            low_altitude: 100.0e3,
+            low_altitude_warp: 4.0,
+        }
+    }
+}
+
+/// Why the time warp had to be reduced
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WarpLimitReason {
+    /// A free object moves too fast for the integrator to stay accurate
+    FastObject { entity: Entity, speed: f64 },
+    /// A maneuver node is coming up in the given seconds
+    ManeuverNode { entity: Entity, seconds: f64 },
+    /// A free object may enter or leave the sphere of influence of `body` in the given seconds
+    SphereOfInfluence {
+        entity: Entity,
+        body: Entity,
+        seconds: f64,
+    },
+    /// A free object is flying close to the surface of its parent
+    LowAltitude { entity: Entity, altitude: f64 },
+}
+
+/// Emitted whenever the policy lowers `TimeSpeed`
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct TimeWarpReduced {
+    pub from: f64,
+    pub to: f64,
+    pub reason: WarpLimitReason,
+}
+
+impl std::fmt::Display for WarpLimitReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FastObject { entity, speed } => {
+                write!(f, "{entity} is moving fast ({speed:.0} m/s)")
+            }
+            Self::ManeuverNode { entity, seconds } => {
+                write!(f, "maneuver node of {entity} in {seconds:.0} s")
+            }
+            Self::SphereOfInfluence {
+                entity,
+                body,
+                seconds,
+            } => write!(
+                f,
+                "{entity} may cross the sphere of influence of {body} in {seconds:.0} s"
+            ),
+            Self::LowAltitude { entity, altitude } => {
+                write!(f, "{entity} is at low altitude ({altitude:.0} m)")
+            }
+        }
+    }
+}
+
+/// Lowest warp allowed and the reason for it
+struct WarpLimit {
+    warp: f64,
+    reason: Option<WarpLimitReason>,
+}
+
+impl WarpLimit {
+    fn apply(&mut self, warp: f64, reason: WarpLimitReason) {
+        if warp < self.warp {
+            self.warp = warp;
+            self.reason = Some(reason);
+        }
+    }
+}
+
+impl WarpPolicy {
+    /// Warp at which `seconds` of simulated time pass in the given amount of frames
+    fn warp_for(&self, seconds: f64, frames: f64) -> f64 {
+        seconds / (frames * self.frame_duration)
+    }
+}
+
+/// Lowers `TimeSpeed` when something that needs attention is close in time or space.
+/// Runs before the delta time is computed, so the reduced warp is applied on the same frame.
+/// While warping to an epoch it also sets the speed, without going over the limit.
+pub fn limit_time_warp(
+    mut time_speed: ResMut<TimeSpeed>,
+    policy: Res<WarpPolicy>,
+    clock: Res<SimulationClock>,
+    objects: Query<(Entity, &Orbit, &OrbitsAround)>,
+    bodies: Query<(&Body, Option<&Orbit>, Option<&Satellites>)>,
+    satellites: Query<(&Body, &Orbit)>,
+    nodes: Query<(Entity, &ManeuverNode)>,
+    mut events: EventWriter<TimeWarpReduced>,
+) {
+    let mut limit = WarpLimit {
+        warp: f64::INFINITY,
+        reason: None,
+    };
+
+    for (entity, node) in nodes.iter() {
+        let seconds = node.epoch - clock.seconds();
+        if seconds > 0.0 {
+            limit.apply(
+                policy.warp_for(seconds, policy.event_frames),
+                WarpLimitReason::ManeuverNode { entity, seconds },
+            );
+        }
+    }
+
+    for (entity, orbit, orbits_around) in objects.iter() {
+        let Frame::Free(state_vectors) = orbit.frame() else {
+            continue;
+        };
+        let distance = state_vectors.position.magnitude();
+        let speed = state_vectors.velocity.magnitude();
+        if speed > 0.0 {
+            limit.apply(
+                policy.warp_for(policy.max_free_step * distance / speed, 1.0),
+                WarpLimitReason::FastObject { entity, speed },
+            );
+        }
+
+        let Ok((parent_body, parent_orbit, siblings)) = bodies.get(orbits_around.0) else {
+            continue;
+        };
+
+        let altitude = distance - parent_body.radius();
+        if altitude < policy.low_altitude {
+            limit.apply(
+                policy.low_altitude_warp,
+                WarpLimitReason::LowAltitude { entity, altitude },
+            );
+        }
+
+        // Leaving the parent's sphere of influence
+        let parent_sphere_of_influence =
+            parent_orbit.and_then(|orbit| orbit.sphere_of_influence(parent_body).ok());
+        if let Some(sphere_of_influence) = parent_sphere_of_influence.filter(|_| speed > 0.0) {
+            let seconds = (sphere_of_influence - distance).max(0.0) / speed;
+            limit.apply(
+                policy.warp_for(seconds, policy.event_frames),
+                WarpLimitReason::SphereOfInfluence {
+                    entity,
+                    body: orbits_around.0,
+                    seconds,
+                },
+            );
+        }
+
+        // Entering the sphere of influence of a body orbiting the same parent
+        for sibling in siblings.into_iter().flat_map(Satellites::iter) {
+            let Ok((sibling_body, sibling_orbit)) = satellites.get(sibling) else {
+                continue;
+            };
+            if sibling == entity {
+                continue;
+            }
+            let Ok(sphere_of_influence) = sibling_orbit.sphere_of_influence(sibling_body) else {
+                continue;
+            };
+            let sibling_state = sibling_orbit.state_vectors();
+            let separation = state_vectors.position - sibling_state.position;
+            // Only the part of the relative velocity towards the sibling brings it closer
+            let closing_speed =
+                -(state_vectors.velocity - sibling_state.velocity).dot(&separation.normalize());
+            if closing_speed <= 0.0 {
+                continue;
+            }
+            let gap = separation.magnitude() - sphere_of_influence;
+            let seconds = gap.max(0.0) / closing_speed;
+            limit.apply(
+                policy.warp_for(seconds, policy.event_frames),
+                WarpLimitReason::SphereOfInfluence {
+                    entity,
+                    body: sibling,
+                    seconds,
+                },
+            );
+        }
+    }
+
+    let warp = limit.warp.max(1.0);
+    if let Some(target) = clock.warp_target() {
+        // Warping to an epoch picks its own speed, slowing down smoothly on the approach
+        let remaining = target - clock.seconds();
+        time_speed.0 = policy
+            .warp_for(remaining, policy.event_frames)
+            .clamp(1.0, warp);
+        return;
+    }
+
+    let Some(reason) = limit.reason else {
+        return;
+    };
+    if time_speed.0 > warp {
+        events.write(TimeWarpReduced {
+            from: time_speed.0,
+            to: warp,
+            reason,
+        });
+        time_speed.0 = warp;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OrbitPlugin;
+    use nalgebra::Vector3;
+
+    fn app_with_earth() -> (App, Entity, Body) {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, OrbitPlugin));
+        let earth_body = Body::new(5.97219e24).with_radius(6.371e6);
+        let earth = app.world_mut().spawn(earth_body).id();
+        (app, earth, earth_body)
+    }
+
+    fn reductions(app: &App) -> Vec<TimeWarpReduced> {
+        let events = app.world().resource::<Events<TimeWarpReduced>>();
+        events.get_cursor().read(events).copied().collect()
+    }
+
+    #[test]
+    fn low_altitude_drops_warp() {
+        let (mut app, earth, earth_body) = app_with_earth();
+        let ship = Orbit::new_free(
+            Vector3::new(6.371e6 + 50.0e3, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, 7.8e3),
+            &earth_body,
+        )
+        .unwrap();
+        let ship = app.world_mut().spawn((ship, OrbitsAround(earth))).id();
+        app.insert_resource(TimeSpeed(1.0e6));
+
+        app.update();
+
+        assert_eq!(app.world().resource::<TimeSpeed>().0, 4.0);
+        let reductions = reductions(&app);
+        assert_eq!(reductions.len(), 1);
+        assert_eq!(reductions[0].from, 1.0e6);
+        assert!(matches!(
+            reductions[0].reason,
+            WarpLimitReason::LowAltitude { entity, .. } if entity == ship
+        ));
+    }
+
+    #[test]
+    fn fast_objects_limit_warp() {
+        let (mut app, earth, earth_body) = app_with_earth();
+        let ship = Orbit::new_free(
+            Vector3::new(1.0e7, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, 6.0e3),
+            &earth_body,
+        )
+        .unwrap();
+        app.world_mut().spawn((ship, OrbitsAround(earth)));
+        app.insert_resource(TimeSpeed(1.0e6));
+
+        app.update();
+
+        // 1% of 10000 km at 6 km/s is 16.7 s per frame
+        let expected = 0.01 * 1.0e7 / 6.0e3 * 60.0;
+        assert!((app.world().resource::<TimeSpeed>().0 - expected).abs() < 1e-6);
+        // Already under the limit, nothing else is reported
+        app.update();
+        assert_eq!(reductions(&app).len(), 1);
+    }
+
+    #[test]
+    fn maneuver_nodes_limit_warp() {
+        let (mut app, earth, earth_body) = app_with_earth();
+        let satellite = Orbit::new_orbit(
+            4.2e7,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            &earth_body,
+            &SimulationClock::default(),
+            0.0,
+        )
+        .unwrap();
+        app.world_mut().spawn((
+            satellite,
+            OrbitsAround(earth),
+            ManeuverNode {
+                epoch: 600.0,
+                delta_v: Vector3::new(0.0, 10.0, 0.0),
+            },
+        ));
+        app.insert_resource(TimeSpeed(1.0e6));
+
+        app.update();
+
+        // Arrives in no less than 10 frames
+        assert!((app.world().resource::<TimeSpeed>().0 - 600.0 * 6.0).abs() < 1e-6);
+        assert!(matches!(
+            reductions(&app)[0].reason,
+            WarpLimitReason::ManeuverNode { .. }
+        ));
+    }
+
+    #[test]
+    fn approaching_moon_limits_warp() {
+        let (mut app, earth, earth_body) = app_with_earth();
+        let moon_body = Body::new(7.342e22).with_radius(1.737e6);
+        let moon_orbit = Orbit::new_orbit(
+            3.844e8,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            &earth_body,
+            &SimulationClock::default(),
+            0.0,
+        )
+        .unwrap();
+        let moon_position = moon_orbit.position();
+        let moon = app
+            .world_mut()
+            .spawn((moon_body, moon_orbit, OrbitsAround(earth)))
+            .id();
+
+        // Slow object 1000 km outside of the Moon's sphere of influence, heading to it
+        let sphere_of_influence = 3.844e8 * (7.342e22_f64 / 5.97219e24).powf(0.4);
+        let direction = moon_position.normalize();
+        let ship = Orbit::new_free(
+            moon_position - direction * (sphere_of_influence + 1.0e6),
+            direction * 100.0,
+            &earth_body,
+        )
+        .unwrap();
+        app.world_mut().spawn((ship, OrbitsAround(earth)));
+        app.insert_resource(TimeSpeed(1.0e6));
+
+        app.update();
+
+        let reductions = reductions(&app);
+        assert!(matches!(
+            reductions[0].reason,
+            WarpLimitReason::SphereOfInfluence { body, .. } if body == moon
+        ));
+        // 1000 km at a closing speed of 100 m/s in 10 frames
+        let expected = 1.0e6 / 100.0 / 10.0 * 60.0;
+        assert!((app.world().resource::<TimeSpeed>().0 / expected - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn receding_from_moon_keeps_warp() {
+        let (mut app, earth, earth_body) = app_with_earth();
+        let moon_body = Body::new(7.342e22).with_radius(1.737e6);
+        let moon_orbit = Orbit::new_orbit(
+            3.844e8,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            &earth_body,
+            &SimulationClock::default(),
+            0.0,
+        )
+        .unwrap();
+        let moon_position = moon_orbit.position();
+        app.world_mut()
+            .spawn((moon_body, moon_orbit, OrbitsAround(earth)));
+
+        // Same place as when approaching, but flying away from the Moon
+        let sphere_of_influence = 3.844e8 * (7.342e22_f64 / 5.97219e24).powf(0.4);
+        let direction = moon_position.normalize();
+        let ship = Orbit::new_free(
+            moon_position - direction * (sphere_of_influence + 1.0e6),
+            -direction * 100.0,
+            &earth_body,
+        )
+        .unwrap();
+        app.world_mut().spawn((ship, OrbitsAround(earth)));
+        app.insert_resource(TimeSpeed(1.0e6));
+
+        app.update();
+
+        assert!(reductions(&app).is_empty());
+        assert_eq!(app.world().resource::<TimeSpeed>().0, 1.0e6);
+    }
+}
@@ -0,0 +1,399 @@
+use bevy::prelude::*;
+
+use crate::{
+    Body, Frame, ManeuverNode, Orbit, OrbitError, OrbitsAround, Satellites, SimulationClock,
+};
+
+/// Samples taken along the trajectory when looking for the next sphere of influence change
+const SPHERE_OF_INFLUENCE_SAMPLES: u32 = 2000;
+/// Bisection steps used to refine a sphere of influence change once it has been bracketed
+const SPHERE_OF_INFLUENCE_BISECTIONS: u32 = 40;
+/// How far ahead open trajectories are searched for a sphere of influence change
+const OPEN_TRAJECTORY_HORIZON: f64 = 365.25 * 86_400.0;
+/// Largest fraction of its distance to the parent a free object moves in a prediction substep
+const PREDICTION_MAX_STEP: f64 = 0.001;
+
+/// Moments the time warp can run to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WarpEvent {
+    /// Next periapsis of the entity's orbit
+    Periapsis(Entity),
+    /// Next apoapsis of the entity's orbit
+    Apoapsis(Entity),
+    /// Next time the entity's orbit crosses the parent's reference plane going north
+    AscendingNode(Entity),
+    /// Next time the entity enters or leaves a sphere of influence
+    SphereOfInfluence(Entity),
+    /// The `ManeuverNode` of the entity
+    Maneuver(Entity),
+    /// A Julian date
+    Date(f64),
+}
+
+/// Request to run time warp until an event. The epoch is computed once, when it is handled.
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct WarpTo(pub WarpEvent);
+
+/// Reasons why a warp to an event could not be scheduled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WarpToError {
+    /// The entity has no `Orbit` or is not orbiting anything
+    NotOrbiting(Entity),
+    /// The entity has no `ManeuverNode`
+    NoManeuverNode(Entity),
+    /// Nothing changes sphere of influence within the searched time
+    NoSphereOfInfluenceChange(Entity),
+    /// The requested epoch is not in the future
+    InThePast(f64),
+    Orbit(OrbitError),
+}
+
+impl std::fmt::Display for WarpToError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotOrbiting(entity) => write!(f, "{entity} is not orbiting anything"),
+            Self::NoManeuverNode(entity) => write!(f, "{entity} has no maneuver node"),
+            Self::NoSphereOfInfluenceChange(entity) => {
+                write!(f, "{entity} does not change sphere of influence soon")
+            }
+            Self::InThePast(epoch) => write!(f, "epoch {epoch} is in the past"),
+            Self::Orbit(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for WarpToError {}
+
+impl From<OrbitError> for WarpToError {
+    fn from(error: OrbitError) -> Self {
+        Self::Orbit(error)
+    }
+}
+
+/// Sphere of influence an object is in, relative to its current parent
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Region {
+    Parent,
+    /// Index in the list of siblings
+    Sibling(usize),
+    Outside,
+}
+
+/// Seconds until `orbit` enters the sphere of influence of one of its siblings or leaves the one
+/// of its parent. The trajectory is sampled over an orbital period, or a year for open
+/// trajectories, and the crossing refined by bisection.
+pub fn time_to_sphere_of_influence_change(
+    orbit: &Orbit,
+    parent_sphere_of_influence: Option<f64>,
+    siblings: &[(Orbit, f64)],
+) -> Option<f64> {
+    let horizon = orbit.period().unwrap_or(OPEN_TRAJECTORY_HORIZON);
+    let sample_step = horizon / SPHERE_OF_INFLUENCE_SAMPLES as f64;
+
+    let region = |orbit: &Orbit, siblings: &[(Orbit, f64)]| {
+        let position = orbit.position();
+        if let Some(index) = siblings.iter().position(|(sibling, sphere_of_influence)| {
+            (position - sibling.position()).magnitude() < *sphere_of_influence
+        }) {
+            Region::Sibling(index)
+        } else if parent_sphere_of_influence.is_some_and(|radius| position.magnitude() > radius) {
+            Region::Outside
+        } else {
+            Region::Parent
+        }
+    };
+
+    let mut orbit = orbit.clone();
+    let mut siblings = siblings.to_vec();
+    let starting_region = region(&orbit, &siblings);
+    for sample in 0..SPHERE_OF_INFLUENCE_SAMPLES {
+        let mut next_orbit = orbit.clone();
+        let mut next_siblings = siblings.clone();
+        advance(&mut next_orbit, &mut next_siblings, sample_step);
+        if region(&next_orbit, &next_siblings) == starting_region {
+            orbit = next_orbit;
+            siblings = next_siblings;
+            continue;
+        }
+
+        // The crossing is inside this sample, halve the interval until it is precise enough
+        let mut low = 0.0;
+        let mut high = sample_step;
+        for _ in 0..SPHERE_OF_INFLUENCE_BISECTIONS {
+            let middle = 0.5 * (low + high);
+            let mut middle_orbit = orbit.clone();
+            let mut middle_siblings = siblings.clone();
+            advance(&mut middle_orbit, &mut middle_siblings, middle);
+            if region(&middle_orbit, &middle_siblings) == starting_region {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        return Some(sample as f64 * sample_step + high);
+    }
+
+    None
+}
+
+/// Steps a predicted trajectory, free objects are split in small steps to keep them accurate
+fn advance(orbit: &mut Orbit, siblings: &mut [(Orbit, f64)], seconds: f64) {
+    for (sibling, _) in siblings.iter_mut() {
+        if let Err(error) = sibling.step(seconds) {
+            warn!("Could not predict orbit: {error}");
+        }
+    }
+
+    let mut remaining = seconds;
+    while remaining > 0.0 {
+        let step = match orbit.frame() {
+            Frame::Free(state_vectors) => (PREDICTION_MAX_STEP
+                * state_vectors.position.magnitude()
+                / state_vectors.velocity.magnitude())
+            .min(remaining),
+            Frame::Orbit { .. } => remaining,
+        };
+        if let Err(error) = orbit.step(step) {
+            warn!("Could not predict orbit: {error}");
+            return;
+        }
+        remaining -= step;
+    }
+}
+
+/// Epoch, in seconds of the clock, when the event happens
+fn warp_event_epoch(
+    event: WarpEvent,
+    clock: &SimulationClock,
+    orbits: &Query<(&Orbit, Option<&OrbitsAround>, Option<&ManeuverNode>)>,
+    bodies: &Query<(&Body, Option<&Orbit>, Option<&Satellites>)>,
+) -> Result<f64, WarpToError> {
+    let orbit = |entity| {
+        orbits
+            .get(entity)
+            .map_err(|_| WarpToError::NotOrbiting(entity))
+    };
+
+    let epoch = match event {
+        WarpEvent::Periapsis(entity) => clock.seconds() + orbit(entity)?.0.time_to_periapsis()?,
+        WarpEvent::Apoapsis(entity) => clock.seconds() + orbit(entity)?.0.time_to_apoapsis()?,
+        WarpEvent::AscendingNode(entity) => {
+            clock.seconds() + orbit(entity)?.0.time_to_ascending_node()?
+        }
+        WarpEvent::Maneuver(entity) => {
+            orbit(entity)?
+                .2
+                .ok_or(WarpToError::NoManeuverNode(entity))?
+                .epoch
+        }
+        WarpEvent::SphereOfInfluence(entity) => {
+            let (orbit, orbits_around, _) = orbit(entity)?;
+            let parent = orbits_around.ok_or(WarpToError::NotOrbiting(entity))?.0;
+            let (parent_body, parent_orbit, satellites) = bodies
+                .get(parent)
+                .map_err(|_| WarpToError::NotOrbiting(entity))?;
+            let parent_sphere_of_influence =
+                parent_orbit.and_then(|orbit| orbit.sphere_of_influence(parent_body).ok());
+            let siblings: Vec<(Orbit, f64)> = satellites
+                .into_iter()
+                .flat_map(Satellites::iter)
+                .filter(|sibling| *sibling != entity)
+                .filter_map(|sibling| {
+                    let (body, orbit, _) = bodies.get(sibling).ok()?;
+                    let orbit = orbit?;
+                    Some((orbit.clone(), orbit.sphere_of_influence(body).ok()?))
+                })
+                .collect();
+
+            clock.seconds()
+                + time_to_sphere_of_influence_change(orbit, parent_sphere_of_influence, &siblings)
+                    .ok_or(WarpToError::NoSphereOfInfluenceChange(entity))?
+        }
+        WarpEvent::Date(julian_date) => clock.seconds_at(julian_date),
+    };
+
+    if epoch <= clock.seconds() {
+        return Err(WarpToError::InThePast(epoch));
+    }
+    Ok(epoch)
+}
+
+/// Starts the requested warps, the clock lands exactly on the computed epoch
+pub fn schedule_warp_to(
+    mut requests: EventReader<WarpTo>,
+    mut clock: ResMut<SimulationClock>,
+    orbits: Query<(&Orbit, Option<&OrbitsAround>, Option<&ManeuverNode>)>,
+    bodies: Query<(&Body, Option<&Orbit>, Option<&Satellites>)>,
+) {
+    for WarpTo(event) in requests.read() {
+        match warp_event_epoch(*event, &clock, &orbits, &bodies) {
+            Ok(epoch) => clock.warp_to(epoch),
+            Err(error) => warn!("Could not warp to {event:?}: {error}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::{OrbitPlugin, TimeSpeed, equinoctial::world_from_reference};
+    use bevy::time::TimeUpdateStrategy;
+    use nalgebra::Vector3;
+
+    /// Frames last exactly what the warp policy expects
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, OrbitPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(
+                std::time::Duration::from_secs_f64(1.0 / 60.0),
+            ));
+        app
+    }
+
+    fn run_until_target(app: &mut App) {
+        // The first update schedules the warp
+        app.update();
+        let mut frames = 1;
+        while app
+            .world()
+            .resource::<SimulationClock>()
+            .warp_target()
+            .is_some()
+        {
+            app.update();
+            frames += 1;
+            assert!(frames < 100_000, "Warp never finished");
+        }
+    }
+
+    #[test]
+    fn warp_lands_on_periapsis() {
+        let mut app = app();
+        let earth_body = Body::new(5.97219e24).with_radius(6.371e6);
+        let earth = app.world_mut().spawn(earth_body).id();
+        let clock = SimulationClock::default();
+        // Half an hour after the periapsis
+        let orbit =
+            Orbit::new_orbit(2.0e7, 0.3, 1.0, 0.4, 0.5, &earth_body, &clock, -1800.0).unwrap();
+        let period = orbit.period().unwrap();
+        let satellite = app.world_mut().spawn((orbit, OrbitsAround(earth))).id();
+
+        app.world_mut()
+            .send_event(WarpTo(WarpEvent::Periapsis(satellite)));
+        run_until_target(&mut app);
+
+        let clock = app.world().resource::<SimulationClock>();
+        assert!((clock.seconds() - (period - 1800.0)).abs() < 1e-6);
+        let orbit = app.world().get::<Orbit>(satellite).unwrap();
+        let elements = orbit.classical_elements().unwrap();
+        let true_anomaly = elements.true_anomaly.min(2.0 * PI - elements.true_anomaly);
+        assert!(true_anomaly < 1e-9, "{true_anomaly}");
+        // Back to real time once there
+        assert_eq!(app.world().resource::<TimeSpeed>().0, 1.0);
+    }
+
+    #[test]
+    fn warp_lands_on_date_and_ascending_node() {
+        let mut app = app();
+        let sun_body = Body::new(1.989e30);
+        let sun = app.world_mut().spawn(sun_body).id();
+        let orbit = Orbit::new_orbit(
+            1.5e11,
+            0.1,
+            0.3,
+            0.2,
+            1.0,
+            &sun_body,
+            &SimulationClock::default(),
+            0.0,
+        )
+        .unwrap();
+        let planet = app.world_mut().spawn((orbit, OrbitsAround(sun))).id();
+
+        let julian_date = crate::julian_date_from_utc(2000, 3, 1, 0, 0, 0.0);
+        app.world_mut()
+            .send_event(WarpTo(WarpEvent::Date(julian_date)));
+        run_until_target(&mut app);
+        let clock = app.world().resource::<SimulationClock>();
+        assert_eq!(clock.utc(), "2000-03-01 00:00:00 UTC");
+
+        app.world_mut()
+            .send_event(WarpTo(WarpEvent::AscendingNode(planet)));
+        run_until_target(&mut app);
+        let state_vectors = app.world().get::<Orbit>(planet).unwrap().state_vectors();
+        let position = world_from_reference().inverse() * state_vectors.position;
+        let velocity = world_from_reference().inverse() * state_vectors.velocity;
+        assert!(position.z.abs() < 1e-6 * position.magnitude());
+        // Crossing the plane going north
+        assert!(velocity.z > 0.0);
+    }
+
+    #[test]
+    fn finds_sphere_of_influence_change() {
+        let earth_body = Body::new(5.97219e24);
+        let clock = SimulationClock::default();
+        let moon_body = Body::new(7.342e22);
+        let moon_orbit =
+            Orbit::new_orbit(3.844e8, 0.0, 0.0, 0.0, 0.0, &earth_body, &clock, 0.0).unwrap();
+        let moon_sphere_of_influence = moon_orbit.sphere_of_influence(&moon_body).unwrap();
+
+        // Transfer orbit whose apoapsis reaches the Moon's distance at the right time
+        let transfer_semimajor_axis: f64 = (6.6e6 + 3.844e8) / 2.0;
+        let transfer_period =
+            2.0 * PI * (transfer_semimajor_axis.powi(3) / (5.97219e24 * 6.67430e-11)).sqrt();
+        let mut moon_at_start = moon_orbit.clone();
+        moon_at_start.step(-transfer_period / 2.0).unwrap();
+        let ship = Orbit::new_orbit(
+            transfer_semimajor_axis,
+            1.0 - 6.6e6 / transfer_semimajor_axis,
+            PI,
+            0.0,
+            0.0,
+            &earth_body,
+            &clock,
+            0.0,
+        )
+        .unwrap();
+
+        let seconds = time_to_sphere_of_influence_change(
+            &ship,
+            None,
+            &[(moon_at_start, moon_sphere_of_influence)],
+        )
+        .unwrap();
+
+        assert!(seconds > 0.0 && seconds < transfer_period / 2.0);
+        let mut ship_at_crossing = ship.clone();
+        ship_at_crossing.step(seconds).unwrap();
+        let mut moon_at_crossing = moon_orbit.clone();
+        moon_at_crossing
+            .step(seconds - transfer_period / 2.0)
+            .unwrap();
+        let distance = (ship_at_crossing.position() - moon_at_crossing.position()).magnitude();
+        assert!((distance / moon_sphere_of_influence - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn rejects_missing_maneuver_node() {
+        let mut app = app();
+        let earth_body = Body::new(5.97219e24);
+        let earth = app.world_mut().spawn(earth_body).id();
+        let ship = Orbit::new_free(
+            Vector3::new(7.0e6, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, 7.5e3),
+            &earth_body,
+        )
+        .unwrap();
+        let ship = app.world_mut().spawn((ship, OrbitsAround(earth))).id();
+
+        app.world_mut()
+            .send_event(WarpTo(WarpEvent::Maneuver(ship)));
+        app.update();
+        assert_eq!(
+            app.world().resource::<SimulationClock>().warp_target(),
+            None
+        );
+    }
+}